@@ -0,0 +1,6 @@
+CREATE TABLE sync_cursors (
+    token TEXT NOT NULL REFERENCES tokens (token) ON DELETE CASCADE,
+    courseid BIGINT NOT NULL,
+    synced_at BIGINT NOT NULL,
+    PRIMARY KEY (token, courseid)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE tokens ADD COLUMN lease_owner TEXT;
+ALTER TABLE tokens ADD COLUMN lease_until BIGINT;
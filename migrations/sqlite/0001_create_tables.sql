@@ -0,0 +1,67 @@
+CREATE TABLE tokens (
+    token TEXT PRIMARY KEY,
+    device_token TEXT,
+    feed_token TEXT UNIQUE,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+CREATE TABLE users (
+    token TEXT PRIMARY KEY REFERENCES tokens (token) ON DELETE CASCADE,
+    userid INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    fullname TEXT NOT NULL
+);
+
+CREATE TABLE courses (
+    token TEXT NOT NULL REFERENCES tokens (token) ON DELETE CASCADE,
+    id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    fullname TEXT NOT NULL,
+    enddate INTEGER NOT NULL,
+    PRIMARY KEY (token, id)
+);
+
+CREATE INDEX courses_id_idx ON courses (id);
+
+CREATE TABLE grades (
+    token TEXT NOT NULL REFERENCES tokens (token) ON DELETE CASCADE,
+    courseid INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    coursename TEXT,
+    PRIMARY KEY (token, courseid)
+);
+
+CREATE TABLE grade_items (
+    token TEXT NOT NULL,
+    courseid INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    itemname TEXT NOT NULL,
+    percentageformatted TEXT NOT NULL,
+    PRIMARY KEY (token, courseid, position),
+    FOREIGN KEY (token, courseid) REFERENCES grades (token, courseid) ON DELETE CASCADE
+);
+
+CREATE TABLE grade_overviews (
+    token TEXT NOT NULL REFERENCES tokens (token) ON DELETE CASCADE,
+    courseid INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    course_name TEXT,
+    grade TEXT NOT NULL,
+    rawgrade TEXT,
+    PRIMARY KEY (token, courseid)
+);
+
+CREATE TABLE deadlines (
+    token TEXT NOT NULL REFERENCES tokens (token) ON DELETE CASCADE,
+    id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    timeusermidnight INTEGER NOT NULL,
+    formattedtime TEXT NOT NULL,
+    coursename TEXT,
+    courseid INTEGER,
+    PRIMARY KEY (token, id)
+);
+
+CREATE INDEX deadlines_timeusermidnight_idx ON deadlines (timeusermidnight);
@@ -0,0 +1,6 @@
+CREATE TABLE sync_cursors (
+    token TEXT NOT NULL REFERENCES tokens (token) ON DELETE CASCADE,
+    courseid INTEGER NOT NULL,
+    synced_at INTEGER NOT NULL,
+    PRIMARY KEY (token, courseid)
+);
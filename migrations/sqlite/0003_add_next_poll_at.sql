@@ -0,0 +1,3 @@
+ALTER TABLE tokens ADD COLUMN next_poll_at INTEGER;
+
+CREATE INDEX tokens_next_poll_at_idx ON tokens (next_poll_at);
@@ -1,33 +1,238 @@
-use std::{env, error::Error, fs::File, io::Write};
+use std::{env, error::Error, fs::File, io::Write, num::NonZeroUsize, str::FromStr};
 
 use base64::{engine::general_purpose, Engine};
 
+use crate::{
+    domain::entities::{lease::generate_worker_id, tenant::Tenant},
+    infrastructure::data_providers::request_limiter::{
+        parse_endpoint_limits, parse_rate, RequestLimits,
+    },
+};
+
+/// Where user data is kept, set with `STORAGE` (`mongo` by default).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageBackend {
+    Mongo,
+    Memory,
+    Postgres,
+    Sqlite,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mongo" => Ok(Self::Mongo),
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            "sqlite" => Ok(Self::Sqlite),
+            other => Err(format!("Invalid STORAGE: {}", other)),
+        }
+    }
+}
+
+/// Read cache in front of the repositories, set with `CACHE`. See [`cache`] when unset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheBackend {
+    None,
+    Memory,
+    Redis,
+}
+
+impl FromStr for CacheBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(Self::None),
+            "memory" => Ok(Self::Memory),
+            "redis" => Ok(Self::Redis),
+            other => Err(format!("Invalid CACHE: {}", other)),
+        }
+    }
+}
+
+/// Where pushes go, set with `NOTIFICATIONS` (`firebase` by default).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationBackend {
+    Firebase,
+    /// Kept in memory and shown at `/api/admin/notifications`, no Firebase credentials needed.
+    Recording,
+}
+
+impl FromStr for NotificationBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "firebase" => Ok(Self::Firebase),
+            "recording" => Ok(Self::Recording),
+            other => Err(format!("Invalid NOTIFICATIONS: {}", other)),
+        }
+    }
+}
+
 pub struct Config {
     pub port: String,
-    pub mongo_uri: String,
-    pub base_url: String,
-    pub format_url: String,
+    pub storage: StorageBackend,
+    pub mongo_uri: Option<String>,
+    pub database_url: Option<String>,
+    pub cache: CacheBackend,
+    pub redis_url: Option<String>,
+    /// At least one second, the granularity of Redis expiry.
+    pub cache_ttl_secs: u64,
+    /// Schools served by this deployment, the first one is the default.
+    pub tenants: Vec<Tenant>,
     pub batch_size: i64,
+    pub poll_interval_secs: i64,
+    pub moodle_limits: RequestLimits,
+    /// Lease owner name of this instance, set with `WORKER_ID` or generated at startup.
+    pub worker_id: String,
+    pub notifications: NotificationBackend,
+    /// Bearer token of the admin routes, required with `NOTIFICATIONS=recording`.
+    pub admin_token: Option<String>,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, Box<dyn Error>> {
-        let service_account_key =
-            env::var("SERVICE_ACCOUNT_KEY").expect("SERVICE_ACCOUNT_KEY must be set");
-        let decoded_service_key = general_purpose::STANDARD
-            .decode(&service_account_key)
-            .unwrap();
-        let mut file = File::create("service_account_key.json")?;
-        file.write_all(&decoded_service_key)?;
+        let notifications = env::var("NOTIFICATIONS")
+            .map(|notifications| notifications.parse())
+            .unwrap_or(Ok(NotificationBackend::Firebase))?;
+        let admin_token = env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        match notifications {
+            NotificationBackend::Firebase => {
+                let service_account_key =
+                    env::var("SERVICE_ACCOUNT_KEY").expect("SERVICE_ACCOUNT_KEY must be set");
+                let decoded_service_key = general_purpose::STANDARD
+                    .decode(&service_account_key)
+                    .unwrap();
+                let mut file = File::create("service_account_key.json")?;
+                file.write_all(&decoded_service_key)?;
+            }
+            NotificationBackend::Recording if admin_token.is_none() => {
+                return Err("ADMIN_TOKEN must be set for NOTIFICATIONS=recording".into());
+            }
+            NotificationBackend::Recording => {}
+        }
+
+        let redis_url = env::var("REDIS_URL").ok();
+        let instances: NonZeroUsize = env_or("INSTANCES", NonZeroUsize::MIN)?;
+        let cache = cache(instances, redis_url.as_deref())?;
+        let cache_ttl_secs: u64 = env_or("CACHE_TTL_SECS", 60)?;
+        if cache_ttl_secs == 0 {
+            return Err("Invalid CACHE_TTL_SECS: must be at least 1".into());
+        }
 
         Ok(Config {
             port: env::var("PORT")?,
-            mongo_uri: env::var("MONGODB_URI")?,
-            base_url: env::var("BASE_URL")?,
-            format_url: env::var("FORMAT_URL")?,
+            storage: env::var("STORAGE")
+                .map(|storage| storage.parse())
+                .unwrap_or(Ok(StorageBackend::Mongo))?,
+            mongo_uri: env::var("MONGODB_URI").ok(),
+            database_url: env::var("DATABASE_URL").ok(),
+            cache,
+            redis_url,
+            cache_ttl_secs,
+            tenants: tenants()?,
             batch_size: env::var("BATCH_SIZE")?
                 .parse::<i64>()
                 .map_err(|e| format!("Invalid BATCH_SIZE: {}", e))?,
+            poll_interval_secs: env::var("POLL_INTERVAL_SECS")
+                .unwrap_or_else(|_| "900".to_owned())
+                .parse::<i64>()
+                .map_err(|e| format!("Invalid POLL_INTERVAL_SECS: {}", e))?,
+            moodle_limits: moodle_limits()?,
+            worker_id: env::var("WORKER_ID").unwrap_or_else(|_| generate_worker_id()),
+            notifications,
+            admin_token,
         })
     }
+
+    pub fn mongo_uri(&self) -> Result<&str, Box<dyn Error>> {
+        Ok(self
+            .mongo_uri
+            .as_deref()
+            .ok_or("MONGODB_URI must be set for STORAGE=mongo")?)
+    }
+
+    pub fn database_url(&self) -> Result<&str, Box<dyn Error>> {
+        Ok(self
+            .database_url
+            .as_deref()
+            .ok_or("DATABASE_URL must be set for SQL storage")?)
+    }
+}
+
+/// Cache of one of `INSTANCES` (1 by default) instances, `redis` once `REDIS_URL` is given and
+/// `memory` otherwise when `CACHE` is unset. Several instances need `REDIS_URL`: an in-process
+/// cache is not invalidated by writes of the others and live updates only cross instances
+/// through Redis, so `CACHE=memory` is refused for them too.
+fn cache(instances: NonZeroUsize, redis_url: Option<&str>) -> Result<CacheBackend, Box<dyn Error>> {
+    let several = instances.get() > 1;
+    if several && redis_url.is_none() {
+        return Err("REDIS_URL must be set to run several INSTANCES".into());
+    }
+    let cache = match env::var("CACHE") {
+        Ok(cache) => cache.parse()?,
+        Err(_) if redis_url.is_some() => CacheBackend::Redis,
+        Err(_) => CacheBackend::Memory,
+    };
+    if several && cache == CacheBackend::Memory {
+        return Err("CACHE=memory can't be shared by several INSTANCES, use redis or none".into());
+    }
+    Ok(cache)
+}
+
+/// Tenants from the JSON array in `TENANTS_FILE`, or the single one of `BASE_URL`
+/// and `FORMAT_URL`.
+fn tenants() -> Result<Vec<Tenant>, Box<dyn Error>> {
+    match env::var("TENANTS_FILE") {
+        Ok(path) => {
+            let tenants: Vec<Tenant> = serde_json::from_reader(File::open(&path)?)
+                .map_err(|e| format!("Invalid TENANTS_FILE: {}", e))?;
+            if tenants.is_empty() {
+                return Err("TENANTS_FILE lists no tenants".into());
+            }
+            Ok(tenants)
+        }
+        Err(_) => Ok(vec![Tenant::single(
+            env::var("BASE_URL")?,
+            env::var("FORMAT_URL")?,
+        )]),
+    }
+}
+
+/// Limits on requests to Moodle, every variable falls back to [`RequestLimits::default`].
+fn moodle_limits() -> Result<RequestLimits, Box<dyn Error>> {
+    let defaults = RequestLimits::default();
+    Ok(RequestLimits {
+        max_concurrent: env_or("MOODLE_MAX_CONCURRENT", defaults.max_concurrent)?,
+        requests_per_sec: match env::var("MOODLE_REQUESTS_PER_SEC") {
+            Ok(value) => parse_rate(&value)
+                .ok_or("Invalid MOODLE_REQUESTS_PER_SEC: expected a positive number")?,
+            Err(_) => defaults.requests_per_sec,
+        },
+        burst: env_or("MOODLE_BURST", defaults.burst)?,
+        endpoints: match env::var("MOODLE_ENDPOINT_LIMITS") {
+            Ok(value) => parse_endpoint_limits(&value)
+                .map_err(|e| format!("Invalid MOODLE_ENDPOINT_LIMITS: {}", e))?,
+            Err(_) => defaults.endpoints,
+        },
+    })
+}
+
+fn env_or<T>(name: &str, default: T) -> Result<T, Box<dyn Error>>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => Ok(value
+            .parse::<T>()
+            .map_err(|e| format!("Invalid {}: {}", name, e))?),
+        Err(_) => Ok(default),
+    }
 }
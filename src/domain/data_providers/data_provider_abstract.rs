@@ -1,12 +1,15 @@
 use async_trait::async_trait;
 use core::fmt::Debug;
 use mockall::automock;
+use std::collections::HashMap;
 
 use crate::{
     domain::entities::{
-        course::Course,
+        course::{Course, CourseUpdates},
         deadline::Events,
+        errors::ServiceError,
         grade::{GradesOverview, UserGrades},
+        sync_cursor::SyncCursor,
         user::User,
     },
     infrastructure::data_providers::errors::ResponseError,
@@ -29,5 +32,50 @@ pub trait DataProviderAbstract: Send + Sync + Debug {
         token: &str,
         course_id: i64,
     ) -> Result<Events, ResponseError>;
+    /// Grades of several courses keyed by course id, in as few round trips as the provider
+    /// allows.
+    async fn get_grades_by_courses(
+        &self,
+        token: &str,
+        user_id: i64,
+        course_ids: &[i64],
+    ) -> Result<HashMap<i64, UserGrades>, ResponseError>;
+    /// Deadlines of several courses keyed by course id. Courses without deadlines may be
+    /// missing from the map.
+    async fn get_deadlines_by_courses(
+        &self,
+        token: &str,
+        course_ids: &[i64],
+    ) -> Result<HashMap<i64, Events>, ResponseError>;
+    /// Whether the deadline still exists. Deadlines also drop out of the fetched lists once
+    /// submitted or beyond the event limit, only a missing one was cancelled.
+    async fn deadline_exists(
+        &self,
+        token: &str,
+        course_id: i64,
+        deadline_id: i64,
+    ) -> Result<bool, ResponseError>;
     async fn get_grades_overview(&self, token: &str) -> Result<GradesOverview, ResponseError>;
+    /// What changed in each course since its cursor, keyed by course id. Courses that could
+    /// not be checked are missing from the map.
+    async fn check_course_updates(
+        &self,
+        token: &str,
+        cursors: &[SyncCursor],
+    ) -> Result<HashMap<i64, CourseUpdates>, ResponseError>;
+    /// Routes an unregistered `token` to `tenant`, or the default one, and returns the tenant
+    /// id to store it with. Providers serving a single LMS have nothing to route.
+    async fn assign_tenant(
+        &self,
+        _token: &str,
+        tenant: Option<String>,
+    ) -> Result<Option<String>, ServiceError> {
+        Ok(tenant)
+    }
+    /// Tenant id a registered `token` is routed to.
+    async fn tenant_id(&self, _token: &str) -> Result<Option<String>, ServiceError> {
+        Ok(None)
+    }
+    /// Drops the routing of `token` after a failed registration, a relink or a deletion.
+    fn forget_tenant(&self, _token: &str) {}
 }
@@ -0,0 +1,24 @@
+use async_trait::async_trait;
+use core::fmt::Debug;
+use std::{error::Error, time::Duration};
+
+use crate::domain::entities::change_event::ChangeEvent;
+
+pub type BusError = Box<dyn Error + Send + Sync>;
+
+/// Carries change events between server instances. An instance listens on the channels of the
+/// users it streams, so their events arrive whichever instance polls them.
+#[async_trait]
+pub trait LiveUpdateBusAbstract: Send + Sync + Debug {
+    /// Starts receiving the events published on `channel` on this instance.
+    async fn listen(&self, channel: &str) -> Result<(), BusError>;
+    async fn unlisten(&self, channel: &str) -> Result<(), BusError>;
+    /// Sends `event` to every instance listening on `channel`.
+    async fn publish(&self, channel: &str, event: &ChangeEvent) -> Result<(), BusError>;
+    /// Whether any instance listens on `channel`.
+    async fn is_listened(&self, channel: &str) -> Result<bool, BusError>;
+    /// Keeps a stream ticket redeemable on every instance for `ttl`.
+    async fn store_ticket(&self, ticket: &str, token: &str, ttl: Duration) -> Result<(), BusError>;
+    /// Removes and returns the user token of a stored, unexpired ticket.
+    async fn take_ticket(&self, ticket: &str) -> Result<Option<String>, BusError>;
+}
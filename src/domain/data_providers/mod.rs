@@ -1,2 +1,3 @@
 pub mod data_provider_abstract;
+pub mod live_update_bus_abstract;
 pub mod notification_provider_abstract;
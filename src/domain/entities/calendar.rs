@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+
+use super::deadline::Deadline;
+
+const ICS_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const REMINDER_BEFORE: &str = "-PT3H";
+/// A deadline is a moment, RFC 5545 wants DTEND after DTSTART so the event has no DTEND.
+const EVENT_DURATION: &str = "PT0S";
+
+/// Renders deadlines as an RFC 5545 calendar with one event and reminder per deadline.
+pub fn render_calendar(deadlines: &[Deadline], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//aitu-keeper//deadlines//EN".to_owned(),
+        "CALSCALE:GREGORIAN".to_owned(),
+        "METHOD:PUBLISH".to_owned(),
+        "X-WR-CALNAME:Moodle deadlines".to_owned(),
+    ];
+
+    for deadline in deadlines {
+        let Some(due_at) = deadline.due_at() else {
+            continue;
+        };
+        let due_at = due_at.format(ICS_DATE_FORMAT).to_string();
+        let course = deadline.coursename.as_deref().unwrap_or("-");
+        let summary = format!("{} | {}", course, deadline.name);
+
+        lines.extend([
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:deadline-{}@aitu-keeper", deadline.id),
+            format!("DTSTAMP:{}", now.format(ICS_DATE_FORMAT)),
+            format!("DTSTART:{}", due_at),
+            format!("DURATION:{}", EVENT_DURATION),
+            format!("SUMMARY:{}", escape_text(&summary)),
+            format!(
+                "DESCRIPTION:{}",
+                escape_text(&deadline.create_body_message_deadline())
+            ),
+            "BEGIN:VALARM".to_owned(),
+            "ACTION:DISPLAY".to_owned(),
+            format!("DESCRIPTION:{}", escape_text(&summary)),
+            format!("TRIGGER:{}", REMINDER_BEFORE),
+            "END:VALARM".to_owned(),
+            "END:VEVENT".to_owned(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_owned());
+
+    let mut calendar = String::new();
+    for line in lines {
+        calendar.push_str(&fold_line(&line));
+        calendar.push_str("\r\n");
+    }
+    calendar
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Content lines longer than 75 octets are split, continuation lines start with a space.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for ch in line.chars() {
+        let len = ch.len_utf8();
+        if width + len > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(ch);
+        width += len;
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deadline() -> Deadline {
+        Deadline {
+            id: 7,
+            name: "Essay, part 1".to_string(),
+            // 2025-03-10 00:00 in UTC+6 plus 23:59.
+            timeusermidnight: 1741543200 + 86340,
+            formattedtime: "Monday, 10 March 23:59".to_string(),
+            coursename: Some("History".to_string()),
+            courseid: Some(3),
+        }
+    }
+
+    #[test]
+    fn test_render_calendar_event() {
+        let now = DateTime::from_timestamp(1741000000, 0).unwrap();
+        let calendar = render_calendar(&[deadline()], now);
+
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(calendar.contains("UID:deadline-7@aitu-keeper\r\n"));
+        assert!(calendar.contains("DTSTART:20250310T175900Z\r\nDURATION:PT0S\r\n"));
+        assert!(!calendar.contains("DTEND"));
+        assert!(calendar.contains("SUMMARY:History | Essay\\, part 1\r\n"));
+        assert!(calendar.contains("BEGIN:VALARM\r\nACTION:DISPLAY\r\n"));
+        assert!(calendar.contains("TRIGGER:-PT3H\r\n"));
+    }
+
+    #[test]
+    fn test_fold_line() {
+        let line = "a".repeat(160);
+        let folded = fold_line(&line);
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].len(), 75);
+        assert!(parts[1].starts_with(' '));
+        assert_eq!(parts.concat().replace(' ', ""), line);
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    User,
+    Course,
+    CourseRenamed,
+    Grade,
+    GradeOverview,
+    Deadline,
+    DeadlineMoved,
+    DeadlineCancelled,
+    TokenRevoked,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::User => "user",
+            ChangeKind::Course => "course",
+            ChangeKind::CourseRenamed => "course_renamed",
+            ChangeKind::Grade => "grade",
+            ChangeKind::GradeOverview => "grade_overview",
+            ChangeKind::Deadline => "deadline",
+            ChangeKind::DeadlineMoved => "deadline_moved",
+            ChangeKind::DeadlineCancelled => "deadline_cancelled",
+            ChangeKind::TokenRevoked => "token_revoked",
+        }
+    }
+}
+
+/// A change detected by the notification worker, mirrored to live clients.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub title: String,
+    pub body: String,
+    /// Machine readable details, also sent as the push data payload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<Value>,
+}
+
+impl ChangeEvent {
+    pub fn new(kind: ChangeKind, title: &str, body: &str) -> Self {
+        Self {
+            kind,
+            title: title.to_owned(),
+            body: body.to_owned(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Option<Value>) -> Self {
+        self.data = data;
+        self
+    }
+}
@@ -1,97 +1,100 @@
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+use super::diff::{FieldChange, Keyed};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
 pub struct Course {
     pub id: i64,
     pub fullname: String,
     pub enddate: i64,
 }
 
+/// Response of `core_course_get_updates_since`, modules of a course changed after its cursor.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CourseUpdates {
+    #[serde(default)]
+    pub instances: Vec<UpdatedInstance>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdatedInstance {
+    pub contextlevel: String,
+    pub id: i64,
+    #[serde(default)]
+    pub updates: Vec<InstanceUpdate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InstanceUpdate {
+    pub name: String,
+    pub timeupdated: Option<i64>,
+}
+
+impl CourseUpdates {
+    pub fn has_updates(&self) -> bool {
+        self.instances
+            .iter()
+            .any(|instance| !instance.updates.is_empty())
+    }
+}
+
 impl Course {
     pub fn delete_past_courses(courses: &mut Vec<Course>) {
-        let current_time =
-            Utc::now().with_timezone(&chrono::FixedOffset::east_opt(6 * 3600).unwrap());
-        let current_unix_time = current_time.timestamp();
+        let current_unix_time = Utc::now().timestamp();
         courses.retain(|course| course.enddate > current_unix_time);
     }
 }
 
-pub fn compare_courses<'a>(external_courses: &'a [Course], courses: &[Course]) -> Vec<&'a Course> {
-    let mut new_courses = Vec::new();
-    for external_course in external_courses {
-        if !courses.contains(external_course) {
-            new_courses.push(external_course);
-        }
+impl Keyed for Course {
+    type Key = i64;
+
+    fn key(&self) -> i64 {
+        self.id
+    }
+
+    fn changes(&self, old: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        FieldChange::push(&mut changes, "fullname", &old.fullname, &self.fullname);
+        FieldChange::push(&mut changes, "enddate", &old.enddate, &self.enddate);
+        changes
     }
-    new_courses
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_compare_courses_empty() {
-        let external_courses = vec![];
-        let courses = vec![];
-        let result = compare_courses(&external_courses, &courses);
-        assert!(result.is_empty());
-    }
+    use crate::domain::entities::diff::diff;
 
-    #[test]
-    fn test_compare_courses_new_course() {
-        let external_courses = vec![Course {
-            id: 1,
-            fullname: "Math".to_string(),
+    fn course(id: i64, fullname: &str) -> Course {
+        Course {
+            id,
+            fullname: fullname.to_string(),
             enddate: 0,
-        }];
-        let courses = vec![];
-        let result = compare_courses(&external_courses, &courses);
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].id, 1);
-        assert_eq!(result[0].fullname, "Math");
+        }
     }
 
     #[test]
-    fn test_compare_courses_existing_course() {
-        let external_courses = vec![Course {
-            id: 1,
-            fullname: "Math".to_string(),
-            enddate: 0,
-        }];
-        let courses = vec![Course {
-            id: 1,
-            fullname: "Math".to_string(),
-            enddate: 0,
-        }];
-        let result = compare_courses(&external_courses, &courses);
-        assert!(result.is_empty());
+    fn test_diff_courses_new_course() {
+        let external_courses = vec![course(1, "Math"), course(2, "Physics")];
+        let courses = vec![course(1, "Math")];
+        let result = diff(&courses, &external_courses);
+        assert_eq!(result.added.len(), 1);
+        assert_eq!(result.added[0].fullname, "Physics");
+        assert!(result.removed.is_empty() && result.modified.is_empty());
     }
 
     #[test]
-    fn test_compare_courses_different_courses() {
-        let external_courses = vec![
-            Course {
-                id: 1,
-                fullname: "Math".to_string(),
-                enddate: 0,
-            },
-            Course {
-                id: 2,
-                fullname: "Physics".to_string(),
-                enddate: 0,
-            },
-        ];
-        let courses = vec![Course {
-            id: 1,
-            fullname: "Math".to_string(),
-            enddate: 0,
-        }];
-        let result = compare_courses(&external_courses, &courses);
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].id, 2);
-        assert_eq!(result[0].fullname, "Physics");
+    fn test_diff_courses_renamed() {
+        let external_courses = vec![course(1, "Calculus")];
+        let courses = vec![course(1, "Math")];
+        let result = diff(&courses, &external_courses);
+        assert!(result.added.is_empty());
+        let renamed = result.modified[0].changed("fullname").unwrap();
+        assert_eq!(renamed.old, "Math");
+        assert_eq!(renamed.new, "Calculus");
     }
 
     #[test]
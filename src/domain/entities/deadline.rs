@@ -1,23 +1,44 @@
-use std::collections::HashSet;
 use std::error::Error;
 
 use chrono::Timelike;
-use chrono::{NaiveTime, Utc};
+use chrono::{DateTime, NaiveTime, Utc};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+use super::course::Course;
+use super::diff::{FieldChange, Keyed};
+
+const DAY: i64 = 24 * 60 * 60;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Events {
     pub events: Vec<Deadline>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// Response of `core_calendar_get_action_events_by_courses`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventsByCourses {
+    #[serde(default)]
+    pub groupedbycourse: Vec<CourseEvents>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CourseEvents {
+    pub courseid: i64,
+    pub events: Vec<Deadline>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, ToSchema)]
 pub struct Deadline {
     pub id: i32,
     pub name: String,
     pub timeusermidnight: i64,
     pub formattedtime: String,
     pub coursename: Option<String>,
+    #[serde(default)]
+    pub courseid: Option<i64>,
 }
 
 impl Deadline {
@@ -29,18 +50,74 @@ impl Deadline {
             self.formattedtime
         )
     }
+
+    /// Exact due time in UTC.
+    ///
+    /// Only valid for deadlines that went through [`sort_deadlines`], which adds the time
+    /// parsed from `formattedtime` to the user's midnight in `timeusermidnight`.
+    pub fn due_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.timeusermidnight, 0)
+    }
+
+    /// Deadlines stored before `courseid` was recorded are matched by course name.
+    pub fn belongs_to(&self, course: &Course) -> bool {
+        match self.courseid {
+            Some(courseid) => courseid == course.id,
+            None => self.coursename.as_deref() == Some(course.fullname.as_str()),
+        }
+    }
+
+    /// Title and body for a deadline whose due time changed from `old`.
+    pub fn create_message_rescheduled(&self, old: &Deadline) -> (&'static str, String) {
+        let (title, verb) = if self.timeusermidnight > old.timeusermidnight {
+            ("Deadline extended", "Extended to")
+        } else {
+            ("Deadline moved", "Moved to")
+        };
+        let body = format!(
+            "Course: {}\nTask: {}\n{} {}",
+            self.coursename.clone().unwrap_or("-".to_string()),
+            self.name,
+            verb,
+            self.formattedtime
+        );
+        (title, body)
+    }
+
+    pub fn create_body_message_cancelled(&self) -> String {
+        format!(
+            "Course: {}\nTask: {}\nWas due {}",
+            self.coursename.clone().unwrap_or("-".to_string()),
+            self.name,
+            self.formattedtime
+        )
+    }
+}
+
+/// Push data for a changed deadline. FCM only accepts string values.
+pub fn deadline_change_data(old: &Deadline, new: Option<&Deadline>) -> Value {
+    let mut data = json!({
+        "deadline_id": old.id.to_string(),
+        "old_time": old.timeusermidnight.to_string(),
+        "old_formattedtime": old.formattedtime,
+    });
+    if let Some(new) = new {
+        data["new_time"] = Value::from(new.timeusermidnight.to_string());
+        data["new_formattedtime"] = Value::from(new.formattedtime.clone());
+    }
+    data
 }
 
 pub fn sort_deadlines(
     deadlines: &mut [Deadline],
 ) -> Result<Vec<Deadline>, Box<dyn Error + Send + Sync>> {
-    let current_time = Utc::now().with_timezone(&chrono::FixedOffset::east_opt(6 * 3600).unwrap());
-    let current_unix_time = current_time.timestamp();
+    let current_unix_time = Utc::now().timestamp();
 
     let mut sorted_deadlines = Vec::new();
 
     for deadline in deadlines.iter_mut() {
-        if deadline.timeusermidnight + 21600 < current_unix_time {
+        // Midnight is in the user's time zone, but a whole day later the due day is over.
+        if deadline.timeusermidnight + DAY < current_unix_time {
             continue;
         }
         let seconds_after_mid;
@@ -61,7 +138,7 @@ pub fn sort_deadlines(
         }
         sorted_deadlines.push(deadline.clone())
     }
-    sorted_deadlines.sort_by(|a, b| a.timeusermidnight.cmp(&b.timeusermidnight));
+    sorted_deadlines.sort_by_key(|a| a.timeusermidnight);
     Ok(sorted_deadlines)
 }
 
@@ -92,21 +169,43 @@ pub fn extract_date_and_time(html: &str) -> Option<String> {
     }
 }
 
-pub fn compare_deadlines<'a>(
-    external_deadlines: &'a [Deadline],
-    deadlines: &[Deadline],
-) -> Vec<&'a Deadline> {
-    let existing_ids: HashSet<i32> = deadlines.iter().map(|d| d.id).collect();
+impl Keyed for Deadline {
+    type Key = i32;
+
+    fn key(&self) -> i32 {
+        self.id
+    }
 
-    external_deadlines
-        .iter()
-        .filter(|d| !existing_ids.contains(&d.id))
-        .collect()
+    fn changes(&self, old: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        FieldChange::push(&mut changes, "name", &old.name, &self.name);
+        FieldChange::push(
+            &mut changes,
+            "timeusermidnight",
+            &old.timeusermidnight,
+            &self.timeusermidnight,
+        );
+        FieldChange::push(
+            &mut changes,
+            "formattedtime",
+            &old.formattedtime,
+            &self.formattedtime,
+        );
+        FieldChange::push(
+            &mut changes,
+            "coursename",
+            &old.coursename,
+            &self.coursename,
+        );
+        FieldChange::push(&mut changes, "courseid", &old.courseid, &self.courseid);
+        changes
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::entities::diff::diff;
 
     #[test]
     fn test_extract_time() {
@@ -133,53 +232,65 @@ mod tests {
         assert_eq!(extract_date_and_time(html_no_match), None);
     }
 
-    #[test]
-    fn test_compare_deadlines_empty() {
-        let external_deadlines = vec![];
-        let deadlines = vec![Deadline {
-            id: 2,
+    fn deadline(id: i32, timeusermidnight: i64) -> Deadline {
+        Deadline {
+            id,
             name: "Test Deadline".to_string(),
-            timeusermidnight: 1678886400,
+            timeusermidnight,
             formattedtime: "2024-02-01 12:00".to_string(),
             coursename: Some("Math".to_string()),
-        }];
-        let result = compare_deadlines(&external_deadlines, &deadlines);
-        assert!(result.is_empty());
+            courseid: None,
+        }
     }
 
     #[test]
-    fn test_compare_deadlines_new_deadline() {
-        let external_deadlines = vec![Deadline {
-            id: 2,
-            name: "Test Deadline".to_string(),
-            timeusermidnight: 1678886400,
-            formattedtime: "2024-02-01 12:00".to_string(),
-            coursename: Some("Math".to_string()),
-        }];
-        let deadlines = vec![];
-        let result = compare_deadlines(&external_deadlines, &deadlines);
-        assert_eq!(result.len(), 1);
+    fn test_diff_deadlines_new_and_removed() {
+        let external_deadlines = vec![deadline(2, 1678886400)];
+        let deadlines = vec![deadline(1, 1678886400)];
+        let result = diff(&deadlines, &external_deadlines);
+        assert_eq!(result.added[0].id, 2);
+        assert_eq!(result.removed[0].id, 1);
     }
 
     #[test]
-    fn test_compare_deadlines_existing_deadline_by_id() {
-        let external_deadlines = vec![Deadline {
-            id: 1,
-            name: "Test Deadline".to_string(),
-            timeusermidnight: 1678886400,
-            formattedtime: "2024-02-01 12:00".to_string(),
-            coursename: Some("Math".to_string()),
-        }];
+    fn test_diff_deadlines_moved() {
+        let external_deadlines = vec![deadline(1, 1678972800)];
+        let deadlines = vec![deadline(1, 1678886400)];
+        let result = diff(&deadlines, &external_deadlines);
+        assert!(result.added.is_empty());
+        let moved = result.modified[0].changed("timeusermidnight").unwrap();
+        assert_eq!(moved.old, 1678886400);
+        assert_eq!(moved.new, 1678972800);
+    }
 
-        let deadlines = vec![Deadline {
-            id: 1,
-            name: "Deadline".to_string(),
-            timeusermidnight: 1678886400,
-            formattedtime: "2024".to_string(),
-            coursename: Some("Chemistry".to_string()),
-        }];
-        let result = compare_deadlines(&external_deadlines, &deadlines);
-        assert!(result.is_empty());
+    #[test]
+    fn test_rescheduled_message_and_data() {
+        let old = deadline(1, 1678886400);
+        let mut new = deadline(1, 1678972800);
+        new.formattedtime = "2024-02-02 12:00".to_string();
+
+        let (title, body) = new.create_message_rescheduled(&old);
+        assert_eq!(title, "Deadline extended");
+        assert!(body.ends_with("Extended to 2024-02-02 12:00"));
+
+        let data = deadline_change_data(&old, Some(&new));
+        assert_eq!(data["old_time"], "1678886400");
+        assert_eq!(data["new_time"], "1678972800");
+        assert!(deadline_change_data(&old, None).get("new_time").is_none());
+    }
+
+    #[test]
+    fn test_belongs_to_legacy_deadline_by_course_name() {
+        let course = Course {
+            id: 7,
+            fullname: "Math".to_string(),
+            enddate: 0,
+        };
+        let mut deadline = deadline(1, 1678886400);
+        assert!(deadline.belongs_to(&course));
+
+        deadline.courseid = Some(8);
+        assert!(!deadline.belongs_to(&course));
     }
 
     #[test]
@@ -198,6 +309,7 @@ mod tests {
             timeusermidnight: 1678886400,
             formattedtime: "<a href=\"some link\">Some Date</a>, 12:00".to_string(),
             coursename: Some("Math".to_string()),
+            courseid: None,
         }];
 
         let result = sort_deadlines(&mut deadlines)?;
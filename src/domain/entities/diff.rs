@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Entity compared between a stored and a freshly fetched snapshot.
+pub trait Keyed {
+    type Key: Ord;
+
+    /// Identity of the entry, stable across snapshots.
+    fn key(&self) -> Self::Key;
+
+    /// Fields that differ from `old`, empty when the entries are the same.
+    fn changes(&self, old: &Self) -> Vec<FieldChange>;
+}
+
+/// One field of an entry with its stored and fetched value.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: Value,
+    pub new: Value,
+}
+
+impl FieldChange {
+    /// Records `field` in `changes` when `old` and `new` differ.
+    pub fn push<V: PartialEq + Serialize>(
+        changes: &mut Vec<FieldChange>,
+        field: &'static str,
+        old: &V,
+        new: &V,
+    ) {
+        if old != new {
+            changes.push(FieldChange {
+                field,
+                old: serde_json::to_value(old).unwrap_or(Value::Null),
+                new: serde_json::to_value(new).unwrap_or(Value::Null),
+            });
+        }
+    }
+}
+
+/// An entry present in both snapshots with different fields.
+#[derive(Debug)]
+pub struct Modified<'a, T> {
+    pub old: &'a T,
+    pub new: &'a T,
+    pub changes: Vec<FieldChange>,
+}
+
+impl<T> Modified<'_, T> {
+    pub fn changed(&self, field: &str) -> Option<&FieldChange> {
+        self.changes.iter().find(|change| change.field == field)
+    }
+}
+
+/// Difference between two snapshots of the same entity type.
+#[derive(Debug)]
+pub struct Diff<'a, T> {
+    /// Fetched entries without a stored counterpart, in fetched order.
+    pub added: Vec<&'a T>,
+    /// Stored entries missing from the fetched snapshot, in stored order.
+    pub removed: Vec<&'a T>,
+    /// Entries in both snapshots whose fields changed, in fetched order.
+    pub modified: Vec<Modified<'a, T>>,
+}
+
+impl<T> Diff<'_, T> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Matches `old` and `new` entries by key. Later duplicates of a key win.
+pub fn diff<'a, T: Keyed>(old: &'a [T], new: &'a [T]) -> Diff<'a, T> {
+    let mut stored: BTreeMap<T::Key, &T> = old.iter().map(|entry| (entry.key(), entry)).collect();
+
+    let mut added = Vec::new();
+    let mut modified = Vec::new();
+    for entry in new {
+        match stored.remove(&entry.key()) {
+            Some(previous) => {
+                let changes = entry.changes(previous);
+                if !changes.is_empty() {
+                    modified.push(Modified {
+                        old: previous,
+                        new: entry,
+                        changes,
+                    });
+                }
+            }
+            None => added.push(entry),
+        }
+    }
+
+    let removed = old
+        .iter()
+        .filter(|entry| stored.contains_key(&entry.key()))
+        .collect();
+
+    Diff {
+        added,
+        removed,
+        modified,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Entry {
+        id: i64,
+        name: &'static str,
+    }
+
+    impl Keyed for Entry {
+        type Key = i64;
+
+        fn key(&self) -> i64 {
+            self.id
+        }
+
+        fn changes(&self, old: &Self) -> Vec<FieldChange> {
+            let mut changes = Vec::new();
+            FieldChange::push(&mut changes, "name", &old.name, &self.name);
+            changes
+        }
+    }
+
+    #[test]
+    fn test_diff_empty() {
+        let result = diff::<Entry>(&[], &[]);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_diff_added_removed_modified() {
+        let old = [
+            Entry { id: 1, name: "a" },
+            Entry { id: 2, name: "b" },
+            Entry { id: 3, name: "c" },
+        ];
+        let new = [
+            Entry { id: 3, name: "c" },
+            Entry { id: 4, name: "d" },
+            Entry { id: 2, name: "B" },
+        ];
+
+        let result = diff(&old, &new);
+        assert_eq!(result.added, vec![&new[1]]);
+        assert_eq!(result.removed, vec![&old[0]]);
+        assert_eq!(result.modified.len(), 1);
+        assert_eq!(result.modified[0].new.id, 2);
+        assert_eq!(
+            result.modified[0].changed("name"),
+            Some(&FieldChange {
+                field: "name",
+                old: Value::from("b"),
+                new: Value::from("B"),
+            })
+        );
+    }
+}
@@ -1,12 +1,14 @@
 use thiserror::Error;
 
+use crate::infrastructure::data_providers::errors::MoodleError;
+
 #[derive(Error, Debug)]
 pub enum ServiceError {
     #[error("User already exists with token: `{0}`")]
     UserAlreadyExists(String),
 
-    #[error("Invalid token: `{0}`")]
-    InvalidToken(String),
+    #[error("Missing or malformed authorization header")]
+    Unauthorized,
 
     #[error("Data not found for token: `{0}`")]
     DataNotFound(String),
@@ -22,6 +24,27 @@ pub enum ServiceError {
 
     #[error("System time error: `{0}`")]
     SystemTime(#[from] std::time::SystemTimeError),
+
+    #[error("Export error: `{0}`")]
+    Export(String),
+
+    #[error("Moodle is unavailable: `{0}`")]
+    Unavailable(String),
+
+    #[error("Moodle error: {0}")]
+    Moodle(MoodleError),
+
+    #[error("Unexpected response from Moodle: `{0}`")]
+    UnexpectedResponse(String),
+
+    #[error("New token belongs to a different Moodle account")]
+    AccountMismatch,
+
+    #[error("Unknown tenant: `{0}`")]
+    UnknownTenant(String),
+
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
 }
 
 #[derive(Error, Debug)]
@@ -34,13 +57,16 @@ pub enum NotificationError {
 
     #[error("Sending error: `{0}`")]
     Sending(String),
+
+    #[error("Moodle rejected the token")]
+    TokenRevoked,
 }
 
 impl From<ServiceError> for NotificationError {
     fn from(value: ServiceError) -> Self {
         match value {
             ServiceError::UserAlreadyExists(err) => Self::Data(err),
-            ServiceError::InvalidToken(err) => Self::Data(err),
+            ServiceError::Unauthorized => Self::Data("Unauthorized".to_owned()),
             ServiceError::DataNotFound(err) => Self::Data(err),
             ServiceError::InternalServerError => Self::Service("Internal service error".to_owned()),
             ServiceError::ReqwestError(err) => Self::Data(err),
@@ -48,6 +74,14 @@ impl From<ServiceError> for NotificationError {
             ServiceError::SystemTime(system_time_error) => {
                 Self::Data(system_time_error.to_string())
             }
+            ServiceError::Export(err) => Self::Service(err),
+            ServiceError::Unavailable(err) => Self::Data(err),
+            ServiceError::Moodle(MoodleError::InvalidToken) => Self::TokenRevoked,
+            ServiceError::Moodle(err) => Self::Data(err.to_string()),
+            ServiceError::UnexpectedResponse(err) => Self::Data(err),
+            ServiceError::AccountMismatch => Self::Data("Account mismatch".to_owned()),
+            ServiceError::UnknownTenant(err) => Self::Data(err),
+            ServiceError::InvalidQuery(err) => Self::Data(err),
         }
     }
 }
@@ -1,18 +1,21 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::diff::{FieldChange, Keyed};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserGrades {
     pub usergrades: Vec<Grade>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Grade {
     pub coursename: Option<String>,
     pub courseid: i64,
     pub gradeitems: Vec<GradeItems>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
 pub struct GradeItems {
     pub id: i64,
     pub itemname: String,
@@ -24,7 +27,7 @@ pub struct GradesOverview {
     pub grades: Vec<GradeOverview>,
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, ToSchema)]
 pub struct GradeOverview {
     pub course_name: Option<String>,
     pub courseid: i64,
@@ -32,43 +35,50 @@ pub struct GradeOverview {
     pub rawgrade: Option<String>,
 }
 
-pub fn compare_grades<'a>(
-    external_grades: &'a mut [Grade],
-    grades: &'a mut [Grade],
-) -> Vec<(&'a GradeItems, &'a GradeItems)> {
-    external_grades.sort_by_key(|g| g.courseid);
-    grades.sort_by_key(|g| g.courseid);
+impl Keyed for Grade {
+    type Key = i64;
 
-    for g in grades.iter_mut() {
-        g.gradeitems.sort_by_key(|item| item.id);
+    fn key(&self) -> i64 {
+        self.courseid
     }
-    for eg in external_grades.iter_mut() {
-        eg.gradeitems.sort_by_key(|item| item.id);
+
+    /// Item changes show up as one `gradeitems` change, diff the items for details.
+    fn changes(&self, old: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        FieldChange::push(
+            &mut changes,
+            "coursename",
+            &old.coursename,
+            &self.coursename,
+        );
+        FieldChange::push(
+            &mut changes,
+            "gradeitems",
+            &old.gradeitems,
+            &self.gradeitems,
+        );
+        changes
     }
+}
 
-    let mut new_and_old_grades = Vec::new();
-
-    for external_grade in external_grades {
-        if let Ok(grade_index) =
-            grades.binary_search_by_key(&external_grade.courseid, |g| g.courseid)
-        {
-            let grade = &grades[grade_index];
-
-            for external_item in &external_grade.gradeitems {
-                if let Ok(item_index) = grade
-                    .gradeitems
-                    .binary_search_by_key(&external_item.id, |gi| gi.id)
-                {
-                    let found_item = &grade.gradeitems[item_index];
-                    if external_item.percentageformatted != found_item.percentageformatted {
-                        new_and_old_grades.push((external_item, found_item));
-                    }
-                }
-            }
-        }
+impl Keyed for GradeItems {
+    type Key = i64;
+
+    fn key(&self) -> i64 {
+        self.id
     }
 
-    new_and_old_grades
+    fn changes(&self, old: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        FieldChange::push(&mut changes, "itemname", &old.itemname, &self.itemname);
+        FieldChange::push(
+            &mut changes,
+            "percentageformatted",
+            &old.percentageformatted,
+            &self.percentageformatted,
+        );
+        changes
+    }
 }
 
 pub fn sort_grades_overview(grades_overview: &mut Vec<GradeOverview>) {
@@ -80,90 +90,94 @@ pub fn sort_grades_overview(grades_overview: &mut Vec<GradeOverview>) {
     });
 }
 
-pub fn compare_grades_overview<'a>(
-    external_grades_overview: &'a [GradeOverview],
-    grades_overview: &[GradeOverview],
-) -> Vec<&'a GradeOverview> {
-    let mut new_grades_overview = Vec::new();
+impl Keyed for GradeOverview {
+    type Key = i64;
 
-    for external_grade_overview in external_grades_overview {
-        if !grades_overview.contains(external_grade_overview) {
-            new_grades_overview.push(external_grade_overview);
-        }
+    fn key(&self) -> i64 {
+        self.courseid
     }
 
-    new_grades_overview
+    fn changes(&self, old: &Self) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        FieldChange::push(
+            &mut changes,
+            "course_name",
+            &old.course_name,
+            &self.course_name,
+        );
+        FieldChange::push(&mut changes, "grade", &old.grade, &self.grade);
+        FieldChange::push(&mut changes, "rawgrade", &old.rawgrade, &self.rawgrade);
+        changes
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::entities::diff::diff;
 
-    #[test]
-    fn test_compare_grades_empty() {
-        let mut external_grades = vec![];
-        let mut grades = vec![];
-        let result = compare_grades(&mut external_grades, &mut grades);
-        assert!(result.is_empty());
+    fn grade(courseid: i64, percentageformatted: &str) -> Grade {
+        Grade {
+            coursename: Some("Math".to_string()),
+            courseid,
+            gradeitems: vec![GradeItems {
+                id: 1,
+                itemname: "Homework 1".to_string(),
+                percentageformatted: percentageformatted.to_string(),
+            }],
+        }
     }
 
     #[test]
-    fn test_compare_grades_different_course_ids() {
-        let mut external_grades = vec![Grade {
-            coursename: Some("Math".to_string()),
-            courseid: 1,
-            gradeitems: vec![],
-        }];
-        let mut grades = vec![Grade {
-            coursename: Some("Physics".to_string()),
-            courseid: 2,
-            gradeitems: vec![],
-        }];
-        let result = compare_grades(&mut external_grades, &mut grades);
+    fn test_diff_grades_empty() {
+        let result = diff::<Grade>(&[], &[]);
         assert!(result.is_empty());
     }
 
     #[test]
-    fn test_compare_grades_same_course_different_grades() {
-        let mut external_grades = vec![Grade {
-            coursename: Some("Math".to_string()),
-            courseid: 1,
-            gradeitems: vec![GradeItems {
-                id: 1,
-                itemname: "Homework 1".to_string(),
-                percentageformatted: "50.00%".to_string(),
-            }],
-        }];
-        let mut grades = vec![Grade {
-            coursename: Some("Math".to_string()),
-            courseid: 1,
-            gradeitems: vec![GradeItems {
-                id: 1,
-                itemname: "Homework 1".to_string(),
-                percentageformatted: "60.00%".to_string(),
-            }],
-        }];
+    fn test_diff_grades_different_course_ids() {
+        let external_grades = vec![grade(1, "50.00%")];
+        let grades = vec![grade(2, "50.00%")];
+        let result = diff(&grades, &external_grades);
+        assert_eq!(result.added[0].courseid, 1);
+        assert_eq!(result.removed[0].courseid, 2);
+        assert!(result.modified.is_empty());
+    }
 
-        let result = compare_grades(&mut external_grades, &mut grades);
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].0.percentageformatted, "50.00%");
-        assert_eq!(result[0].1.percentageformatted, "60.00%");
+    #[test]
+    fn test_diff_grades_same_course_different_grades() {
+        let external_grades = vec![grade(1, "50.00%")];
+        let grades = vec![grade(1, "60.00%")];
+
+        let result = diff(&grades, &external_grades);
+        assert_eq!(result.modified.len(), 1);
+        let modified = &result.modified[0];
+        let items = diff(&modified.old.gradeitems, &modified.new.gradeitems);
+        let change = items.modified[0].changed("percentageformatted").unwrap();
+        assert_eq!(change.old, "60.00%");
+        assert_eq!(change.new, "50.00%");
     }
 
     #[test]
-    fn test_compare_grades_same_course_same_grades() {
-        let mut external_grades = vec![Grade {
-            coursename: Some("Math".to_string()),
-            courseid: 1,
-            gradeitems: vec![GradeItems {
-                id: 1,
-                itemname: "Homework 1".to_string(),
-                percentageformatted: "50.00%".to_string(),
-            }],
-        }];
-        let mut grades = external_grades.clone();
+    fn test_diff_grades_same_course_same_grades() {
+        let external_grades = vec![grade(1, "50.00%")];
+        let grades = external_grades.clone();
 
-        let result = compare_grades(&mut external_grades, &mut grades);
+        let result = diff(&grades, &external_grades);
         assert!(result.is_empty());
     }
+
+    #[test]
+    fn test_diff_grades_overview_new_total() {
+        let overview = |grade: &str| GradeOverview {
+            course_name: Some("Math".to_string()),
+            courseid: 1,
+            grade: grade.to_string(),
+            rawgrade: Some(grade.to_string()),
+        };
+        let (grades_overview, external_grades_overview) =
+            ([overview("80.00")], [overview("90.00")]);
+        let result = diff(&grades_overview, &external_grades_overview);
+        assert_eq!(result.modified[0].changed("grade").unwrap().new, "90.00");
+    }
 }
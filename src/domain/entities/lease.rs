@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use rand::{distributions::Alphanumeric, Rng};
+
+/// Seconds a claimed user stays with one worker, a crashed worker blocks its users this long.
+pub const LEASE_DURATION: i64 = 120;
+
+/// How often a worker renews the lease of a user it is still polling.
+pub const LEASE_RENEWAL: Duration = Duration::from_secs(40);
+
+const WORKER_ID_LENGTH: usize = 12;
+
+/// Identifies this process as the owner of its leases. Leases only keep polls apart, several
+/// workers also need a shared or no read cache, see `CACHE` in the README.
+pub fn generate_worker_id() -> String {
+    let suffix: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(WORKER_ID_LENGTH)
+        .map(char::from)
+        .collect();
+    format!("worker-{}", suffix)
+}
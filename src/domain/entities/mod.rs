@@ -1,6 +1,16 @@
+pub mod calendar;
+pub mod change_event;
 pub mod course;
 pub mod deadline;
+pub mod diff;
 pub mod errors;
 pub mod grade;
+pub mod lease;
+pub mod poll_schedule;
+pub mod query;
+pub mod registration;
+pub mod sync_cursor;
+pub mod tenant;
 pub mod token;
+pub mod transcript;
 pub mod user;
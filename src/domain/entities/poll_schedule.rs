@@ -0,0 +1,111 @@
+use super::{course::Course, deadline::Deadline};
+
+/// Deadlines due within this window make polling four times as frequent.
+const NEAR_DEADLINE_WINDOW: i64 = 24 * 60 * 60;
+/// Courses ending within this window are in their exam period, polled twice as often.
+const EXAM_WINDOW: i64 = 14 * 24 * 60 * 60;
+const DORMANT_FACTOR: i64 = 12;
+const MIN_INTERVAL: i64 = 60;
+
+/// How long to wait before polling a user again, scaled from the configured base interval.
+#[derive(Debug, Clone, Copy)]
+pub struct PollSchedule {
+    base: i64,
+}
+
+impl PollSchedule {
+    pub fn new(base_secs: i64) -> Self {
+        Self {
+            base: base_secs.max(MIN_INTERVAL),
+        }
+    }
+
+    /// Seconds until the next poll at `now`. Users nobody listens to, or without a running
+    /// course, are dormant and polled rarely.
+    pub fn interval(
+        &self,
+        now: i64,
+        courses: &[Course],
+        deadlines: &[Deadline],
+        reachable: bool,
+    ) -> i64 {
+        let running = |course: &Course| course.enddate == 0 || course.enddate > now;
+        if !reachable || !courses.iter().any(running) {
+            return self.base * DORMANT_FACTOR;
+        }
+
+        let near_deadline = deadlines.iter().any(|deadline| {
+            deadline.timeusermidnight > now
+                && deadline.timeusermidnight - now <= NEAR_DEADLINE_WINDOW
+        });
+        let exam_period = courses
+            .iter()
+            .filter(|course| running(course))
+            .any(|course| course.enddate != 0 && course.enddate - now <= EXAM_WINDOW);
+
+        let interval = if near_deadline {
+            self.base / 4
+        } else if exam_period {
+            self.base / 2
+        } else {
+            self.base
+        };
+        interval.max(MIN_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn course(enddate: i64) -> Course {
+        Course {
+            id: 1,
+            fullname: "Math".to_string(),
+            enddate,
+        }
+    }
+
+    fn deadline(timeusermidnight: i64) -> Deadline {
+        Deadline {
+            id: 1,
+            name: "Quiz".to_string(),
+            timeusermidnight,
+            formattedtime: String::new(),
+            coursename: None,
+            courseid: Some(1),
+        }
+    }
+
+    #[test]
+    fn test_interval_adapts_to_activity() {
+        let schedule = PollSchedule::new(1200);
+        let term = [course(NOW + 60 * DAY)];
+
+        assert_eq!(schedule.interval(NOW, &term, &[], true), 1200);
+        assert_eq!(
+            schedule.interval(NOW, &term, &[deadline(NOW + DAY / 2)], true),
+            300
+        );
+        assert_eq!(
+            schedule.interval(NOW, &[course(NOW + 7 * DAY)], &[], true),
+            600
+        );
+    }
+
+    #[test]
+    fn test_interval_dormant_users() {
+        let schedule = PollSchedule::new(1200);
+        let term = [course(NOW + 60 * DAY)];
+
+        assert_eq!(schedule.interval(NOW, &term, &[], false), 1200 * 12);
+        assert_eq!(
+            schedule.interval(NOW, &[course(NOW - DAY)], &[], true),
+            1200 * 12
+        );
+        assert_eq!(schedule.interval(NOW, &[course(0)], &[], true), 1200);
+    }
+}
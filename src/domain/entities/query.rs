@@ -0,0 +1,384 @@
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::errors::ServiceError;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 200;
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_i32(&self) -> i32 {
+        match self {
+            SortOrder::Asc => 1,
+            SortOrder::Desc => -1,
+        }
+    }
+}
+
+/// Sort key of an entry: the sorted field plus the entity id as a tie-breaker.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd)]
+#[serde(untagged)]
+pub enum CursorValue {
+    /// A missing sort value, e.g. a grade without a course name. Sorts first, as in MongoDB.
+    Null,
+    Int(i64),
+    Str(String),
+}
+
+/// Keyset position of the last entry of a page, handed to clients as an opaque string.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Cursor {
+    pub value: CursorValue,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn new(value: CursorValue, id: i64) -> Self {
+        Self { value, id }
+    }
+
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let json = general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from up to `limit + 1` fetched entries, the extra one only signals a next page.
+    pub fn from_overfetch(mut items: Vec<T>, limit: u32, cursor_of: impl Fn(&T) -> Cursor) -> Self {
+        let has_more = items.len() > limit as usize;
+        items.truncate(limit as usize);
+        let next_cursor = if has_more {
+            items.last().map(|item| cursor_of(item).encode())
+        } else {
+            None
+        };
+        Self { items, next_cursor }
+    }
+}
+
+/// Paging parameters shared by all list queries.
+pub trait PageQuery {
+    fn raw_limit(&self) -> Option<u32>;
+    fn raw_cursor(&self) -> Option<&str>;
+
+    fn limit(&self) -> u32 {
+        self.raw_limit()
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    /// Whether `value` can be a position in this query's sort.
+    fn fits_sort(&self, value: &CursorValue) -> bool;
+
+    /// Decoded cursor, see [`PageQuery::validate_cursor`] for rejecting bad ones up front.
+    fn cursor(&self) -> Option<Cursor> {
+        self.raw_cursor().and_then(Cursor::decode)
+    }
+
+    /// Rejects a cursor that is malformed or was issued for another sort, which would
+    /// otherwise restart or skip the listing silently.
+    fn validate_cursor(&self) -> Result<(), ServiceError> {
+        let Some(raw) = self.raw_cursor() else {
+            return Ok(());
+        };
+        match Cursor::decode(raw) {
+            Some(cursor) if self.fits_sort(&cursor.value) => Ok(()),
+            Some(_) => Err(ServiceError::InvalidQuery(
+                "cursor belongs to another sort".to_owned(),
+            )),
+            None => Err(ServiceError::InvalidQuery("malformed cursor".to_owned())),
+        }
+    }
+}
+
+fn merge_range(
+    from: Option<i64>,
+    to: Option<i64>,
+    status_from: Option<i64>,
+    status_to: Option<i64>,
+) -> (Option<i64>, Option<i64>) {
+    let from = match (from, status_from) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    let to = match (to, status_to) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    (from, to)
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CourseStatus {
+    Active,
+    Past,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CourseSort {
+    #[default]
+    Id,
+    Name,
+    Enddate,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CourseQuery {
+    pub course_id: Option<i64>,
+    /// Unix time, courses ending at or after it.
+    pub from: Option<i64>,
+    /// Unix time, courses ending before it.
+    pub to: Option<i64>,
+    pub status: Option<CourseStatus>,
+    #[serde(default)]
+    pub sort: CourseSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl CourseQuery {
+    /// `enddate` bounds from the explicit range narrowed by the status.
+    pub fn enddate_range(&self, now: i64) -> (Option<i64>, Option<i64>) {
+        let (status_from, status_to) = match self.status {
+            Some(CourseStatus::Active) => (Some(now), None),
+            Some(CourseStatus::Past) => (None, Some(now)),
+            None => (None, None),
+        };
+        merge_range(self.from, self.to, status_from, status_to)
+    }
+}
+
+impl PageQuery for CourseQuery {
+    fn raw_limit(&self) -> Option<u32> {
+        self.limit
+    }
+
+    fn raw_cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn fits_sort(&self, value: &CursorValue) -> bool {
+        match self.sort {
+            CourseSort::Id | CourseSort::Enddate => matches!(value, CursorValue::Int(_)),
+            CourseSort::Name => matches!(value, CursorValue::Str(_)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeadlineStatus {
+    Upcoming,
+    Overdue,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeadlineSort {
+    #[default]
+    Due,
+    Name,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeadlineQuery {
+    pub course_id: Option<i64>,
+    /// Unix time, deadlines due at or after it.
+    pub from: Option<i64>,
+    /// Unix time, deadlines due before it.
+    pub to: Option<i64>,
+    pub status: Option<DeadlineStatus>,
+    #[serde(default)]
+    pub sort: DeadlineSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl DeadlineQuery {
+    /// Due time bounds from the explicit range narrowed by the status.
+    pub fn due_range(&self, now: i64) -> (Option<i64>, Option<i64>) {
+        let (status_from, status_to) = match self.status {
+            Some(DeadlineStatus::Upcoming) => (Some(now), None),
+            Some(DeadlineStatus::Overdue) => (None, Some(now)),
+            None => (None, None),
+        };
+        merge_range(self.from, self.to, status_from, status_to)
+    }
+}
+
+impl PageQuery for DeadlineQuery {
+    fn raw_limit(&self) -> Option<u32> {
+        self.limit
+    }
+
+    fn raw_cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn fits_sort(&self, value: &CursorValue) -> bool {
+        match self.sort {
+            DeadlineSort::Due => matches!(value, CursorValue::Int(_)),
+            DeadlineSort::Name => matches!(value, CursorValue::Str(_)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GradeStatus {
+    Graded,
+    Ungraded,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GradeSort {
+    #[default]
+    Course,
+    CourseId,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GradeQuery {
+    pub course_id: Option<i64>,
+    /// Keeps only grade items that have (or have not) been graded.
+    pub status: Option<GradeStatus>,
+    #[serde(default)]
+    pub sort: GradeSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl PageQuery for GradeQuery {
+    fn raw_limit(&self) -> Option<u32> {
+        self.limit
+    }
+
+    fn raw_cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn fits_sort(&self, value: &CursorValue) -> bool {
+        match self.sort {
+            GradeSort::Course => matches!(value, CursorValue::Str(_) | CursorValue::Null),
+            GradeSort::CourseId => matches!(value, CursorValue::Int(_)),
+        }
+    }
+}
+
+/// Moodle shows `-` for grade items without a grade.
+pub const UNGRADED: &str = "-";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = Cursor::new(CursorValue::Str("Math".to_string()), 42);
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(Cursor::decode("not a cursor"), None);
+    }
+
+    #[test]
+    fn test_cursor_must_fit_the_sort() {
+        let by_name = Cursor::new(CursorValue::Str("Math".to_string()), 1).encode();
+        let unnamed = Cursor::new(CursorValue::Null, 1).encode();
+        let query = |sort, cursor: &str| GradeQuery {
+            sort,
+            cursor: Some(cursor.to_owned()),
+            ..Default::default()
+        };
+
+        assert!(query(GradeSort::Course, &by_name).validate_cursor().is_ok());
+        assert!(query(GradeSort::Course, &unnamed).validate_cursor().is_ok());
+        assert!(matches!(
+            query(GradeSort::CourseId, &by_name).validate_cursor(),
+            Err(ServiceError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            query(GradeSort::Course, "not a cursor").validate_cursor(),
+            Err(ServiceError::InvalidQuery(_))
+        ));
+        let by_due = DeadlineQuery {
+            cursor: Some(by_name),
+            ..Default::default()
+        };
+        assert!(by_due.validate_cursor().is_err());
+        assert_eq!(Cursor::decode(&unnamed).unwrap().value, CursorValue::Null);
+    }
+
+    #[test]
+    fn test_page_from_overfetch() {
+        let page = Page::from_overfetch(vec![1, 2, 3], 2, |item| {
+            Cursor::new(CursorValue::Int(*item), *item)
+        });
+        assert_eq!(page.items, vec![1, 2]);
+        let next = Cursor::decode(&page.next_cursor.unwrap()).unwrap();
+        assert_eq!(next.id, 2);
+
+        let last = Page::from_overfetch(vec![1], 2, |item| {
+            Cursor::new(CursorValue::Int(*item), *item)
+        });
+        assert!(last.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_limit_is_clamped() {
+        let query = CourseQuery {
+            limit: Some(10_000),
+            ..Default::default()
+        };
+        assert_eq!(query.limit(), MAX_PAGE_SIZE);
+        assert_eq!(CourseQuery::default().limit(), DEFAULT_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_deadline_status_narrows_range() {
+        let query = DeadlineQuery {
+            from: Some(100),
+            to: Some(500),
+            status: Some(DeadlineStatus::Upcoming),
+            ..Default::default()
+        };
+        assert_eq!(query.due_range(300), (Some(300), Some(500)));
+
+        let query = DeadlineQuery {
+            status: Some(DeadlineStatus::Overdue),
+            ..Default::default()
+        };
+        assert_eq!(query.due_range(300), (None, Some(300)));
+    }
+}
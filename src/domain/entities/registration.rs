@@ -0,0 +1,18 @@
+use super::{
+    course::Course,
+    deadline::Deadline,
+    grade::{Grade, GradesOverview},
+    token::Token,
+    user::User,
+};
+
+/// Everything stored for a new user, written by the repository as one unit of work.
+#[derive(Debug)]
+pub struct Registration {
+    pub token: Token,
+    pub user: User,
+    pub courses: Vec<Course>,
+    pub grades: Vec<Grade>,
+    pub grades_overview: GradesOverview,
+    pub deadlines: Vec<Deadline>,
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// When a course was last fully synced for a user, unix seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SyncCursor {
+    pub courseid: i64,
+    pub synced_at: i64,
+}
+
+impl SyncCursor {
+    pub fn new(courseid: i64, synced_at: i64) -> Self {
+        Self {
+            courseid,
+            synced_at,
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Tenant of users registered before tenants existed, and of configs without `TENANTS_FILE`.
+pub const DEFAULT_TENANT: &str = "default";
+
+/// A school with its own Moodle instance. Users belong to one tenant from registration on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Tenant {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing)]
+    pub provider: LmsProvider,
+    /// Moodle web service endpoint up to and including `?`, like `BASE_URL`, or the Canvas
+    /// instance root.
+    #[serde(skip_serializing)]
+    pub base_url: String,
+    /// Response format parameters appended to every Moodle call, like `FORMAT_URL`.
+    #[serde(default, skip_serializing)]
+    pub format_url: String,
+    #[serde(default)]
+    pub branding: Branding,
+    /// IANA time zone name, e.g. `Asia/Almaty`, due times of LMSs that only report UTC are
+    /// shown in. Unknown names fail loading the tenants.
+    #[serde(default = "default_timezone")]
+    #[schema(value_type = String, example = "Asia/Almaty")]
+    pub timezone: Tz,
+    #[serde(default)]
+    pub grade_scale: GradeScale,
+}
+
+impl Tenant {
+    /// The single tenant of a deployment configured with `BASE_URL` and `FORMAT_URL`.
+    pub fn single(base_url: String, format_url: String) -> Self {
+        Self {
+            id: DEFAULT_TENANT.to_owned(),
+            name: DEFAULT_TENANT.to_owned(),
+            provider: LmsProvider::Moodle,
+            base_url,
+            format_url,
+            branding: Branding::default(),
+            timezone: default_timezone(),
+            grade_scale: GradeScale::default(),
+        }
+    }
+}
+
+/// LMS a tenant runs, `moodle` unless set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LmsProvider {
+    #[default]
+    Moodle,
+    Canvas,
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Branding {
+    pub logo_url: Option<String>,
+    /// CSS color, e.g. `#0055a5`.
+    pub primary_color: Option<String>,
+}
+
+/// Letter grades by lower percentage bound. Empty when the school only uses percentages.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GradeScale {
+    pub bands: Vec<GradeBand>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GradeBand {
+    pub min_percentage: f64,
+    pub letter: String,
+}
+
+impl GradeScale {
+    /// Letter of the highest band `percentage` reaches.
+    pub fn letter(&self, percentage: f64) -> Option<&str> {
+        self.bands
+            .iter()
+            .filter(|band| percentage >= band.min_percentage)
+            .max_by(|a, b| a.min_percentage.total_cmp(&b.min_percentage))
+            .map(|band| band.letter.as_str())
+    }
+
+    /// Appends the letter to a formatted percentage like `85,00 %`, other values are kept.
+    pub fn format(&self, percentage: &str) -> String {
+        let letter = percentage
+            .trim_end_matches('%')
+            .trim()
+            .replace(',', ".")
+            .parse()
+            .ok()
+            .and_then(|percentage| self.letter(percentage));
+        match letter {
+            Some(letter) => format!("{} ({})", percentage, letter),
+            None => percentage.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale() -> GradeScale {
+        GradeScale {
+            bands: vec![
+                GradeBand {
+                    min_percentage: 50.0,
+                    letter: "D".to_owned(),
+                },
+                GradeBand {
+                    min_percentage: 90.0,
+                    letter: "A".to_owned(),
+                },
+                GradeBand {
+                    min_percentage: 70.0,
+                    letter: "B".to_owned(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_grade_scale_letter() {
+        let scale = scale();
+        assert_eq!(scale.letter(95.0), Some("A"));
+        assert_eq!(scale.letter(70.0), Some("B"));
+        assert_eq!(scale.letter(20.0), None);
+        assert_eq!(GradeScale::default().letter(100.0), None);
+    }
+
+    #[test]
+    fn test_grade_scale_format() {
+        let scale = scale();
+        assert_eq!(scale.format("92,50 %"), "92,50 % (A)");
+        assert_eq!(scale.format("75.00 %"), "75.00 % (B)");
+        assert_eq!(scale.format("20,00 %"), "20,00 %");
+        assert_eq!(scale.format("-"), "-");
+        assert_eq!(GradeScale::default().format("92,50 %"), "92,50 %");
+    }
+
+    #[test]
+    fn test_tenant_from_json_defaults() {
+        let tenant: Tenant = serde_json::from_str(
+            r#"{"id": "enu", "name": "ENU", "base_url": "https://moodle.enu.kz/webservice/rest/server.php?", "format_url": "&moodlewsrestformat=json"}"#,
+        )
+        .unwrap();
+        assert_eq!(tenant.timezone, Tz::UTC);
+        assert_eq!(tenant.provider, LmsProvider::Moodle);
+        assert!(tenant.grade_scale.bands.is_empty());
+
+        let public = serde_json::to_value(&tenant).unwrap();
+        assert!(public.get("base_url").is_none());
+    }
+
+    #[test]
+    fn test_canvas_tenant_from_json() {
+        let tenant: Tenant = serde_json::from_str(
+            r#"{"id": "partner", "name": "Partner", "provider": "canvas", "base_url": "https://partner.instructure.com"}"#,
+        )
+        .unwrap();
+        assert_eq!(tenant.provider, LmsProvider::Canvas);
+        assert!(tenant.format_url.is_empty());
+    }
+
+    #[test]
+    fn test_timezone_must_be_iana() {
+        let tenant: Tenant = serde_json::from_str(
+            r#"{"id": "enu", "name": "ENU", "base_url": "https://moodle.enu.kz/?", "timezone": "Asia/Almaty"}"#,
+        )
+        .unwrap();
+        assert_eq!(tenant.timezone, Tz::Asia__Almaty);
+        assert_eq!(
+            serde_json::to_value(&tenant).unwrap()["timezone"],
+            "Asia/Almaty"
+        );
+
+        let invalid = serde_json::from_str::<Tenant>(
+            r#"{"id": "enu", "name": "ENU", "base_url": "https://moodle.enu.kz/?", "timezone": "GMT+6"}"#,
+        );
+        assert!(invalid.is_err());
+    }
+}
@@ -1,10 +1,17 @@
+use rand::{distributions::Alphanumeric, Rng};
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
 
-#[derive(Debug, Deserialize, Clone)]
+const FEED_TOKEN_LENGTH: usize = 40;
 
+#[derive(Debug, Deserialize, Clone, ToSchema)]
 pub struct Token {
     pub token: String,
     pub device_token: Option<String>,
+    /// Tenant id the token was issued by, the default tenant when omitted.
+    #[serde(default)]
+    pub tenant: Option<String>,
 }
 
 impl Token {
@@ -12,6 +19,24 @@ impl Token {
         Self {
             token,
             device_token,
+            tenant: None,
         }
     }
 }
+
+/// Secret for public, revocable URLs (e.g. the calendar feed), unrelated to the Moodle token.
+pub fn generate_feed_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(FEED_TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Hex SHA-256 of a Moodle token, for naming it in shared infrastructure such as Redis.
+pub fn token_digest(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,95 @@
+use super::grade::{Grade, GradeItems, GradeOverview};
+
+/// Stored grades of one user regrouped per course, with the course total next to its items.
+#[derive(Debug, PartialEq)]
+pub struct Transcript {
+    pub courses: Vec<TranscriptCourse>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TranscriptCourse {
+    pub courseid: i64,
+    pub course_name: String,
+    pub total: Option<String>,
+    pub items: Vec<GradeItems>,
+}
+
+impl Transcript {
+    pub fn new(grades: &[Grade], grades_overview: &[GradeOverview]) -> Self {
+        let mut courses: Vec<TranscriptCourse> = grades
+            .iter()
+            .map(|grade| TranscriptCourse {
+                courseid: grade.courseid,
+                course_name: grade.coursename.clone().unwrap_or("-".to_string()),
+                total: None,
+                items: grade.gradeitems.clone(),
+            })
+            .collect();
+
+        for overview in grades_overview {
+            match courses.iter_mut().find(|c| c.courseid == overview.courseid) {
+                Some(course) => course.total = Some(overview.grade.clone()),
+                None => courses.push(TranscriptCourse {
+                    courseid: overview.courseid,
+                    course_name: overview.course_name.clone().unwrap_or("-".to_string()),
+                    total: Some(overview.grade.clone()),
+                    items: Vec::new(),
+                }),
+            }
+        }
+
+        courses.sort_by(|a, b| a.course_name.cmp(&b.course_name));
+        Self { courses }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcript_merges_totals() {
+        let grades = vec![
+            Grade {
+                coursename: Some("Physics".to_string()),
+                courseid: 2,
+                gradeitems: vec![GradeItems {
+                    id: 1,
+                    itemname: "Lab 1".to_string(),
+                    percentageformatted: "90.00 %".to_string(),
+                }],
+            },
+            Grade {
+                coursename: Some("Math".to_string()),
+                courseid: 1,
+                gradeitems: vec![],
+            },
+        ];
+        let grades_overview = vec![
+            GradeOverview {
+                course_name: Some("Physics".to_string()),
+                courseid: 2,
+                grade: "88.50".to_string(),
+                rawgrade: Some("88.5".to_string()),
+            },
+            GradeOverview {
+                course_name: Some("Chemistry".to_string()),
+                courseid: 3,
+                grade: "75.00".to_string(),
+                rawgrade: Some("75".to_string()),
+            },
+        ];
+
+        let transcript = Transcript::new(&grades, &grades_overview);
+        let names: Vec<&str> = transcript
+            .courses
+            .iter()
+            .map(|c| c.course_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Chemistry", "Math", "Physics"]);
+        assert_eq!(transcript.courses[0].total.as_deref(), Some("75.00"));
+        assert_eq!(transcript.courses[1].total, None);
+        assert_eq!(transcript.courses[2].total.as_deref(), Some("88.50"));
+        assert_eq!(transcript.courses[2].items.len(), 1);
+    }
+}
@@ -5,6 +5,9 @@ use crate::{
         course::Course,
         deadline::Deadline,
         grade::{Grade, GradeOverview, GradesOverview},
+        query::{CourseQuery, DeadlineQuery, GradeQuery, Page},
+        registration::Registration,
+        sync_cursor::SyncCursor,
         token::Token,
         user::User,
     },
@@ -20,6 +23,43 @@ pub trait TokenRepositoryAbstract: Send + Sync + Debug {
     async fn save_tokens(&self, token: &Token) -> Result<(), DbError>;
     async fn find_all_device_tokens(&self, limit: i64, skip: u64) -> Result<Vec<Token>, DbError>;
     async fn delete(&self, token: &str) -> Result<(), DbError>;
+    async fn save_feed_token(&self, token: &str, feed_token: &str) -> Result<(), DbError>;
+    async fn find_token_by_feed_token(&self, feed_token: &str) -> Result<String, DbError>;
+    async fn delete_feed_token(&self, token: &str) -> Result<(), DbError>;
+    /// Stores the token and all initial data atomically. Only a token that already has a
+    /// user is `UserAlreadyExist`, leftovers of an interrupted registration are replaced.
+    async fn save_registration(&self, registration: &Registration) -> Result<(), DbError>;
+    /// Atomically leases up to `limit` tokens due for a poll at `now` to `worker` until
+    /// `lease_until`, never polled ones first. Tokens under another live lease and suspended
+    /// tokens are skipped.
+    async fn claim_due_tokens(
+        &self,
+        worker: &str,
+        now: i64,
+        lease_until: i64,
+        limit: i64,
+    ) -> Result<Vec<Token>, DbError>;
+    /// Extends `worker`'s lease, false once the lease was lost.
+    async fn renew_lease(
+        &self,
+        token: &str,
+        worker: &str,
+        lease_until: i64,
+    ) -> Result<bool, DbError>;
+    /// Sets the next poll and ends `worker`'s lease. Does nothing if the lease was lost.
+    async fn schedule_poll(
+        &self,
+        token: &str,
+        worker: &str,
+        next_poll_at: i64,
+    ) -> Result<(), DbError>;
+    /// Stops polling a token Moodle rejected. False when it was already suspended.
+    async fn suspend(&self, token: &str, suspended_at: i64) -> Result<bool, DbError>;
+    /// Tenant the token was registered with, `None` for users from before tenants.
+    async fn find_tenant(&self, token: &str) -> Result<Option<String>, DbError>;
+    /// Moves the user with all stored data, device and feed token from `old` to `new`, lifts
+    /// a suspension and makes the user due at once. `new` must not belong to a user yet.
+    async fn relink(&self, old: &str, new: &str) -> Result<(), DbError>;
 }
 
 #[automock]
@@ -34,6 +74,14 @@ pub trait UserRepositoryAbstract: Send + Sync + Debug {
 pub trait CourseRepositoryAbstract: Send + Sync + Debug {
     async fn save_courses(&self, token: &str, courses: &[Course]) -> Result<(), DbError>;
     async fn find_courses_by_token(&self, token: &str) -> Result<Vec<Course>, DbError>;
+    async fn find_courses_page(
+        &self,
+        token: &str,
+        query: &CourseQuery,
+    ) -> Result<Page<Course>, DbError>;
+    async fn find_sync_cursors(&self, token: &str) -> Result<Vec<SyncCursor>, DbError>;
+    /// Upserts the cursors by course, cursors of other courses are kept.
+    async fn save_sync_cursors(&self, token: &str, cursors: &[SyncCursor]) -> Result<(), DbError>;
 }
 
 #[automock]
@@ -41,6 +89,11 @@ pub trait CourseRepositoryAbstract: Send + Sync + Debug {
 pub trait DeadlineRepositoryAbstract: Send + Sync + Debug {
     async fn save_deadlines(&self, token: &str, deadlines: &[Deadline]) -> Result<(), DbError>;
     async fn find_deadlines_by_token(&self, token: &str) -> Result<Vec<Deadline>, DbError>;
+    async fn find_deadlines_page(
+        &self,
+        token: &str,
+        query: &DeadlineQuery,
+    ) -> Result<Page<Deadline>, DbError>;
     async fn delete_expired_deadlines(&self, unix_date: u64) -> Result<(), DbError>;
 }
 
@@ -49,6 +102,11 @@ pub trait DeadlineRepositoryAbstract: Send + Sync + Debug {
 pub trait GradeRepositoryAbstract: Send + Sync + Debug {
     async fn save_grades(&self, token: &str, grades: &[Grade]) -> Result<(), DbError>;
     async fn find_grades_by_token(&self, token: &str) -> Result<Vec<Grade>, DbError>;
+    async fn find_grades_page(
+        &self,
+        token: &str,
+        query: &GradeQuery,
+    ) -> Result<Page<Grade>, DbError>;
     async fn save_grades_overview(
         &self,
         token: &str,
@@ -1,11 +1,26 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use log::warn;
 
 use crate::domain::{
     data_providers::data_provider_abstract::DataProviderAbstract,
-    entities::{course::Course, errors::ServiceError, user::User},
+    entities::{
+        course::Course,
+        errors::ServiceError,
+        query::{CourseQuery, Page, PageQuery},
+        sync_cursor::SyncCursor,
+        user::User,
+    },
     repositories::data_repository_abstract::CourseRepositoryAbstract,
 };
 
+/// Courses are fully resynced at least this often, `core_course_get_updates_since` does not
+/// report gradebook overrides or calendar events outside activities.
+const FULL_SYNC_INTERVAL: i64 = 6 * 60 * 60;
+
 #[derive(Debug)]
 pub struct CourseService<T, U>
 where
@@ -33,6 +48,19 @@ where
         Ok(courses)
     }
 
+    pub async fn find_courses(
+        &self,
+        token: &str,
+        query: &CourseQuery,
+    ) -> Result<Page<Course>, ServiceError> {
+        query.validate_cursor()?;
+        let courses = self
+            .course_repository
+            .find_courses_page(token, query)
+            .await?;
+        Ok(courses)
+    }
+
     pub async fn update_courses(
         &self,
         token: &str,
@@ -42,4 +70,163 @@ where
         self.course_repository.save_courses(token, &courses).await?;
         Ok(courses)
     }
+
+    /// Replaces the stored courses with an already fetched copy.
+    pub async fn save_courses(&self, token: &str, courses: &[Course]) -> Result<(), ServiceError> {
+        self.course_repository.save_courses(token, courses).await?;
+        Ok(())
+    }
+
+    /// Ids of the courses that need a sync at `now`: never synced, due for a full sync, or
+    /// changed in Moodle since their cursor. A failed update check counts as changed.
+    pub async fn find_outdated_courses(
+        &self,
+        token: &str,
+        courses: &[Course],
+        now: i64,
+    ) -> Result<HashSet<i64>, ServiceError> {
+        let cursors: HashMap<i64, i64> = self
+            .course_repository
+            .find_sync_cursors(token)
+            .await?
+            .into_iter()
+            .map(|cursor| (cursor.courseid, cursor.synced_at))
+            .collect();
+
+        let mut outdated = HashSet::new();
+        let mut checks = Vec::new();
+        for course in courses {
+            match cursors.get(&course.id) {
+                Some(&synced_at) if now - synced_at < FULL_SYNC_INTERVAL => {
+                    checks.push(SyncCursor::new(course.id, synced_at));
+                }
+                _ => {
+                    outdated.insert(course.id);
+                }
+            }
+        }
+        if checks.is_empty() {
+            return Ok(outdated);
+        }
+
+        match self
+            .data_provider
+            .check_course_updates(token, &checks)
+            .await
+        {
+            Ok(updates) => {
+                for check in checks {
+                    match updates.get(&check.courseid) {
+                        Some(updates) if !updates.has_updates() => {}
+                        _ => {
+                            outdated.insert(check.courseid);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Update check failed: {}", e);
+                outdated.extend(checks.iter().map(|check| check.courseid));
+            }
+        }
+        Ok(outdated)
+    }
+
+    /// Moves the cursors of `course_ids` to `synced_at`.
+    pub async fn save_sync_cursors(
+        &self,
+        token: &str,
+        course_ids: &HashSet<i64>,
+        synced_at: i64,
+    ) -> Result<(), ServiceError> {
+        let cursors: Vec<SyncCursor> = course_ids
+            .iter()
+            .map(|courseid| SyncCursor::new(*courseid, synced_at))
+            .collect();
+        self.course_repository
+            .save_sync_cursors(token, &cursors)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::eq;
+
+    use super::*;
+    use crate::domain::{
+        data_providers::data_provider_abstract::MockDataProviderAbstract,
+        entities::{
+            course::{CourseUpdates, InstanceUpdate, UpdatedInstance},
+            token::Token,
+        },
+        repositories::data_repository_abstract::TokenRepositoryAbstract,
+    };
+    use crate::infrastructure::repositories::in_memory_repository::InMemoryRepository;
+
+    fn course(id: i64) -> Course {
+        Course {
+            id,
+            fullname: "Math".to_string(),
+            enddate: 0,
+        }
+    }
+
+    fn updates(changed: bool) -> CourseUpdates {
+        let updates = if changed {
+            vec![InstanceUpdate {
+                name: "gradeitems".to_string(),
+                timeupdated: Some(150),
+            }]
+        } else {
+            Vec::new()
+        };
+        CourseUpdates {
+            instances: vec![UpdatedInstance {
+                contextlevel: "module".to_string(),
+                id: 10,
+                updates,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_outdated_courses() {
+        let repository = InMemoryRepository::new();
+        repository
+            .save_tokens(&Token::new("token".to_string(), None))
+            .await
+            .unwrap();
+        repository
+            .save_sync_cursors(
+                "token",
+                &[
+                    SyncCursor::new(1, 100),
+                    SyncCursor::new(2, 100),
+                    SyncCursor::new(3, 100 - FULL_SYNC_INTERVAL),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider
+            .expect_check_course_updates()
+            .with(
+                eq("token"),
+                eq(vec![SyncCursor::new(1, 100), SyncCursor::new(2, 100)]),
+            )
+            .times(1)
+            .returning(|_, _| Ok(HashMap::from([(1, updates(false)), (2, updates(true))])));
+
+        let service = CourseService::new(Arc::new(data_provider), Arc::new(repository));
+        let courses = [course(1), course(2), course(3), course(4)];
+        let outdated = service
+            .find_outdated_courses("token", &courses, 200)
+            .await
+            .unwrap();
+
+        assert_eq!(outdated, HashSet::from([2, 3, 4]));
+    }
 }
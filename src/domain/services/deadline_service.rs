@@ -4,12 +4,16 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
+use chrono::Utc;
+
 use crate::domain::{
     data_providers::data_provider_abstract::DataProviderAbstract,
     entities::{
+        calendar::render_calendar,
         course::Course,
         deadline::{sort_deadlines, Deadline},
         errors::ServiceError,
+        query::{DeadlineQuery, Page, PageQuery},
     },
     repositories::data_repository_abstract::DeadlineRepositoryAbstract,
 };
@@ -44,21 +48,43 @@ where
         Ok(deadlines)
     }
 
+    pub async fn find_deadlines(
+        &self,
+        token: &str,
+        query: &DeadlineQuery,
+    ) -> Result<Page<Deadline>, ServiceError> {
+        query.validate_cursor()?;
+        let deadlines = self
+            .deadline_repository
+            .find_deadlines_page(token, query)
+            .await?;
+        Ok(deadlines)
+    }
+
+    pub async fn get_calendar(&self, token: &str) -> Result<String, ServiceError> {
+        let deadlines = self.get_deadlines(token).await?;
+        Ok(render_calendar(&deadlines, Utc::now()))
+    }
+
     pub async fn fetch_deadlines(
         &self,
         token: &str,
         courses: &[Course],
     ) -> Result<Vec<Deadline>, ServiceError> {
+        let course_ids: Vec<i64> = courses.iter().map(|course| course.id).collect();
+        let mut by_course = self
+            .data_provider
+            .get_deadlines_by_courses(token, &course_ids)
+            .await?;
         let mut deadlines = Vec::new();
 
         for course in courses {
-            let external_deadlines = self
-                .data_provider
-                .get_deadline_by_course_id(token, course.id)
-                .await?
-                .events;
-            for mut deadline in external_deadlines {
+            let Some(external_deadlines) = by_course.remove(&course.id) else {
+                continue;
+            };
+            for mut deadline in external_deadlines.events {
                 deadline.coursename = Option::from(course.fullname.clone());
+                deadline.courseid = Some(course.id);
                 deadlines.push(deadline);
             }
         }
@@ -78,8 +104,21 @@ where
         Ok(())
     }
 
+    /// Replaces the stored deadlines with an already fetched copy.
+    pub async fn save_deadlines(
+        &self,
+        token: &str,
+        deadlines: &[Deadline],
+    ) -> Result<(), ServiceError> {
+        self.deadline_repository
+            .save_deadlines(token, deadlines)
+            .await?;
+        Ok(())
+    }
+
     pub async fn remove_expired_deadlines(&self) -> Result<(), ServiceError> {
-        let unix_date = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + 21600;
+        // Stored deadlines hold the due time itself, see `sort_deadlines`.
+        let unix_date = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
         self.deadline_repository
             .delete_expired_deadlines(unix_date)
             .await?;
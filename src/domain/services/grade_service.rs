@@ -6,6 +6,8 @@ use crate::domain::{
         course::Course,
         errors::ServiceError,
         grade::{sort_grades_overview, Grade, GradeOverview, GradesOverview},
+        query::{GradeQuery, Page, PageQuery},
+        transcript::Transcript,
         user::User,
     },
     repositories::data_repository_abstract::GradeRepositoryAbstract,
@@ -38,21 +40,34 @@ where
         Ok(grades)
     }
 
+    pub async fn find_grades(
+        &self,
+        token: &str,
+        query: &GradeQuery,
+    ) -> Result<Page<Grade>, ServiceError> {
+        query.validate_cursor()?;
+        let grades = self.grade_repository.find_grades_page(token, query).await?;
+        Ok(grades)
+    }
+
     pub async fn fetch_grades(
         &self,
         token: &str,
         user: &User,
         courses: &[Course],
     ) -> Result<Vec<Grade>, ServiceError> {
+        let course_ids: Vec<i64> = courses.iter().map(|course| course.id).collect();
+        let mut by_course = self
+            .data_provider
+            .get_grades_by_courses(token, user.userid, &course_ids)
+            .await?;
         let mut grades = Vec::new();
 
         for course in courses {
-            let external_grades = self
-                .data_provider
-                .get_grades_by_course_id(token, user.userid, course.id)
-                .await?
-                .usergrades;
-            for mut grade in external_grades {
+            let Some(external_grades) = by_course.remove(&course.id) else {
+                continue;
+            };
+            for mut grade in external_grades.usergrades {
                 grade.coursename = Option::from(course.fullname.clone());
                 grades.push(grade);
             }
@@ -72,6 +87,12 @@ where
         Ok(())
     }
 
+    /// Replaces the stored grades with an already fetched copy.
+    pub async fn save_grades(&self, token: &str, grades: &[Grade]) -> Result<(), ServiceError> {
+        self.grade_repository.save_grades(token, grades).await?;
+        Ok(())
+    }
+
     pub async fn get_grades_overview(
         &self,
         token: &str,
@@ -83,6 +104,12 @@ where
         Ok(grades)
     }
 
+    pub async fn get_transcript(&self, token: &str) -> Result<Transcript, ServiceError> {
+        let grades = self.get_grades(token).await?;
+        let grades_overview = self.get_grades_overview(token).await?;
+        Ok(Transcript::new(&grades, &grades_overview))
+    }
+
     pub async fn fetch_grades_overview(
         &self,
         token: &str,
@@ -102,6 +129,17 @@ where
         Ok(grades_overview)
     }
 
+    pub async fn save_grades_overview(
+        &self,
+        token: &str,
+        grades_overview: &GradesOverview,
+    ) -> Result<(), ServiceError> {
+        self.grade_repository
+            .save_grades_overview(token, grades_overview)
+            .await?;
+        Ok(())
+    }
+
     pub async fn update_grades_overview(
         &self,
         token: &str,
@@ -0,0 +1,362 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use tokio::sync::{
+    broadcast::{self, Receiver, Sender},
+    Mutex as AsyncMutex,
+};
+
+use crate::domain::{
+    data_providers::live_update_bus_abstract::LiveUpdateBusAbstract,
+    entities::{
+        change_event::ChangeEvent,
+        token::{generate_feed_token, token_digest},
+    },
+};
+
+const CHANNEL_CAPACITY: usize = 64;
+
+/// How long a stream ticket can be redeemed, it is meant to be used right away.
+pub const TICKET_TTL: Duration = Duration::from_secs(30);
+
+/// Fan-out of change events to live (SSE / WebSocket) clients.
+///
+/// Channels are named by the digest of the user token. Without a bus events stay in this
+/// process; with one they go through the bus, which hands them back to every instance
+/// streaming the user through [`LiveUpdateService::deliver`].
+#[derive(Debug, Default)]
+pub struct LiveUpdateService {
+    channels: Mutex<HashMap<String, Sender<ChangeEvent>>>,
+    /// Single-use stream tickets with the user token they stand for and when they expire.
+    tickets: Mutex<HashMap<String, (String, Instant)>>,
+    bus: Option<Arc<dyn LiveUpdateBusAbstract>>,
+    /// Channels this instance listens on, held across bus calls so listening and pruning
+    /// don't interleave.
+    listening: AsyncMutex<HashSet<String>>,
+}
+
+impl LiveUpdateService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_bus(bus: Arc<dyn LiveUpdateBusAbstract>) -> Self {
+        Self {
+            bus: Some(bus),
+            ..Self::default()
+        }
+    }
+
+    pub async fn subscribe(&self, token: &str) -> Receiver<ChangeEvent> {
+        let channel = token_digest(token);
+        let receiver = self
+            .channels
+            .lock()
+            .unwrap()
+            .entry(channel.clone())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe();
+
+        if let Some(bus) = &self.bus {
+            let mut listening = self.listening.lock().await;
+            if !listening.contains(&channel) {
+                match bus.listen(&channel).await {
+                    Ok(()) => {
+                        listening.insert(channel);
+                    }
+                    Err(e) => warn!("Failed to listen for live updates: {}", e),
+                }
+            }
+        }
+        receiver
+    }
+
+    /// Whether the user is streamed by this or, with a bus, any other instance.
+    pub async fn has_subscribers(&self, token: &str) -> bool {
+        let channel = token_digest(token);
+        if self.has_local_subscribers(&channel) {
+            return true;
+        }
+        match &self.bus {
+            Some(bus) => bus.is_listened(&channel).await.unwrap_or_else(|e| {
+                warn!("Failed to look up live subscribers: {}", e);
+                false
+            }),
+            None => false,
+        }
+    }
+
+    fn has_local_subscribers(&self, channel: &str) -> bool {
+        let channels = self.channels.lock().unwrap();
+        channels
+            .get(channel)
+            .is_some_and(|sender| sender.receiver_count() > 0)
+    }
+
+    /// Issues a ticket clients without headers (`EventSource`) pass as `?ticket=` instead of
+    /// the Moodle token, which would otherwise end up in access logs. With a bus the ticket can
+    /// be redeemed on any instance.
+    pub async fn issue_ticket(&self, token: &str) -> String {
+        let ticket = generate_feed_token();
+        if let Some(bus) = &self.bus {
+            match bus.store_ticket(&ticket, token, TICKET_TTL).await {
+                Ok(()) => return ticket,
+                Err(e) => warn!("Failed to share a stream ticket, keeping it local: {}", e),
+            }
+        }
+        let now = Instant::now();
+        let mut tickets = self.tickets.lock().unwrap();
+        tickets.retain(|_, (_, expires_at)| *expires_at > now);
+        tickets.insert(ticket.clone(), (token.to_owned(), now + TICKET_TTL));
+        ticket
+    }
+
+    /// Returns the user token of an unexpired ticket, which can't be redeemed again.
+    pub async fn redeem_ticket(&self, ticket: &str) -> Option<String> {
+        let local = self
+            .tickets
+            .lock()
+            .unwrap()
+            .remove(ticket)
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(token, _)| token);
+        match (local, &self.bus) {
+            (Some(token), _) => Some(token),
+            (None, Some(bus)) => bus.take_ticket(ticket).await.unwrap_or_else(|e| {
+                warn!("Failed to redeem a stream ticket: {}", e);
+                None
+            }),
+            (None, None) => None,
+        }
+    }
+
+    pub async fn publish(&self, token: &str, event: ChangeEvent) {
+        let channel = token_digest(token);
+        if let Some(bus) = &self.bus {
+            match bus.publish(&channel, &event).await {
+                Ok(()) => return,
+                Err(e) => warn!("Live update bus failed, delivering locally only: {}", e),
+            }
+        }
+        self.deliver(&channel, event);
+    }
+
+    /// Hands `event` to this instance's subscribers of `channel`.
+    pub fn deliver(&self, channel: &str, event: ChangeEvent) {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(sender) = channels.get(channel) {
+            if sender.send(event).is_err() {
+                // Every receiver is gone, drop the channel until somebody subscribes again.
+                channels.remove(channel);
+            }
+        }
+    }
+
+    /// Stops listening on the bus for users nobody streams from this instance anymore.
+    pub async fn prune(&self) {
+        let Some(bus) = &self.bus else {
+            return;
+        };
+        let mut listening = self.listening.lock().await;
+        let idle: Vec<String> = listening
+            .iter()
+            .filter(|channel| !self.has_local_subscribers(channel))
+            .cloned()
+            .collect();
+        for channel in idle {
+            match bus.unlisten(&channel).await {
+                Ok(()) => {
+                    listening.remove(&channel);
+                    // A subscriber that arrived meanwhile listens again once the lock is free.
+                    let mut channels = self.channels.lock().unwrap();
+                    if channels
+                        .get(&channel)
+                        .is_some_and(|sender| sender.receiver_count() == 0)
+                    {
+                        channels.remove(&channel);
+                    }
+                }
+                Err(e) => warn!("Failed to stop listening for live updates: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::domain::{
+        data_providers::live_update_bus_abstract::BusError, entities::change_event::ChangeKind,
+    };
+
+    /// Bus shared by several instances, events are handed back by the test.
+    #[derive(Debug, Default)]
+    struct SharedBus {
+        listeners: Mutex<HashMap<String, usize>>,
+        published: Mutex<Vec<(String, ChangeEvent)>>,
+        tickets: Mutex<HashMap<String, String>>,
+    }
+
+    #[async_trait]
+    impl LiveUpdateBusAbstract for SharedBus {
+        async fn listen(&self, channel: &str) -> Result<(), BusError> {
+            *self
+                .listeners
+                .lock()
+                .unwrap()
+                .entry(channel.to_owned())
+                .or_default() += 1;
+            Ok(())
+        }
+
+        async fn unlisten(&self, channel: &str) -> Result<(), BusError> {
+            if let Some(count) = self.listeners.lock().unwrap().get_mut(channel) {
+                *count -= 1;
+            }
+            Ok(())
+        }
+
+        async fn publish(&self, channel: &str, event: &ChangeEvent) -> Result<(), BusError> {
+            self.published
+                .lock()
+                .unwrap()
+                .push((channel.to_owned(), event.clone()));
+            Ok(())
+        }
+
+        async fn is_listened(&self, channel: &str) -> Result<bool, BusError> {
+            Ok(self
+                .listeners
+                .lock()
+                .unwrap()
+                .get(channel)
+                .is_some_and(|count| *count > 0))
+        }
+
+        async fn store_ticket(
+            &self,
+            ticket: &str,
+            token: &str,
+            _ttl: Duration,
+        ) -> Result<(), BusError> {
+            self.tickets
+                .lock()
+                .unwrap()
+                .insert(ticket.to_owned(), token.to_owned());
+            Ok(())
+        }
+
+        async fn take_ticket(&self, ticket: &str) -> Result<Option<String>, BusError> {
+            Ok(self.tickets.lock().unwrap().remove(ticket))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_reaches_only_matching_user() {
+        let service = LiveUpdateService::new();
+        let mut first = service.subscribe("first").await;
+        let mut second = service.subscribe("second").await;
+
+        let event = ChangeEvent::new(ChangeKind::Grade, "Math", "New grade");
+        service.publish("first", event.clone()).await;
+
+        assert_eq!(first.recv().await.unwrap(), event);
+        assert!(second.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_channel_dropped_without_receivers() {
+        let service = LiveUpdateService::new();
+        let receiver = service.subscribe("token").await;
+        assert!(service.has_subscribers("token").await);
+
+        drop(receiver);
+        service
+            .publish("token", ChangeEvent::new(ChangeKind::User, "t", "b"))
+            .await;
+        assert!(!service.has_subscribers("token").await);
+        assert!(service.channels.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_events_reach_streams_of_other_instances() {
+        let bus = Arc::new(SharedBus::default());
+        let polling =
+            LiveUpdateService::with_bus(Arc::clone(&bus) as Arc<dyn LiveUpdateBusAbstract>);
+        let streaming =
+            LiveUpdateService::with_bus(Arc::clone(&bus) as Arc<dyn LiveUpdateBusAbstract>);
+        assert!(!polling.has_subscribers("token").await);
+
+        let mut receiver = streaming.subscribe("token").await;
+        assert!(polling.has_subscribers("token").await);
+
+        let event = ChangeEvent::new(ChangeKind::Grade, "Math", "New grade");
+        polling.publish("token", event.clone()).await;
+        let (channel, published) = bus.published.lock().unwrap().pop().unwrap();
+        // The raw token never leaves the process.
+        assert_eq!(channel, token_digest("token"));
+        streaming.deliver(&channel, published);
+
+        assert_eq!(receiver.recv().await.unwrap(), event);
+    }
+
+    #[tokio::test]
+    async fn test_prune_stops_listening_for_closed_streams() {
+        let bus = Arc::new(SharedBus::default());
+        let service =
+            LiveUpdateService::with_bus(Arc::clone(&bus) as Arc<dyn LiveUpdateBusAbstract>);
+        let open = service.subscribe("open").await;
+        let closed = service.subscribe("closed").await;
+
+        drop(closed);
+        service.prune().await;
+
+        assert!(service.has_subscribers("open").await);
+        assert!(!service.has_subscribers("closed").await);
+        drop(open);
+    }
+
+    #[tokio::test]
+    async fn test_ticket_is_single_use() {
+        let service = LiveUpdateService::new();
+        let ticket = service.issue_ticket("token").await;
+
+        assert_eq!(
+            service.redeem_ticket(&ticket).await.as_deref(),
+            Some("token")
+        );
+        assert_eq!(service.redeem_ticket(&ticket).await, None);
+        assert_eq!(service.redeem_ticket("unknown").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_expired_ticket_is_rejected() {
+        let service = LiveUpdateService::new();
+        let ticket = service.issue_ticket("token").await;
+        service.tickets.lock().unwrap().get_mut(&ticket).unwrap().1 = Instant::now();
+
+        assert_eq!(service.redeem_ticket(&ticket).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_ticket_is_redeemed_on_another_instance() {
+        let bus = Arc::new(SharedBus::default());
+        let issuing =
+            LiveUpdateService::with_bus(Arc::clone(&bus) as Arc<dyn LiveUpdateBusAbstract>);
+        let streaming =
+            LiveUpdateService::with_bus(Arc::clone(&bus) as Arc<dyn LiveUpdateBusAbstract>);
+        let ticket = issuing.issue_ticket("token").await;
+
+        assert_eq!(
+            streaming.redeem_ticket(&ticket).await.as_deref(),
+            Some("token")
+        );
+        assert_eq!(issuing.redeem_ticket(&ticket).await, None);
+    }
+}
@@ -1,6 +1,7 @@
 pub mod course_service;
 pub mod deadline_service;
 pub mod grade_service;
+pub mod live_update_service;
 pub mod notification_service;
 pub mod token_service;
 pub mod user_service;
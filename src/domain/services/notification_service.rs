@@ -1,7 +1,12 @@
-use std::sync::Arc;
+use std::{collections::HashSet, future::Future, sync::Arc};
 
-use log::warn;
-use tokio::task;
+use chrono::Utc;
+use log::{info, warn};
+use serde_json::Value;
+use tokio::{
+    task,
+    time::{self, Instant},
+};
 
 use crate::domain::{
     data_providers::{
@@ -9,10 +14,14 @@ use crate::domain::{
         notification_provider_abstract::NotificationProviderAbstract,
     },
     entities::{
-        course::{compare_courses, Course},
-        deadline::{compare_deadlines, sort_deadlines},
+        change_event::{ChangeEvent, ChangeKind},
+        course::Course,
+        deadline::{deadline_change_data, sort_deadlines, Deadline},
+        diff::diff,
         errors::NotificationError,
-        grade::{compare_grades, compare_grades_overview, sort_grades_overview},
+        grade::{sort_grades_overview, Grade},
+        lease::LEASE_RENEWAL,
+        poll_schedule::PollSchedule,
         token::Token,
         user::User,
     },
@@ -24,7 +33,7 @@ use crate::domain::{
 
 use super::{
     course_service::CourseService, deadline_service::DeadlineService, grade_service::GradeService,
-    token_service::TokenService, user_service::UserService,
+    live_update_service::LiveUpdateService, token_service::TokenService, user_service::UserService,
 };
 
 #[derive(Debug)]
@@ -53,6 +62,9 @@ pub struct NotificationService<
     course_service: Arc<CourseService<DataProvider, CourseRepo>>,
     grade_service: Arc<GradeService<DataProvider, GradeRepo>>,
     deadline_service: Arc<DeadlineService<DataProvider, DeadlineRepo>>,
+    live_updates: Arc<LiveUpdateService>,
+    schedule: PollSchedule,
+    worker_id: String,
 }
 
 impl<
@@ -82,6 +94,7 @@ where
     GradeRepo: GradeRepositoryAbstract,
     DeadlineRepo: DeadlineRepositoryAbstract,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         notification_provider: Arc<NotificationProvider>,
         data_provider: Arc<DataProvider>,
@@ -92,6 +105,9 @@ where
         course_service: Arc<CourseService<DataProvider, CourseRepo>>,
         grade_service: Arc<GradeService<DataProvider, GradeRepo>>,
         deadline_service: Arc<DeadlineService<DataProvider, DeadlineRepo>>,
+        live_updates: Arc<LiveUpdateService>,
+        schedule: PollSchedule,
+        worker_id: String,
     ) -> Self {
         Self {
             notification_provider,
@@ -101,6 +117,9 @@ where
             course_service,
             grade_service,
             deadline_service,
+            live_updates,
+            schedule,
+            worker_id,
         }
     }
 }
@@ -124,44 +143,46 @@ impl<
         DeadlineRepo,
     >
 where
-    NotificationProvider: NotificationProviderAbstract,
-    DataProvider: DataProviderAbstract,
-    TokenRepo: TokenRepositoryAbstract,
-    UserRepo: UserRepositoryAbstract,
-    CourseRepo: CourseRepositoryAbstract,
-    GradeRepo: GradeRepositoryAbstract,
-    DeadlineRepo: DeadlineRepositoryAbstract,
+    NotificationProvider: NotificationProviderAbstract + 'static,
+    DataProvider: DataProviderAbstract + 'static,
+    TokenRepo: TokenRepositoryAbstract + 'static,
+    UserRepo: UserRepositoryAbstract + 'static,
+    CourseRepo: CourseRepositoryAbstract + 'static,
+    GradeRepo: GradeRepositoryAbstract + 'static,
+    DeadlineRepo: DeadlineRepositoryAbstract + 'static,
 {
-    pub async fn get_batches(
-        &'static self,
+    pub fn worker_id(&self) -> &str {
+        &self.worker_id
+    }
+
+    /// Claims up to `limit` due users and polls them, returns how many were claimed.
+    pub async fn poll_due(self: &Arc<Self>, limit: i64) -> Result<usize, NotificationError> {
+        self.poll_due_at(Utc::now().timestamp(), limit).await
+    }
+
+    /// [`Self::poll_due`] with the users due at `now`, later times poll users early.
+    pub async fn poll_due_at(
+        self: &Arc<Self>,
+        now: i64,
         limit: i64,
-        skip: &mut u64,
-    ) -> Result<(), NotificationError> {
-        let batch = self.token_service.find_all_tokens(limit, skip).await?;
+    ) -> Result<usize, NotificationError> {
+        let batch = self
+            .token_service
+            .claim_due_tokens(&self.worker_id, now, limit)
+            .await?;
 
         self.process_batch(&batch).await?;
-        Ok(())
+        Ok(batch.len())
     }
 
-    async fn process_batch(&'static self, batch: &[Token]) -> Result<(), NotificationError> {
+    async fn process_batch(self: &Arc<Self>, batch: &[Token]) -> Result<(), NotificationError> {
         let mut handles = Vec::new();
 
         for tokens in batch.iter() {
             let tokens = tokens.clone();
+            let service = Arc::clone(self);
 
-            let handle = task::spawn(async move {
-                if let Some(device_token) = &tokens.device_token {
-                    if let Err(e) = self.send_notification(&tokens.token, device_token).await {
-                        warn!("Error sending notification: {:?}", e.to_string());
-                    }
-                } else if let Err(e) = self
-                    .token_service
-                    .fetch_and_update_data(&tokens.token)
-                    .await
-                {
-                    warn!("Error fetching and saving data: {:?}", e.to_string());
-                }
-            });
+            let handle = task::spawn(async move { service.poll_leased(&tokens).await });
 
             handles.push(handle);
         }
@@ -175,40 +196,197 @@ where
         Ok(())
     }
 
+    /// Polls a claimed user while renewing its lease. Stops once the lease is lost,
+    /// the user belongs to another worker then.
+    async fn poll_leased(&self, tokens: &Token) {
+        let Some(reachable) = self.hold_lease(&tokens.token, self.poll(tokens)).await else {
+            warn!("Lease lost while polling, leaving the user to its new worker");
+            return;
+        };
+
+        // Failed polls are rescheduled too, so they are retried without spinning.
+        if let Err(e) = self.schedule_next_poll(&tokens.token, reachable).await {
+            warn!("Error scheduling next poll: {:?}", e.to_string());
+        }
+    }
+
+    /// Renews the lease on `token` while `poll` runs, `None` when another worker took it over.
+    async fn hold_lease<F>(&self, token: &str, poll: F) -> Option<bool>
+    where
+        F: Future<Output = bool>,
+    {
+        tokio::pin!(poll);
+        let mut renewal = time::interval_at(Instant::now() + LEASE_RENEWAL, LEASE_RENEWAL);
+
+        loop {
+            tokio::select! {
+                reachable = &mut poll => return Some(reachable),
+                _ = renewal.tick() => {
+                    match self
+                        .token_service
+                        .renew_lease(token, &self.worker_id, Utc::now().timestamp())
+                        .await
+                    {
+                        Ok(true) => {}
+                        Ok(false) => return None,
+                        Err(e) => warn!("Error renewing lease: {:?}", e.to_string()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns whether anyone is listening for the user's changes.
+    async fn poll(&self, tokens: &Token) -> bool {
+        let device_token = tokens.device_token.as_deref();
+        let reachable =
+            device_token.is_some() || self.live_updates.has_subscribers(&tokens.token).await;
+        // Unreachable users go through the same cursors, their changes are only stored.
+        match self.send_notification(&tokens.token, device_token).await {
+            Ok(()) => {}
+            Err(NotificationError::TokenRevoked) => {
+                if let Err(e) = self.suspend_revoked(&tokens.token, device_token).await {
+                    warn!("Error suspending user: {:?}", e.to_string());
+                }
+            }
+            Err(e) => warn!("Error polling user: {:?}", e.to_string()),
+        }
+        reachable
+    }
+
+    /// Stops polling a user whose token was revoked, e.g. after a password change, and asks
+    /// them once to log in again.
+    async fn suspend_revoked(
+        &self,
+        token: &str,
+        device_token: Option<&str>,
+    ) -> Result<(), NotificationError> {
+        let suspended = self
+            .token_service
+            .suspend_if_revoked(token, Utc::now().timestamp())
+            .await?;
+        if suspended {
+            info!("Suspended a user whose Moodle token was revoked");
+            self.notify(
+                token,
+                device_token,
+                ChangeKind::TokenRevoked,
+                "Please log in again",
+                "Your Moodle session has expired, log in again to keep receiving updates.",
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn schedule_next_poll(
+        &self,
+        token: &str,
+        reachable: bool,
+    ) -> Result<(), NotificationError> {
+        let now = Utc::now().timestamp();
+        let courses = self
+            .course_service
+            .get_courses(token)
+            .await
+            .unwrap_or_default();
+        let deadlines = self
+            .deadline_service
+            .get_deadlines(token)
+            .await
+            .unwrap_or_default();
+
+        let interval = self.schedule.interval(now, &courses, &deadlines, reachable);
+        self.token_service
+            .schedule_poll(token, &self.worker_id, now + interval)
+            .await?;
+        Ok(())
+    }
+
     async fn send_notification(
         &self,
         token: &str,
-        device_token: &str,
+        device_token: Option<&str>,
     ) -> Result<(), NotificationError> {
+        let started_at = Utc::now().timestamp();
         let user = self.send_user_info(token, device_token).await?;
         let mut courses = self.send_course(token, device_token, &user).await?;
-        self.send_grade(token, device_token, &user, &courses)
+        let outdated = self
+            .course_service
+            .find_outdated_courses(token, &courses, started_at)
+            .await?;
+
+        self.send_grade(token, device_token, &user, &courses, &outdated)
             .await?;
         self.send_grade_overview(token, device_token, &courses)
             .await?;
         Course::delete_past_courses(&mut courses);
-        self.send_deadline(token, device_token, &courses).await?;
+        self.send_deadline(token, device_token, &courses, &outdated)
+            .await?;
 
+        // Only after everything was compared, a failed cycle retries the same courses.
+        self.course_service
+            .save_sync_cursors(token, &outdated, started_at)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn notify(
+        &self,
+        token: &str,
+        device_token: Option<&str>,
+        kind: ChangeKind,
+        title: &str,
+        body: &str,
+    ) -> Result<(), NotificationError> {
+        self.notify_with_data(token, device_token, kind, title, body, None)
+            .await
+    }
+
+    /// Pushes the message to the device, if any, and mirrors it to live subscribers.
+    async fn notify_with_data(
+        &self,
+        token: &str,
+        device_token: Option<&str>,
+        kind: ChangeKind,
+        title: &str,
+        body: &str,
+        data: Option<Value>,
+    ) -> Result<(), NotificationError> {
+        if let Some(device_token) = device_token {
+            let mut message = self
+                .notification_provider
+                .create_message(device_token, title, body);
+            message.data = data.clone();
+            self.notification_provider
+                .send_notification(message)
+                .await
+                .map_err(|e| NotificationError::Sending(e.to_string()))?;
+        }
+        self.live_updates
+            .publish(token, ChangeEvent::new(kind, title, body).with_data(data))
+            .await;
         Ok(())
     }
 
     async fn send_user_info(
         &self,
         token: &str,
-        device_token: &str,
+        device_token: Option<&str>,
     ) -> Result<User, NotificationError> {
         let external_user = self.data_provider.get_user(token).await?;
         let user = self.user_service.get_user(token).await?;
         if !user.eq(&external_user) {
             let body = external_user.create_body_message_user();
-            let message =
-                self.notification_provider
-                    .create_message(device_token, "New user info", &body);
-
-            self.notification_provider
-                .send_notification(message)
-                .await
-                .map_err(|e| NotificationError::Sending(e.to_string()))?;
+            self.notify(
+                token,
+                device_token,
+                ChangeKind::User,
+                "New user info",
+                &body,
+            )
+            .await?;
             self.user_service.update_user(token).await?;
         }
         Ok(external_user)
@@ -217,31 +395,43 @@ where
     async fn send_course(
         &self,
         token: &str,
-        device_token: &str,
+        device_token: Option<&str>,
         user: &User,
     ) -> Result<Vec<Course>, NotificationError> {
-        let mut flag = false;
         let external_courses = self.data_provider.get_courses(token, user.userid).await?;
         let courses = self.course_service.get_courses(token).await?;
-        let new_courses = compare_courses(&external_courses, &courses);
-
-        if !new_courses.is_empty() {
-            flag = true;
-
-            for new_course in new_courses {
-                let body = new_course.fullname.clone();
-                let message =
-                    self.notification_provider
-                        .create_message(device_token, "New course", &body);
-                self.notification_provider
-                    .send_notification(message)
-                    .await
-                    .map_err(|e| NotificationError::Sending(e.to_string()))?;
-            }
+        let changes = diff(&courses, &external_courses);
+
+        for new_course in &changes.added {
+            self.notify(
+                token,
+                device_token,
+                ChangeKind::Course,
+                "New course",
+                &new_course.fullname,
+            )
+            .await?;
+        }
+        for renamed in changes
+            .modified
+            .iter()
+            .filter(|m| m.changed("fullname").is_some())
+        {
+            let body = format!("{} -> {}", renamed.old.fullname, renamed.new.fullname);
+            self.notify(
+                token,
+                device_token,
+                ChangeKind::CourseRenamed,
+                "Course renamed",
+                &body,
+            )
+            .await?;
         }
 
-        if flag {
-            self.course_service.update_courses(token, user).await?;
+        if !changes.is_empty() {
+            self.course_service
+                .save_courses(token, &external_courses)
+                .await?;
         }
         Ok(external_courses)
     }
@@ -249,55 +439,120 @@ where
     async fn send_deadline(
         &self,
         token: &str,
-        device_token: &str,
+        device_token: Option<&str>,
         courses: &[Course],
+        outdated: &HashSet<i64>,
     ) -> Result<(), NotificationError> {
-        let mut flag = false;
-        for course in courses {
-            let deadlines = self
-                .deadline_service
-                .get_deadlines(token)
-                .await
-                .unwrap_or_default();
+        let deadlines = self
+            .deadline_service
+            .get_deadlines(token)
+            .await
+            .unwrap_or_default();
+        let now = Utc::now().timestamp();
+        let mut changed = false;
+        let mut fetched = Vec::new();
 
-            let mut external_deadlines = self
-                .data_provider
-                .get_deadline_by_course_id(token, course.id)
-                .await?
-                .events;
+        let outdated_ids: Vec<i64> = courses
+            .iter()
+            .map(|course| course.id)
+            .filter(|id| outdated.contains(id))
+            .collect();
+        let mut by_course = self
+            .data_provider
+            .get_deadlines_by_courses(token, &outdated_ids)
+            .await?;
 
-            if external_deadlines.is_empty() {
+        for course in courses {
+            if !outdated.contains(&course.id) {
+                fetched.extend(
+                    deadlines
+                        .iter()
+                        .filter(|deadline| deadline.belongs_to(course))
+                        .cloned(),
+                );
                 continue;
-            };
+            }
+
+            let mut external_deadlines = by_course
+                .remove(&course.id)
+                .map(|events| events.events)
+                .unwrap_or_default();
 
-            for sorted_deadline in external_deadlines.iter_mut() {
-                sorted_deadline.coursename = Option::from(course.fullname.clone());
+            for external_deadline in external_deadlines.iter_mut() {
+                external_deadline.coursename = Option::from(course.fullname.clone());
+                external_deadline.courseid = Some(course.id);
             }
 
             let sorted_deadlines = sort_deadlines(&mut external_deadlines)
                 .map_err(|e| NotificationError::Data(e.to_string()))?;
-            let new_deadlines = compare_deadlines(&sorted_deadlines, &deadlines);
-
-            if !new_deadlines.is_empty() {
-                flag = true;
-                for new_deadline in new_deadlines {
-                    let body = new_deadline.create_body_message_deadline();
-                    let message = self.notification_provider.create_message(
-                        device_token,
-                        "New deadline",
-                        &body,
-                    );
-                    self.notification_provider
-                        .send_notification(message)
-                        .await
-                        .map_err(|e| NotificationError::Sending(e.to_string()))?
+            let stored: Vec<Deadline> = deadlines
+                .iter()
+                .filter(|deadline| deadline.belongs_to(course))
+                .cloned()
+                .collect();
+            let changes = diff(&stored, &sorted_deadlines);
+            changed |= !changes.is_empty();
+
+            for new_deadline in &changes.added {
+                let body = new_deadline.create_body_message_deadline();
+                self.notify(
+                    token,
+                    device_token,
+                    ChangeKind::Deadline,
+                    "New deadline",
+                    &body,
+                )
+                .await?;
+            }
+            for moved in changes
+                .modified
+                .iter()
+                .filter(|m| m.changed("timeusermidnight").is_some())
+            {
+                let (title, body) = moved.new.create_message_rescheduled(moved.old);
+                self.notify_with_data(
+                    token,
+                    device_token,
+                    ChangeKind::DeadlineMoved,
+                    title,
+                    &body,
+                    Some(deadline_change_data(moved.old, Some(moved.new))),
+                )
+                .await?;
+            }
+            // Deadlines that passed, were submitted or fell beyond the event limit drop out of
+            // the fetched list too, only those the LMS no longer has were cancelled.
+            for cancelled in changes
+                .removed
+                .iter()
+                .filter(|deadline| deadline.timeusermidnight > now)
+            {
+                if self
+                    .data_provider
+                    .deadline_exists(token, course.id, i64::from(cancelled.id))
+                    .await?
+                {
+                    continue;
                 }
+                let body = cancelled.create_body_message_cancelled();
+                self.notify_with_data(
+                    token,
+                    device_token,
+                    ChangeKind::DeadlineCancelled,
+                    "Deadline cancelled",
+                    &body,
+                    Some(deadline_change_data(cancelled, None)),
+                )
+                .await?;
             }
+
+            fetched.extend(sorted_deadlines);
         }
 
-        if flag {
+        if changed {
+            fetched.sort_by_key(|deadline| deadline.timeusermidnight);
             self.deadline_service
-                .update_deadlines(token, courses)
+                .save_deadlines(token, &fetched)
                 .await?;
         }
 
@@ -307,74 +562,74 @@ where
     async fn send_grade(
         &self,
         token: &str,
-        device_token: &str,
+        device_token: Option<&str>,
         user: &User,
         courses: &[Course],
+        outdated: &HashSet<i64>,
     ) -> Result<(), NotificationError> {
-        let mut flag = false;
-        let past_grades = self.grade_service.get_grades(token).await?;
+        let stored_grades = self.grade_service.get_grades(token).await?;
+        let mut changed = false;
+        let mut grades = Vec::new();
 
-        let all_courses_in_grades = courses
+        let stale: Vec<i64> = courses
             .iter()
-            .all(|course| past_grades.iter().any(|grade| grade.courseid == course.id));
-
-        if !all_courses_in_grades {
-            self.grade_service
-                .update_grades(token, user, courses)
-                .await?;
-        }
+            .map(|course| course.id)
+            .filter(|id| {
+                outdated.contains(id) || !stored_grades.iter().any(|grade| grade.courseid == *id)
+            })
+            .collect();
+        let mut by_course = self
+            .data_provider
+            .get_grades_by_courses(token, user.userid, &stale)
+            .await?;
 
         for course in courses {
-            let mut external_grades = self
-                .data_provider
-                .get_grades_by_course_id(token, user.userid, course.id)
-                .await?
-                .usergrades;
-
-            for external_grade in external_grades.iter_mut() {
-                external_grade.coursename = Option::from(course.fullname.clone());
+            let stored: Vec<Grade> = stored_grades
+                .iter()
+                .filter(|grade| grade.courseid == course.id)
+                .cloned()
+                .collect();
+            if !stored.is_empty() && !outdated.contains(&course.id) {
+                grades.extend(stored);
+                continue;
             }
 
-            let mut grades = self.grade_service.get_grades(token).await?;
+            let mut external_grades = by_course
+                .remove(&course.id)
+                .map(|grades| grades.usergrades)
+                .unwrap_or_default();
 
-            for external_grade in external_grades.iter() {
-                for grade in grades.iter() {
-                    if external_grade.courseid == grade.courseid
-                        && external_grade.gradeitems.len() != grade.gradeitems.len()
-                    {
-                        self.grade_service
-                            .update_grades(token, user, courses)
-                            .await?;
-                    }
-                }
+            for external_grade in external_grades.iter_mut() {
+                external_grade.coursename = Option::from(course.fullname.clone());
             }
 
-            let new_grades = compare_grades(&mut external_grades, &mut grades);
+            // Grades of a course seen for the first time are stored without notifications.
+            let changes = diff(&stored, &external_grades);
+            changed |= !changes.is_empty();
 
-            if !new_grades.is_empty() {
-                flag = true;
-                for new_grade in new_grades {
+            for modified in &changes.modified {
+                let items = diff(&modified.old.gradeitems, &modified.new.gradeitems);
+                for item in items
+                    .modified
+                    .iter()
+                    .filter(|m| m.changed("percentageformatted").is_some())
+                {
                     let title = course.fullname.clone();
                     let body = format!(
                         "New grade | {}\n{} -> {}",
-                        new_grade.0.itemname,
-                        new_grade.1.percentageformatted,
-                        new_grade.0.percentageformatted
+                        item.new.itemname,
+                        item.old.percentageformatted,
+                        item.new.percentageformatted
                     );
-                    let message =
-                        self.notification_provider
-                            .create_message(device_token, &title, &body);
-                    self.notification_provider
-                        .send_notification(message)
-                        .await
-                        .map_err(|e| NotificationError::Sending(e.to_string()))?
+                    self.notify(token, device_token, ChangeKind::Grade, &title, &body)
+                        .await?;
                 }
             }
+            grades.extend(external_grades);
         }
-        if flag {
-            self.grade_service
-                .update_grades(token, user, courses)
-                .await?;
+
+        if changed || grades.len() != stored_grades.len() {
+            self.grade_service.save_grades(token, &grades).await?;
         }
 
         Ok(())
@@ -383,10 +638,9 @@ where
     async fn send_grade_overview(
         &self,
         token: &str,
-        device_token: &str,
+        device_token: Option<&str>,
         courses: &[Course],
     ) -> Result<(), NotificationError> {
-        let mut flag = false;
         let mut external_grades_overview = self.data_provider.get_grades_overview(token).await?;
 
         for external_grade_overview in external_grades_overview.grades.iter_mut() {
@@ -401,31 +655,459 @@ where
         let mut grades_overview = self.grade_service.get_grades_overview(token).await?;
         sort_grades_overview(&mut grades_overview);
 
-        let new_external_grades =
-            compare_grades_overview(&external_grades_overview.grades, &grades_overview);
-        if !new_external_grades.is_empty() {
-            flag = true;
-            for new_external_grade in new_external_grades.iter() {
-                let title = new_external_grade
-                    .course_name
-                    .clone()
-                    .unwrap_or("-".to_string());
-                let body = format!("New course total grade | {}", new_external_grade.grade);
-                let message =
-                    self.notification_provider
-                        .create_message(device_token, &title, &body);
-                self.notification_provider
-                    .send_notification(message)
-                    .await
-                    .map_err(|e| NotificationError::Sending(e.to_string()))?
-            }
+        let changes = diff(&grades_overview, &external_grades_overview.grades);
+        let new_totals = changes.added.iter().copied().chain(
+            changes
+                .modified
+                .iter()
+                .filter(|m| m.changed("grade").is_some())
+                .map(|m| m.new),
+        );
+        for new_external_grade in new_totals {
+            let title = new_external_grade
+                .course_name
+                .clone()
+                .unwrap_or("-".to_string());
+            let body = format!("New course total grade | {}", new_external_grade.grade);
+            self.notify(
+                token,
+                device_token,
+                ChangeKind::GradeOverview,
+                &title,
+                &body,
+            )
+            .await?;
         }
-        if flag {
+        if !changes.is_empty() {
             self.grade_service
-                .update_grades_overview(token, courses)
+                .save_grades_overview(token, &external_grades_overview)
                 .await?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mockall::predicate::eq;
+
+    use super::*;
+    use crate::{
+        domain::{
+            data_providers::data_provider_abstract::MockDataProviderAbstract,
+            entities::{deadline::Events, grade::GradesOverview, registration::Registration},
+            repositories::data_repository_abstract::TokenRepositoryAbstract,
+        },
+        infrastructure::{
+            data_providers::errors::{MoodleError, ResponseError},
+            notification_provider::recording_notification_provider::RecordingNotificationProvider,
+            repositories::in_memory_repository::InMemoryRepository,
+        },
+    };
+
+    type Service = NotificationService<
+        RecordingNotificationProvider,
+        MockDataProviderAbstract,
+        InMemoryRepository,
+        InMemoryRepository,
+        InMemoryRepository,
+        InMemoryRepository,
+        InMemoryRepository,
+    >;
+
+    const TOKEN: &str = "token";
+    const DEVICE: &str = "device";
+    const DAY: i64 = 24 * 60 * 60;
+
+    struct Fixture {
+        service: Arc<Service>,
+        pushes: Arc<RecordingNotificationProvider>,
+    }
+
+    async fn fixture(
+        data_provider: MockDataProviderAbstract,
+        registration: Registration,
+    ) -> Fixture {
+        let repository = Arc::new(InMemoryRepository::new());
+        repository.save_registration(&registration).await.unwrap();
+        let data_provider = Arc::new(data_provider);
+        let user_service = Arc::new(UserService::new(
+            Arc::clone(&data_provider),
+            Arc::clone(&repository),
+        ));
+        let course_service = Arc::new(CourseService::new(
+            Arc::clone(&data_provider),
+            Arc::clone(&repository),
+        ));
+        let grade_service = Arc::new(GradeService::new(
+            Arc::clone(&data_provider),
+            Arc::clone(&repository),
+        ));
+        let deadline_service = Arc::new(DeadlineService::new(
+            Arc::clone(&data_provider),
+            Arc::clone(&repository),
+        ));
+        let token_service = Arc::new(TokenService::new(
+            Arc::clone(&data_provider),
+            Arc::clone(&repository),
+            Arc::clone(&user_service),
+            Arc::clone(&course_service),
+            Arc::clone(&grade_service),
+            Arc::clone(&deadline_service),
+        ));
+        let pushes = Arc::new(RecordingNotificationProvider::default());
+        let service = Arc::new(NotificationService::new(
+            Arc::clone(&pushes),
+            data_provider,
+            token_service,
+            user_service,
+            course_service,
+            grade_service,
+            deadline_service,
+            Arc::new(LiveUpdateService::new()),
+            PollSchedule::new(900),
+            "worker".to_owned(),
+        ));
+        Fixture { service, pushes }
+    }
+
+    fn course(id: i64, fullname: &str) -> Course {
+        Course {
+            id,
+            fullname: fullname.to_owned(),
+            enddate: 0,
+        }
+    }
+
+    fn deadline(id: i32, name: &str, courseid: i64) -> Deadline {
+        Deadline {
+            id,
+            name: name.to_owned(),
+            timeusermidnight: Utc::now().timestamp() + 3 * DAY,
+            formattedtime: "Friday, 23:59".to_owned(),
+            coursename: None,
+            courseid: Some(courseid),
+        }
+    }
+
+    fn registration(courses: Vec<Course>, deadlines: Vec<Deadline>) -> Registration {
+        Registration {
+            token: Token::new(TOKEN.to_owned(), Some(DEVICE.to_owned())),
+            user: User {
+                username: "student".to_owned(),
+                fullname: "Student".to_owned(),
+                userid: 1,
+            },
+            courses,
+            grades: Vec::new(),
+            grades_overview: GradesOverview { grades: Vec::new() },
+            deadlines,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submitted_deadline_is_not_cancelled() {
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider
+            .expect_get_deadlines_by_courses()
+            .returning(|_, _| Ok(HashMap::new()));
+        data_provider
+            .expect_deadline_exists()
+            .with(eq(TOKEN), eq(1), eq(1))
+            .returning(|_, _, _| Ok(true));
+        data_provider
+            .expect_deadline_exists()
+            .with(eq(TOKEN), eq(1), eq(2))
+            .returning(|_, _, _| Ok(false));
+        let courses = vec![course(1, "Math")];
+        let fixture = fixture(
+            data_provider,
+            registration(
+                courses.clone(),
+                vec![
+                    deadline(1, "Submitted essay", 1),
+                    deadline(2, "Dropped quiz", 1),
+                ],
+            ),
+        )
+        .await;
+
+        fixture
+            .service
+            .send_deadline(TOKEN, Some(DEVICE), &courses, &HashSet::from([1]))
+            .await
+            .unwrap();
+
+        fixture.pushes.assert_titles(&["Deadline cancelled"]);
+        let cancelled = fixture.pushes.assert_sent("Deadline cancelled");
+        assert!(cancelled.body.contains("Dropped quiz"));
+        assert_eq!(cancelled.data.unwrap()["deadline_id"], "2");
+    }
+
+    #[tokio::test]
+    async fn test_renamed_course_is_notified() {
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider
+            .expect_get_courses()
+            .returning(|_, _| Ok(vec![course(1, "Calculus"), course(2, "Physics")]));
+        let registration = registration(vec![course(1, "Math"), course(2, "Physics")], Vec::new());
+        let user = registration.user.clone();
+        let fixture = fixture(data_provider, registration).await;
+
+        fixture
+            .service
+            .send_course(TOKEN, Some(DEVICE), &user)
+            .await
+            .unwrap();
+
+        fixture.pushes.assert_titles(&["Course renamed"]);
+        assert_eq!(
+            fixture.pushes.assert_sent("Course renamed").body,
+            "Math -> Calculus"
+        );
+        let stored = fixture
+            .service
+            .course_service
+            .get_courses(TOKEN)
+            .await
+            .unwrap();
+        assert_eq!(stored[0].fullname, "Calculus");
+    }
+
+    #[tokio::test]
+    async fn test_moved_deadline_is_notified() {
+        let stored = deadline(1, "Essay", 1);
+        let mut moved = stored.clone();
+        moved.timeusermidnight -= DAY;
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider
+            .expect_get_deadlines_by_courses()
+            .returning(move |_, _| {
+                Ok(HashMap::from([(
+                    1,
+                    Events {
+                        events: vec![moved.clone()],
+                    },
+                )]))
+            });
+        data_provider.expect_deadline_exists().never();
+        let courses = vec![course(1, "Math")];
+        let fixture = fixture(
+            data_provider,
+            registration(courses.clone(), vec![stored.clone()]),
+        )
+        .await;
+
+        fixture
+            .service
+            .send_deadline(TOKEN, Some(DEVICE), &courses, &HashSet::from([1]))
+            .await
+            .unwrap();
+
+        fixture.pushes.assert_titles(&["Deadline moved"]);
+        let data = fixture.pushes.assert_sent("Deadline moved").data.unwrap();
+        assert_eq!(data["deadline_id"], "1");
+        assert_eq!(data["old_time"], stored.timeusermidnight.to_string());
+        let saved = fixture
+            .service
+            .deadline_service
+            .get_deadlines(TOKEN)
+            .await
+            .unwrap();
+        assert!(saved[0].timeusermidnight < stored.timeusermidnight);
+    }
+
+    #[tokio::test]
+    async fn test_passed_deadline_is_dropped_silently() {
+        let mut passed = deadline(1, "Old quiz", 1);
+        passed.timeusermidnight = Utc::now().timestamp() - DAY;
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider
+            .expect_get_deadlines_by_courses()
+            .returning(|_, _| Ok(HashMap::new()));
+        data_provider.expect_deadline_exists().never();
+        let courses = vec![course(1, "Math")];
+        let fixture = fixture(data_provider, registration(courses.clone(), vec![passed])).await;
+
+        fixture
+            .service
+            .send_deadline(TOKEN, Some(DEVICE), &courses, &HashSet::from([1]))
+            .await
+            .unwrap();
+
+        fixture.pushes.assert_titles(&[]);
+        let saved = fixture
+            .service
+            .deadline_service
+            .get_deadlines(TOKEN)
+            .await
+            .unwrap();
+        assert!(saved.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_poll_is_rescheduled() {
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider
+            .expect_get_user()
+            .returning(|_| Err(ResponseError::Decode("maintenance".to_owned())));
+        let fixture = fixture(data_provider, registration(Vec::new(), Vec::new())).await;
+        let token_service = &fixture.service.token_service;
+        let now = Utc::now().timestamp();
+        let claimed = token_service
+            .claim_due_tokens("worker", now, 10)
+            .await
+            .unwrap();
+        assert_eq!(claimed.len(), 1);
+
+        fixture.service.poll_leased(&claimed[0]).await;
+
+        // The lease is released and the retry waits for the next interval.
+        assert!(token_service
+            .claim_due_tokens("other", now, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            token_service
+                .claim_due_tokens("other", now + DAY, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        fixture.pushes.assert_none_sent();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lost_lease_aborts_the_poll() {
+        let fixture = fixture(
+            MockDataProviderAbstract::new(),
+            registration(Vec::new(), Vec::new()),
+        )
+        .await;
+        let token_service = &fixture.service.token_service;
+        let now = Utc::now().timestamp();
+        token_service
+            .claim_due_tokens("other", now, 10)
+            .await
+            .unwrap();
+
+        let held = fixture
+            .service
+            .hold_lease(TOKEN, std::future::pending())
+            .await;
+
+        assert_eq!(held, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lease_is_renewed_during_a_long_poll() {
+        let fixture = fixture(
+            MockDataProviderAbstract::new(),
+            registration(Vec::new(), Vec::new()),
+        )
+        .await;
+        let token_service = &fixture.service.token_service;
+        token_service
+            .claim_due_tokens("worker", Utc::now().timestamp(), 10)
+            .await
+            .unwrap();
+
+        let held = fixture
+            .service
+            .hold_lease(TOKEN, async {
+                time::sleep(LEASE_RENEWAL * 5).await;
+                true
+            })
+            .await;
+
+        assert_eq!(held, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_revoked_token_is_announced_once() {
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider
+            .expect_get_user()
+            .returning(|_| Err(ResponseError::Moodle(MoodleError::InvalidToken)));
+        data_provider
+            .expect_valid_token()
+            .times(2)
+            .returning(|_| Err(ResponseError::Moodle(MoodleError::InvalidToken)));
+        let registration = registration(Vec::new(), Vec::new());
+        let tokens = registration.token.clone();
+        let fixture = fixture(data_provider, registration).await;
+
+        fixture.service.poll(&tokens).await;
+        fixture.service.poll(&tokens).await;
+
+        fixture.pushes.assert_titles(&["Please log in again"]);
+        assert_eq!(fixture.pushes.pushes_to(DEVICE).len(), 1);
+        // A suspended user is no longer claimed.
+        assert!(fixture
+            .service
+            .token_service
+            .claim_due_tokens("worker", Utc::now().timestamp(), 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_request_with_a_valid_token_is_not_suspended() {
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider
+            .expect_get_user()
+            .returning(|_| Err(ResponseError::Moodle(MoodleError::InvalidToken)));
+        data_provider.expect_valid_token().returning(|_| Ok(()));
+        let registration = registration(Vec::new(), Vec::new());
+        let tokens = registration.token.clone();
+        let fixture = fixture(data_provider, registration).await;
+
+        fixture.service.poll(&tokens).await;
+
+        fixture.pushes.assert_none_sent();
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_user_is_synced_through_cursors() {
+        let mut data_provider = MockDataProviderAbstract::new();
+        data_provider.expect_get_user().returning(|_| {
+            Ok(User {
+                username: "student".to_owned(),
+                fullname: "Student".to_owned(),
+                userid: 1,
+            })
+        });
+        data_provider
+            .expect_get_courses()
+            .returning(|_, _| Ok(vec![course(1, "Math")]));
+        data_provider
+            .expect_get_grades_by_courses()
+            .returning(|_, _, _| Ok(HashMap::new()));
+        data_provider
+            .expect_get_grades_overview()
+            .returning(|_| Ok(GradesOverview { grades: Vec::new() }));
+        data_provider
+            .expect_get_deadlines_by_courses()
+            .returning(|_, _| Ok(HashMap::new()));
+        // Only the second poll finds a cursor to check from.
+        data_provider
+            .expect_check_course_updates()
+            .times(1)
+            .returning(|_, _| Ok(HashMap::new()));
+        let mut registration = registration(vec![course(1, "Math")], Vec::new());
+        registration.token.device_token = None;
+        let tokens = registration.token.clone();
+        let fixture = fixture(data_provider, registration).await;
+
+        assert!(!fixture.service.poll(&tokens).await);
+        assert!(!fixture.service.poll(&tokens).await);
+
+        fixture.pushes.assert_titles(&[]);
+    }
+}
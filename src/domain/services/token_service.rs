@@ -1,11 +1,22 @@
 use std::sync::Arc;
 
-use crate::domain::{
-    data_providers::data_provider_abstract::DataProviderAbstract,
-    entities::{errors::ServiceError, token::Token},
-    repositories::data_repository_abstract::{
-        CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
-        TokenRepositoryAbstract, UserRepositoryAbstract,
+use crate::{
+    domain::{
+        data_providers::data_provider_abstract::DataProviderAbstract,
+        entities::{
+            errors::ServiceError,
+            lease::LEASE_DURATION,
+            registration::Registration,
+            token::{generate_feed_token, Token},
+        },
+        repositories::data_repository_abstract::{
+            CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
+            TokenRepositoryAbstract, UserRepositoryAbstract,
+        },
+    },
+    infrastructure::{
+        data_providers::errors::{MoodleError, ResponseError},
+        repositories::errors::DbError,
     },
 };
 
@@ -62,28 +73,107 @@ where
 
     pub async fn delete_one_user(&self, token: &str) -> Result<(), ServiceError> {
         self.token_repository.delete(token).await?;
+        self.data_provider.forget_tenant(token);
+        Ok(())
+    }
+
+    pub async fn create_feed_token(&self, token: &str) -> Result<String, ServiceError> {
+        let feed_token = generate_feed_token();
+        self.token_repository
+            .save_feed_token(token, &feed_token)
+            .await?;
+        Ok(feed_token)
+    }
+
+    pub async fn revoke_feed_token(&self, token: &str) -> Result<(), ServiceError> {
+        self.token_repository.delete_feed_token(token).await?;
         Ok(())
     }
 
-    pub async fn find_all_tokens(
+    pub async fn find_token_by_feed_token(&self, feed_token: &str) -> Result<String, ServiceError> {
+        let token = self
+            .token_repository
+            .find_token_by_feed_token(feed_token)
+            .await?;
+        Ok(token)
+    }
+
+    /// Leases the users due at `now` to `worker` for [`LEASE_DURATION`].
+    pub async fn claim_due_tokens(
         &self,
+        worker: &str,
+        now: i64,
         limit: i64,
-        skip: &mut u64,
     ) -> Result<Vec<Token>, ServiceError> {
-        match self
+        let tokens = self
             .token_repository
-            .find_all_device_tokens(limit, *skip)
-            .await
-        {
-            Ok(batch) => {
-                *skip = batch.len() as u64;
-                Ok(batch)
-            }
-            Err(e) => {
-                *skip = 0;
-                Err(ServiceError::DataNotFound(e.to_string()))
-            }
+            .claim_due_tokens(worker, now, now + LEASE_DURATION, limit)
+            .await?;
+        Ok(tokens)
+    }
+
+    pub async fn renew_lease(
+        &self,
+        token: &str,
+        worker: &str,
+        now: i64,
+    ) -> Result<bool, ServiceError> {
+        let renewed = self
+            .token_repository
+            .renew_lease(token, worker, now + LEASE_DURATION)
+            .await?;
+        Ok(renewed)
+    }
+
+    pub async fn schedule_poll(
+        &self,
+        token: &str,
+        worker: &str,
+        next_poll_at: i64,
+    ) -> Result<(), ServiceError> {
+        self.token_repository
+            .schedule_poll(token, worker, next_poll_at)
+            .await?;
+        Ok(())
+    }
+
+    /// Suspends the user once Moodle rejects the token on a fresh check as well, a single
+    /// failed call may come from one misbehaving function. True when newly suspended.
+    pub async fn suspend_if_revoked(&self, token: &str, now: i64) -> Result<bool, ServiceError> {
+        match self.data_provider.valid_token(token).await {
+            Err(ResponseError::Moodle(MoodleError::InvalidToken)) => {}
+            _ => return Ok(false),
         }
+        let suspended = self.token_repository.suspend(token, now).await?;
+        Ok(suspended)
+    }
+
+    /// Moves a user, suspended or not, to a new Moodle token, keeping all stored data.
+    /// The new token must belong to the same Moodle account and is routed to the old one's tenant.
+    pub async fn relink(&self, old: &str, new: &str) -> Result<(), ServiceError> {
+        let stored = self
+            .user_service
+            .user_repository
+            .find_user_by_token(old)
+            .await?;
+        let tenant = self.data_provider.tenant_id(old).await?;
+        self.data_provider.assign_tenant(new, tenant).await?;
+
+        if let Err(e) = self.move_user(old, new, stored.userid).await {
+            self.data_provider.forget_tenant(new);
+            return Err(e);
+        }
+        self.data_provider.forget_tenant(old);
+        Ok(())
+    }
+
+    async fn move_user(&self, old: &str, new: &str, userid: i64) -> Result<(), ServiceError> {
+        let user = self.data_provider.get_user(new).await?;
+        if user.userid != userid {
+            return Err(ServiceError::AccountMismatch);
+        }
+        self.token_repository.relink(old, new).await?;
+        Ok(())
     }
 
     pub async fn fetch_and_update_data(&self, token: &str) -> Result<(), ServiceError> {
@@ -101,9 +191,38 @@ where
         Ok(())
     }
 
+    /// Safe to retry: nothing is stored unless every write succeeds, and a token left
+    /// without a user by an older, interrupted registration can register again. The token is
+    /// routed to its tenant first, the routing is dropped again when the registration fails.
     pub async fn register_user(&self, tokens: &Token) -> Result<(), ServiceError> {
+        let tenant = self
+            .data_provider
+            .assign_tenant(&tokens.token, tokens.tenant.clone())
+            .await?;
+        let tokens = Token {
+            tenant,
+            ..tokens.clone()
+        };
+
+        if let Err(e) = self.register(&tokens).await {
+            self.data_provider.forget_tenant(&tokens.token);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    async fn register(&self, tokens: &Token) -> Result<(), ServiceError> {
         self.data_provider.valid_token(&tokens.token).await?;
-        self.token_repository.find_token(tokens).await?;
+        match self
+            .user_service
+            .user_repository
+            .find_user_by_token(&tokens.token)
+            .await
+        {
+            Ok(_) => return Err(ServiceError::UserAlreadyExists(tokens.token.to_owned())),
+            Err(DbError::DataNotFound(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
 
         let user = self.data_provider.get_user(&tokens.token).await?;
         let courses = self
@@ -123,33 +242,234 @@ where
             .fetch_grades_overview(&tokens.token, &courses)
             .await?;
 
-        self.token_repository.save_tokens(tokens).await?;
-
-        self.user_service
-            .user_repository
-            .save_user(&user, &tokens.token)
+        let registration = Registration {
+            token: tokens.clone(),
+            user,
+            courses,
+            grades,
+            grades_overview,
+            deadlines,
+        };
+        self.token_repository
+            .save_registration(&registration)
             .await?;
 
-        self.course_service
-            .course_repository
-            .save_courses(&tokens.token, &courses)
-            .await?;
+        Ok(())
+    }
+}
 
-        self.grade_service
-            .grade_repository
-            .save_grades(&tokens.token, &grades)
-            .await?;
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
 
-        self.grade_service
-            .grade_repository
-            .save_grades_overview(&tokens.token, &grades_overview)
-            .await?;
+    use chrono::Utc;
+    use mockall::predicate::eq;
 
-        self.deadline_service
-            .deadline_repository
-            .save_deadlines(&tokens.token, &deadlines)
-            .await?;
+    use super::*;
+    use crate::{
+        domain::{
+            data_providers::data_provider_abstract::MockDataProviderAbstract,
+            entities::{
+                course::Course,
+                deadline::{Deadline, Events},
+                grade::GradesOverview,
+                user::User,
+            },
+            repositories::data_repository_abstract::{
+                CourseRepositoryAbstract, DeadlineRepositoryAbstract, MockUserRepositoryAbstract,
+            },
+        },
+        infrastructure::repositories::in_memory_repository::InMemoryRepository,
+    };
 
-        Ok(())
+    type Service<UserRepo> = TokenService<
+        MockDataProviderAbstract,
+        InMemoryRepository,
+        UserRepo,
+        InMemoryRepository,
+        InMemoryRepository,
+        InMemoryRepository,
+    >;
+
+    const TOKEN: &str = "token";
+
+    fn service<UserRepo: UserRepositoryAbstract>(
+        data_provider: MockDataProviderAbstract,
+        repository: Arc<InMemoryRepository>,
+        user_repository: Arc<UserRepo>,
+    ) -> Service<UserRepo> {
+        let data_provider = Arc::new(data_provider);
+        TokenService::new(
+            Arc::clone(&data_provider),
+            Arc::clone(&repository),
+            Arc::new(UserService::new(
+                Arc::clone(&data_provider),
+                user_repository,
+            )),
+            Arc::new(CourseService::new(
+                Arc::clone(&data_provider),
+                Arc::clone(&repository),
+            )),
+            Arc::new(GradeService::new(
+                Arc::clone(&data_provider),
+                Arc::clone(&repository),
+            )),
+            Arc::new(DeadlineService::new(data_provider, repository)),
+        )
+    }
+
+    fn user(userid: i64) -> User {
+        User {
+            username: "student".to_owned(),
+            fullname: "Student".to_owned(),
+            userid,
+        }
+    }
+
+    /// Routing of a single LMS, every token keeps the tenant it asks for.
+    fn routed(data_provider: &mut MockDataProviderAbstract) {
+        data_provider
+            .expect_assign_tenant()
+            .returning(|_, tenant| Ok(tenant));
+        data_provider.expect_tenant_id().returning(|_| Ok(None));
+    }
+
+    fn moodle(userid: i64) -> MockDataProviderAbstract {
+        let mut data_provider = MockDataProviderAbstract::new();
+        routed(&mut data_provider);
+        data_provider.expect_forget_tenant().return_const(());
+        data_provider.expect_valid_token().returning(|_| Ok(()));
+        data_provider
+            .expect_get_user()
+            .returning(move |_| Ok(user(userid)));
+        data_provider.expect_get_courses().returning(|_, _| {
+            Ok(vec![Course {
+                id: 1,
+                fullname: "Math".to_owned(),
+                enddate: 0,
+            }])
+        });
+        data_provider
+            .expect_get_grades_by_courses()
+            .returning(|_, _, _| Ok(HashMap::new()));
+        data_provider
+            .expect_get_deadlines_by_courses()
+            .returning(|_, _| {
+                let quiz = Deadline {
+                    id: 7,
+                    name: "Quiz".to_owned(),
+                    timeusermidnight: Utc::now().timestamp() + 86_400,
+                    formattedtime: "10:00".to_owned(),
+                    coursename: None,
+                    courseid: None,
+                };
+                Ok(HashMap::from([(1, Events { events: vec![quiz] })]))
+            });
+        data_provider
+            .expect_get_grades_overview()
+            .returning(|_| Ok(GradesOverview { grades: Vec::new() }));
+        data_provider
+    }
+
+    #[tokio::test]
+    async fn test_register_user_stores_everything_once() {
+        let repository = Arc::new(InMemoryRepository::new());
+        let service = service(moodle(1), Arc::clone(&repository), Arc::clone(&repository));
+        let tokens = Token::new(TOKEN.to_owned(), None);
+
+        service.register_user(&tokens).await.unwrap();
+
+        assert_eq!(
+            repository.find_user_by_token(TOKEN).await.unwrap().userid,
+            1
+        );
+        assert_eq!(
+            repository.find_courses_by_token(TOKEN).await.unwrap().len(),
+            1
+        );
+        let deadlines = repository.find_deadlines_by_token(TOKEN).await.unwrap();
+        assert_eq!(deadlines[0].coursename.as_deref(), Some("Math"));
+        assert!(matches!(
+            service.register_user(&tokens).await,
+            Err(ServiceError::UserAlreadyExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_register_user_fails_on_storage_errors() {
+        let mut data_provider = MockDataProviderAbstract::new();
+        routed(&mut data_provider);
+        data_provider.expect_valid_token().returning(|_| Ok(()));
+        data_provider.expect_get_user().never();
+        data_provider
+            .expect_forget_tenant()
+            .with(eq(TOKEN))
+            .times(1)
+            .return_const(());
+        let mut user_repository = MockUserRepositoryAbstract::new();
+        user_repository
+            .expect_find_user_by_token()
+            .with(eq(TOKEN))
+            .returning(|_| Err(DbError::Storage("connection reset".to_owned())));
+        let service = service(
+            data_provider,
+            Arc::new(InMemoryRepository::new()),
+            Arc::new(user_repository),
+        );
+
+        let result = service
+            .register_user(&Token::new(TOKEN.to_owned(), None))
+            .await;
+
+        assert!(matches!(result, Err(ServiceError::InternalServerError)));
+    }
+
+    #[tokio::test]
+    async fn test_suspend_if_revoked_needs_an_invalid_token() {
+        let repository = Arc::new(InMemoryRepository::new());
+        repository
+            .save_tokens(&Token::new(TOKEN.to_owned(), None))
+            .await
+            .unwrap();
+        let mut data_provider = MockDataProviderAbstract::new();
+        let mut calls = 0;
+        data_provider.expect_valid_token().returning(move |_| {
+            calls += 1;
+            if calls == 1 {
+                Err(ResponseError::Moodle(MoodleError::Maintenance))
+            } else {
+                Err(ResponseError::Moodle(MoodleError::InvalidToken))
+            }
+        });
+        let service = service(data_provider, Arc::clone(&repository), repository);
+
+        assert!(!service.suspend_if_revoked(TOKEN, 100).await.unwrap());
+        assert!(service.suspend_if_revoked(TOKEN, 100).await.unwrap());
+        assert!(!service.suspend_if_revoked(TOKEN, 200).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_relink_rejects_another_account() {
+        let repository = Arc::new(InMemoryRepository::new());
+        let registered = service(moodle(1), Arc::clone(&repository), Arc::clone(&repository));
+        registered
+            .register_user(&Token::new(TOKEN.to_owned(), None))
+            .await
+            .unwrap();
+        let mut other_account = MockDataProviderAbstract::new();
+        routed(&mut other_account);
+        other_account.expect_get_user().returning(|_| Ok(user(2)));
+        // Only the new token loses its routing, the user stays where it was.
+        other_account
+            .expect_forget_tenant()
+            .with(eq("new"))
+            .times(1)
+            .return_const(());
+        let service = service(other_account, Arc::clone(&repository), repository);
+
+        assert!(matches!(
+            service.relink(TOKEN, "new").await,
+            Err(ServiceError::AccountMismatch)
+        ));
     }
 }
@@ -1,40 +1,58 @@
 use core::time;
-use std::{error::Error, sync::Arc};
+use std::{error::Error, num::NonZeroUsize, sync::Arc, time::Duration};
 
 use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer};
 use fcm_rs::client::FcmClient;
-use log::{info, warn};
+use log::{error, info, warn};
 
 use crate::{
-    config::Config,
+    config::{CacheBackend, Config, StorageBackend},
     domain::{
         data_providers::{
             data_provider_abstract::DataProviderAbstract,
+            live_update_bus_abstract::LiveUpdateBusAbstract,
             notification_provider_abstract::NotificationProviderAbstract,
         },
+        entities::poll_schedule::PollSchedule,
         repositories::data_repository_abstract::{
             CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
             TokenRepositoryAbstract, UserRepositoryAbstract,
         },
         services::{
             course_service::CourseService, deadline_service::DeadlineService,
-            grade_service::GradeService, notification_service::NotificationService,
-            token_service::TokenService, user_service::UserService,
+            grade_service::GradeService, live_update_service::LiveUpdateService,
+            notification_service::NotificationService, token_service::TokenService,
+            user_service::UserService,
         },
     },
     presentation::{
         handlers::{
-            course_handler::course_routes, deadline_handler::deadline_routes,
-            grade_handler::grade_routes, user_handler::user_routes,
+            admin_handler::{admin_routes, NotificationInbox},
+            course_handler::course_routes,
+            deadline_handler::deadline_routes,
+            grade_handler::grade_routes,
+            stream_handler::stream_routes,
+            user_handler::user_routes,
+            v1::v1_routes,
         },
         shared::app_state::AppState,
     },
 };
 
 use super::{
-    data_providers::moodle_client::MoodleClient, db::connection::connect,
+    cache::{cache_abstract::CacheAbstract, lru_cache::LruCache, redis_cache::RedisCache},
+    data_providers::tenant_router::TenantRouter,
+    db::connection::connect,
+    live_updates::redis_live_update_bus::RedisLiveUpdateBus,
     notification_provider::firebase_messages_client::FirebaseMessagesClient,
-    repositories::data_repository::DataRepository,
+    repositories::{
+        cached_repository::CachedRepository,
+        data_repository::DataRepository,
+        in_memory_repository::InMemoryRepository,
+        postgres_repository::PostgresRepository,
+        sqlite_repository::SqliteRepository,
+        storage::{CachedStorage, Storage},
+    },
 };
 
 pub struct AppDependencies<
@@ -60,42 +78,168 @@ pub struct AppDependencies<
     pub course_service: Arc<CourseService<DataProvider, CourseRepo>>,
     pub grade_service: Arc<GradeService<DataProvider, GradeRepo>>,
     pub deadline_service: Arc<DeadlineService<DataProvider, DeadlineRepo>>,
-    pub notification_service: NotificationService<
-        NotificationProvider,
-        DataProvider,
-        TokenRepo,
-        UserRepo,
-        CourseRepo,
-        GradeRepo,
-        DeadlineRepo,
+    pub notification_service: Arc<
+        NotificationService<
+            NotificationProvider,
+            DataProvider,
+            TokenRepo,
+            UserRepo,
+            CourseRepo,
+            GradeRepo,
+            DeadlineRepo,
+        >,
     >,
     pub app_state:
         web::Data<AppState<DataProvider, TokenRepo, UserRepo, CourseRepo, GradeRepo, DeadlineRepo>>,
 }
 
+async fn connect_storage(config: &Config) -> Result<Storage, Box<dyn Error>> {
+    match config.storage {
+        StorageBackend::Mongo => {
+            let db = connect(config.mongo_uri()?).await?;
+            let data_repository = DataRepository::new(&db);
+            data_repository.require_transactions().await?;
+            data_repository.create_indexes().await?;
+            // Reads only see the per-entry collections, users still holding embedded arrays
+            // would look empty and have everything announced again.
+            let converted = data_repository.split_embedded_arrays().await?;
+            if converted > 0 {
+                info!(
+                    "Moved embedded arrays of {} users to their collections",
+                    converted
+                );
+            }
+
+            let migrating = data_repository.clone();
+            tokio::spawn(async move {
+                match migrating.migrate_documents().await {
+                    Ok(upgraded) => info!("Upgraded {} documents to the current schema", upgraded),
+                    Err(e) => error!("Schema migration failed: {}", e),
+                }
+            });
+            Ok(Storage::Mongo(data_repository))
+        }
+        StorageBackend::Memory => {
+            warn!("Using in-memory storage, all data is lost on restart");
+            Ok(Storage::InMemory(InMemoryRepository::new()))
+        }
+        StorageBackend::Postgres => {
+            let repository = PostgresRepository::connect(config.database_url()?).await?;
+            repository.run_migrations().await?;
+            Ok(Storage::Postgres(repository))
+        }
+        StorageBackend::Sqlite => {
+            let repository = SqliteRepository::connect(config.database_url()?).await?;
+            repository.run_migrations().await?;
+            Ok(Storage::Sqlite(repository))
+        }
+    }
+}
+
+const CACHE_CAPACITY: usize = 10_000;
+
+async fn connect_cache(config: &Config) -> Result<Option<Arc<dyn CacheAbstract>>, Box<dyn Error>> {
+    let ttl = Duration::from_secs(config.cache_ttl_secs);
+    match config.cache {
+        CacheBackend::None => Ok(None),
+        CacheBackend::Memory => {
+            let capacity = NonZeroUsize::new(CACHE_CAPACITY).ok_or("Invalid cache capacity")?;
+            Ok(Some(Arc::new(LruCache::new(capacity, ttl))))
+        }
+        CacheBackend::Redis => {
+            let redis_url = config
+                .redis_url
+                .as_deref()
+                .ok_or("REDIS_URL must be set for CACHE=redis")?;
+            Ok(Some(Arc::new(RedisCache::connect(redis_url, ttl).await?)))
+        }
+    }
+}
+
+/// How often listeners of users nobody streams here anymore are dropped from the bus.
+const LIVE_UPDATE_PRUNE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Live updates go through Redis once `REDIS_URL` is given, so every instance streams the
+/// events of users polled by another. Without it they stay in this process.
+async fn connect_live_updates(config: &Config) -> Result<Arc<LiveUpdateService>, Box<dyn Error>> {
+    let Some(redis_url) = config.redis_url.as_deref() else {
+        return Ok(Arc::new(LiveUpdateService::new()));
+    };
+    let bus = Arc::new(
+        RedisLiveUpdateBus::connect(redis_url)
+            .await
+            .map_err(|e| format!("Failed to connect the live update bus: {}", e))?,
+    );
+    let live_updates = Arc::new(LiveUpdateService::with_bus(
+        Arc::clone(&bus) as Arc<dyn LiveUpdateBusAbstract>
+    ));
+
+    let delivering = Arc::clone(&live_updates);
+    tokio::spawn(async move {
+        bus.run(|channel, event| delivering.deliver(channel, event))
+            .await
+    });
+    let pruning = Arc::clone(&live_updates);
+    tokio::spawn(async move {
+        let mut prune = tokio::time::interval(LIVE_UPDATE_PRUNE_INTERVAL);
+        loop {
+            prune.tick().await;
+            pruning.prune().await;
+        }
+    });
+    Ok(live_updates)
+}
+
 pub async fn initialize_dependencies(
     config: &Config,
 ) -> Result<
     AppDependencies<
         FirebaseMessagesClient,
-        MoodleClient,
-        DataRepository,
-        DataRepository,
-        DataRepository,
-        DataRepository,
-        DataRepository,
+        TenantRouter,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
     >,
     Box<dyn std::error::Error>,
 > {
-    // Initialize Moodle client
-    let moodle_client = Arc::new(MoodleClient::new(
-        config.base_url.clone(),
-        config.format_url.clone(),
-    ));
+    let fcm_client = FcmClient::new("service_account_key.json").await?;
+    let notification_provider = Arc::new(FirebaseMessagesClient::new(fcm_client));
+    build_dependencies(config, notification_provider).await
+}
 
+/// Wires the services around `notification_provider`, tests pass one that records pushes.
+pub async fn build_dependencies<NotificationProvider>(
+    config: &Config,
+    notification_provider: Arc<NotificationProvider>,
+) -> Result<
+    AppDependencies<
+        NotificationProvider,
+        TenantRouter,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+    >,
+    Box<dyn std::error::Error>,
+>
+where
+    NotificationProvider: NotificationProviderAbstract,
+{
     // Initialize database
-    let db = connect(&config.mongo_uri).await?.collection("users");
-    let data_repository = Arc::new(DataRepository::new(db));
+    let data_repository = Arc::new(CachedRepository::new(
+        connect_storage(config).await?,
+        connect_cache(config).await?,
+    ));
+
+    // Initialize Moodle clients, one per tenant
+    let moodle_client = Arc::new(TenantRouter::new(
+        config.tenants.clone(),
+        &config.moodle_limits,
+        Arc::clone(&data_repository) as Arc<dyn TokenRepositoryAbstract>,
+    )?);
 
     // Initialize services
     let user_service = Arc::new(UserService::new(
@@ -127,18 +271,20 @@ pub async fn initialize_dependencies(
         Arc::clone(&deadline_service),
     ));
 
-    let fcm_client = FcmClient::new("service_account_key.json").await?;
-    let notification_provider = Arc::new(FirebaseMessagesClient::new(fcm_client));
+    let live_updates = connect_live_updates(config).await?;
 
-    let notification_service = NotificationService::new(
+    let notification_service = Arc::new(NotificationService::new(
         notification_provider,
-        moodle_client,
+        Arc::clone(&moodle_client),
         Arc::clone(&token_service),
         Arc::clone(&user_service),
         Arc::clone(&course_service),
         Arc::clone(&grade_service),
         Arc::clone(&deadline_service),
-    );
+        Arc::clone(&live_updates),
+        PollSchedule::new(config.poll_interval_secs),
+        config.worker_id.clone(),
+    ));
 
     let app_state = AppState::new(
         Arc::clone(&token_service),
@@ -146,6 +292,8 @@ pub async fn initialize_dependencies(
         Arc::clone(&course_service),
         Arc::clone(&grade_service),
         Arc::clone(&deadline_service),
+        live_updates,
+        moodle_client,
     );
 
     Ok(AppDependencies {
@@ -159,33 +307,77 @@ pub async fn initialize_dependencies(
     })
 }
 
-pub async fn spawn_notification_worker(
-    notification_service: &'static NotificationService<
-        FirebaseMessagesClient,
-        MoodleClient,
-        DataRepository,
-        DataRepository,
-        DataRepository,
-        DataRepository,
-        DataRepository,
+/// Brings the configured storage to the current layout: SQL migrations for Postgres and SQLite,
+/// see `DataRepository::split_embedded_arrays` and `DataRepository::migrate_documents` for MongoDB.
+pub async fn migrate_database(config: &Config) -> Result<(), Box<dyn Error>> {
+    match config.storage {
+        StorageBackend::Mongo => {
+            let db = connect(config.mongo_uri()?).await?;
+            let data_repository = DataRepository::new(&db);
+            data_repository.create_indexes().await?;
+
+            let converted = data_repository.split_embedded_arrays().await?;
+            info!(
+                "Moved embedded arrays of {} users to their collections",
+                converted
+            );
+            let upgraded = data_repository.migrate_documents().await?;
+            info!("Upgraded {} documents to the current schema", upgraded);
+        }
+        StorageBackend::Postgres => {
+            let repository = PostgresRepository::connect(config.database_url()?).await?;
+            repository.run_migrations().await?;
+            info!("Postgres migrations applied");
+        }
+        StorageBackend::Sqlite => {
+            let repository = SqliteRepository::connect(config.database_url()?).await?;
+            repository.run_migrations().await?;
+            info!("SQLite migrations applied");
+        }
+        StorageBackend::Memory => info!("In-memory storage has nothing to migrate"),
+    }
+    Ok(())
+}
+
+/// How long the notification worker waits when no user is due.
+const IDLE_WAIT: Duration = Duration::from_secs(5);
+
+pub async fn spawn_notification_worker<NotificationProvider>(
+    notification_service: Arc<
+        NotificationService<
+            NotificationProvider,
+            TenantRouter,
+            CachedStorage,
+            CachedStorage,
+            CachedStorage,
+            CachedStorage,
+            CachedStorage,
+        >,
     >,
     batch_size: i64,
-) {
+) where
+    NotificationProvider: NotificationProviderAbstract + 'static,
+{
+    info!(
+        "Notification worker {} started",
+        notification_service.worker_id()
+    );
     tokio::spawn(async move {
-        let mut skip = 0;
         loop {
-            if let Err(e) = notification_service
-                .get_batches(batch_size, &mut skip)
-                .await
-            {
-                warn!("Warning in notification worker: {}", e);
+            match notification_service.poll_due(batch_size).await {
+                Ok(0) => tokio::time::sleep(IDLE_WAIT).await,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Warning in notification worker: {}", e);
+                    tokio::time::sleep(IDLE_WAIT).await;
+                }
             }
         }
     });
 }
 
 pub async fn spawn_deadline_cleaner_worker(
-    deadline_service: Arc<DeadlineService<MoodleClient, DataRepository>>,
+    deadline_service: Arc<DeadlineService<TenantRouter, CachedStorage>>,
 ) {
     tokio::spawn(async move {
         loop {
@@ -201,33 +393,46 @@ pub async fn spawn_deadline_cleaner_worker(
     });
 }
 
+/// Serves the API, plus the admin routes when `inbox` is given.
 pub async fn server(
     app_state: web::Data<
         AppState<
-            MoodleClient,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
+            TenantRouter,
+            CachedStorage,
+            CachedStorage,
+            CachedStorage,
+            CachedStorage,
+            CachedStorage,
         >,
     >,
+    inbox: Option<web::Data<NotificationInbox>>,
     port: &str,
 ) -> Result<(), Box<dyn Error>> {
     let address = format!("0.0.0.0:{}", port);
 
     HttpServer::new(move || {
-        App::new()
+        let app = App::new()
             .wrap(Logger::default())
             .app_data(app_state.clone())
-            .configure(user_routes)
-            .configure(course_routes)
-            .configure(grade_routes)
-            .configure(deadline_routes)
-            .default_service(web::to(HttpResponse::MethodNotAllowed))
+            .configure(routes);
+        let app = match &inbox {
+            Some(inbox) => app.app_data(inbox.clone()).configure(admin_routes),
+            None => app,
+        };
+        app.default_service(web::to(HttpResponse::MethodNotAllowed))
     })
     .bind(address)?
     .run()
     .await?;
     Ok(())
 }
+
+/// Every route the server answers, expects the `AppState` as app data.
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.configure(v1_routes)
+        .configure(stream_routes)
+        .configure(user_routes)
+        .configure(course_routes)
+        .configure(grade_routes)
+        .configure(deadline_routes);
+}
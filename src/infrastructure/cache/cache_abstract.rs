@@ -0,0 +1,14 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+
+use super::errors::CacheError;
+
+/// String key-value store with expiring entries, values are serialized JSON.
+#[async_trait]
+pub trait CacheAbstract: Send + Sync + Debug {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError>;
+    async fn set(&self, key: &str, value: String) -> Result<(), CacheError>;
+    async fn delete(&self, keys: &[String]) -> Result<(), CacheError>;
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), CacheError>;
+}
@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("Redis error: `{0}`")]
+    Redis(#[from] redis::RedisError),
+
+    #[error("Cache lock poisoned")]
+    Poisoned,
+}
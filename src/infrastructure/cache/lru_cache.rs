@@ -0,0 +1,99 @@
+use std::{
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use lru::LruCache as Lru;
+
+use super::{cache_abstract::CacheAbstract, errors::CacheError};
+
+/// In-process cache, only coherent with writes made by this instance.
+#[derive(Debug)]
+pub struct LruCache {
+    entries: Mutex<Lru<String, (Instant, String)>>,
+    ttl: Duration,
+}
+
+impl LruCache {
+    pub fn new(capacity: NonZeroUsize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(Lru::new(capacity)),
+            ttl,
+        }
+    }
+}
+
+#[async_trait]
+impl CacheAbstract for LruCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        let mut entries = self.entries.lock().map_err(|_| CacheError::Poisoned)?;
+        match entries.get(key) {
+            Some((expires_at, value)) if *expires_at > Instant::now() => Ok(Some(value.clone())),
+            Some(_) => {
+                entries.pop(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, value: String) -> Result<(), CacheError> {
+        let mut entries = self.entries.lock().map_err(|_| CacheError::Poisoned)?;
+        entries.put(key.to_owned(), (Instant::now() + self.ttl, value));
+        Ok(())
+    }
+
+    async fn delete(&self, keys: &[String]) -> Result<(), CacheError> {
+        let mut entries = self.entries.lock().map_err(|_| CacheError::Poisoned)?;
+        for key in keys {
+            entries.pop(key);
+        }
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), CacheError> {
+        let mut entries = self.entries.lock().map_err(|_| CacheError::Poisoned)?;
+        let keys: Vec<String> = entries
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        for key in keys {
+            entries.pop(&key);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_entries_expire_and_evict() {
+        let cache = LruCache::new(NonZeroUsize::new(2).unwrap(), Duration::from_secs(60));
+        cache.set("a", "1".to_string()).await.unwrap();
+        cache.set("b", "2".to_string()).await.unwrap();
+        cache.set("c", "3".to_string()).await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), None);
+        assert_eq!(cache.get("c").await.unwrap().as_deref(), Some("3"));
+
+        let cache = LruCache::new(NonZeroUsize::new(2).unwrap(), Duration::ZERO);
+        cache.set("a", "1".to_string()).await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_delete_prefix() {
+        let cache = LruCache::new(NonZeroUsize::new(8).unwrap(), Duration::from_secs(60));
+        cache.set("deadlines:a", "[]".to_string()).await.unwrap();
+        cache.set("courses:a", "[]".to_string()).await.unwrap();
+
+        cache.delete_prefix("deadlines:").await.unwrap();
+        assert_eq!(cache.get("deadlines:a").await.unwrap(), None);
+        assert!(cache.get("courses:a").await.unwrap().is_some());
+    }
+}
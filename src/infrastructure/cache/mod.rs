@@ -0,0 +1,4 @@
+pub mod cache_abstract;
+pub mod errors;
+pub mod lru_cache;
+pub mod redis_cache;
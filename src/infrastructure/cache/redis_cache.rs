@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use super::{cache_abstract::CacheAbstract, errors::CacheError};
+
+const NAMESPACE: &str = "aitu-keeper:";
+const SCAN_BATCH: usize = 500;
+
+/// Shared cache for several server instances.
+#[derive(Clone)]
+pub struct RedisCache {
+    connection: ConnectionManager,
+    ttl: Duration,
+}
+
+impl std::fmt::Debug for RedisCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisCache")
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl RedisCache {
+    pub async fn connect(url: &str, ttl: Duration) -> Result<Self, CacheError> {
+        let client = redis::Client::open(url)?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(Self { connection, ttl })
+    }
+}
+
+#[async_trait]
+impl CacheAbstract for RedisCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        let mut connection = self.connection.clone();
+        Ok(connection.get(format!("{}{}", NAMESPACE, key)).await?)
+    }
+
+    async fn set(&self, key: &str, value: String) -> Result<(), CacheError> {
+        let mut connection = self.connection.clone();
+        let _: () = connection
+            .set_ex(format!("{}{}", NAMESPACE, key), value, self.ttl.as_secs())
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, keys: &[String]) -> Result<(), CacheError> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let mut connection = self.connection.clone();
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|key| format!("{}{}", NAMESPACE, key))
+            .collect();
+        let _: () = connection.del(keys).await?;
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), CacheError> {
+        let mut connection = self.connection.clone();
+        let pattern = format!("{}{}*", NAMESPACE, prefix);
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_BATCH)
+                .query_async(&mut connection)
+                .await?;
+            if !keys.is_empty() {
+                let _: () = connection.del(keys).await?;
+            }
+            if next == 0 {
+                return Ok(());
+            }
+            cursor = next;
+        }
+    }
+}
@@ -0,0 +1,731 @@
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use chrono_tz::Tz;
+use futures::future::try_join_all;
+use log::warn;
+use reqwest::{header::LINK, Client, Response, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{collections::HashMap, time::Duration};
+
+use crate::domain::{
+    data_providers::data_provider_abstract::DataProviderAbstract,
+    entities::{
+        course::{Course, CourseUpdates, InstanceUpdate, UpdatedInstance},
+        deadline::{Deadline, Events},
+        grade::{Grade, GradeItems, GradeOverview, GradesOverview, UserGrades},
+        sync_cursor::SyncCursor,
+        user::User,
+    },
+};
+
+use super::{
+    errors::{MoodleError, ResponseError},
+    moodle_client::{is_overloaded, retry_after},
+    request_limiter::{RequestLimiter, RequestLimits, DEFAULT_BACKOFF},
+};
+
+/// Largest page Canvas serves, longer lists are followed through the `Link` header.
+const PER_PAGE: usize = 100;
+
+/// Most `context_codes` the calendar events endpoint takes, further ones are ignored.
+const CONTEXTS_PER_REQUEST: usize = 10;
+
+/// How far ahead deadlines are fetched, Canvas only returns today's events by default.
+const DEADLINE_HORIZON_DAYS: i64 = 365;
+
+/// Canvas LMS REST API, mapped onto the Moodle-shaped domain entities.
+///
+/// Canvas answers a rejected token with 401 and a missing permission with 403, they are
+/// reported as the matching [`MoodleError`] so suspension works the same for both.
+#[derive(Debug)]
+pub struct CanvasClient {
+    client: Client,
+    /// Instance root, e.g. `https://school.instructure.com`.
+    base_url: String,
+    /// Tenant time zone due times are shown in, Canvas reports them in UTC.
+    timezone: Tz,
+    limiter: RequestLimiter,
+}
+
+impl CanvasClient {
+    pub fn new(base_url: String, timezone: Tz, limits: &RequestLimits) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(Duration::from_secs(15))
+                .build()
+                .unwrap(),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            timezone,
+            limiter: RequestLimiter::new(limits),
+        }
+    }
+
+    /// GETs `path` of the API, `endpoint` names it for the rate limits.
+    async fn get<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        token: &str,
+        path: &str,
+    ) -> Result<T, ResponseError> {
+        let url = format!("{}/api/v1/{}", self.base_url, path);
+        let (body, _) = self.send_request(endpoint, token, &url).await?;
+        decode(&body)
+    }
+
+    /// GETs every page of a list.
+    async fn get_all<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        token: &str,
+        path: &str,
+    ) -> Result<Vec<T>, ResponseError> {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let mut url = Some(format!(
+            "{}/api/v1/{}{}per_page={}",
+            self.base_url, path, separator, PER_PAGE
+        ));
+        let mut items = Vec::new();
+        while let Some(current) = url {
+            let (body, next) = self.send_request(endpoint, token, &current).await?;
+            items.extend(decode::<Vec<T>>(&body)?);
+            // The token goes along with every page, it must not leave the instance.
+            if let Some(next) = &next {
+                if !same_origin(&self.base_url, next) {
+                    return Err(ResponseError::Decode(format!(
+                        "Next page of `{}` is on another origin: `{}`",
+                        endpoint, next
+                    )));
+                }
+            }
+            url = next;
+        }
+        Ok(items)
+    }
+
+    /// Body and next page link of a successful response.
+    async fn send_request(
+        &self,
+        endpoint: &str,
+        token: &str,
+        url: &str,
+    ) -> Result<(String, Option<String>), ResponseError> {
+        let mut attempt = 0;
+        loop {
+            let permit = self.limiter.acquire(endpoint).await;
+            let response = self.client.get(url).bearer_auth(token).send().await;
+            drop(permit);
+            match response {
+                Ok(resp) if is_overloaded(resp.status()) => {
+                    let wait = retry_after(&resp).unwrap_or(DEFAULT_BACKOFF);
+                    warn!("Canvas is overloaded, pausing requests for {:?}", wait);
+                    self.limiter.pause(wait);
+                    if attempt >= 2 {
+                        return Err(ResponseError::Overloaded(endpoint.to_owned()));
+                    }
+                    attempt += 1;
+                    continue;
+                }
+                Ok(resp) => return read_response(resp).await,
+                Err(e) => {
+                    if attempt >= 2 {
+                        return Err(e.into());
+                    }
+                }
+            }
+            attempt += 1;
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+
+    async fn assignments(
+        &self,
+        token: &str,
+        course_id: i64,
+    ) -> Result<Vec<CanvasAssignment>, ResponseError> {
+        self.get_all(
+            "assignments",
+            token,
+            &format!(
+                "courses/{}/assignments?include[]=submission&order_by=due_at",
+                course_id
+            ),
+        )
+        .await
+    }
+}
+
+async fn read_response(response: Response) -> Result<(String, Option<String>), ResponseError> {
+    let status = response.status();
+    let next = response
+        .headers()
+        .get(LINK)
+        .and_then(|link| link.to_str().ok())
+        .and_then(next_link);
+    let body = response.text().await?;
+    match status {
+        StatusCode::UNAUTHORIZED => Err(MoodleError::InvalidToken.into()),
+        StatusCode::FORBIDDEN => Err(MoodleError::AccessDenied(body).into()),
+        StatusCode::NOT_FOUND => Err(MoodleError::NotFound(body).into()),
+        status if !status.is_success() => Err(MoodleError::Other {
+            errorcode: status.as_u16().to_string(),
+            message: body,
+        }
+        .into()),
+        _ if body.is_empty() => Err(ResponseError::EmptyBody(
+            "Empty response from Canvas".to_owned(),
+        )),
+        _ => Ok((body, next)),
+    }
+}
+
+fn decode<T: DeserializeOwned>(body: &str) -> Result<T, ResponseError> {
+    serde_json::from_str(body).map_err(|e| ResponseError::Decode(e.to_string()))
+}
+
+/// URL of `rel="next"` in a `Link` header.
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| url.trim().trim_matches(['<', '>']).to_owned())
+    })
+}
+
+/// Whether `url` has the scheme, host and port of `base_url`.
+fn same_origin(base_url: &str, url: &str) -> bool {
+    match (Url::parse(base_url), Url::parse(url)) {
+        (Ok(base), Ok(url)) => base.origin() == url.origin(),
+        _ => false,
+    }
+}
+
+/// `name[]=..&name[]=..`, how Canvas takes array parameters.
+fn array_param<T: std::fmt::Display>(name: &str, values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| format!("{}[]={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn timestamp(value: Option<&str>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value?)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+#[derive(Debug, Deserialize)]
+struct CanvasUser {
+    id: i64,
+    name: String,
+    #[serde(default)]
+    login_id: Option<String>,
+    #[serde(default)]
+    short_name: Option<String>,
+}
+
+impl From<CanvasUser> for User {
+    fn from(user: CanvasUser) -> Self {
+        Self {
+            username: user
+                .login_id
+                .or(user.short_name)
+                .unwrap_or_else(|| user.name.clone()),
+            fullname: user.name,
+            userid: user.id,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CanvasCourse {
+    id: i64,
+    name: String,
+    #[serde(default)]
+    end_at: Option<String>,
+}
+
+impl From<CanvasCourse> for Course {
+    fn from(course: CanvasCourse) -> Self {
+        Self {
+            id: course.id,
+            fullname: course.name,
+            enddate: timestamp(course.end_at.as_deref()).map_or(0, |end| end.timestamp()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CanvasAssignment {
+    id: i64,
+    name: String,
+    #[serde(default)]
+    points_possible: Option<f64>,
+    #[serde(default)]
+    submission: Option<CanvasSubmission>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CanvasSubmission {
+    #[serde(default)]
+    score: Option<f64>,
+}
+
+impl CanvasAssignment {
+    /// Formatted like Moodle's `percentageformatted`, `-` while ungraded.
+    fn percentage(&self) -> String {
+        let score = self.submission.as_ref().and_then(|s| s.score);
+        match (score, self.points_possible) {
+            (Some(score), Some(points)) if points > 0.0 => {
+                format!("{:.2} %", score / points * 100.0)
+            }
+            _ => "-".to_owned(),
+        }
+    }
+}
+
+/// Assignment event of `calendar_events`, the Canvas counterpart of Moodle's action events.
+#[derive(Debug, Deserialize)]
+struct CanvasEvent {
+    assignment: CanvasEventAssignment,
+}
+
+#[derive(Debug, Deserialize)]
+struct CanvasEventAssignment {
+    id: i64,
+    name: String,
+    course_id: i64,
+    #[serde(default)]
+    due_at: Option<String>,
+    #[serde(default)]
+    html_url: String,
+}
+
+impl CanvasEventAssignment {
+    /// Moodle's shape: midnight of the due day and a link with the date and time, which
+    /// [`sort_deadlines`](crate::domain::entities::deadline::sort_deadlines) turns back into
+    /// the due time. Both are in `timezone`.
+    /// Undated assignments and ids beyond what [`Deadline::id`] holds are skipped.
+    fn deadline(self, timezone: Tz) -> Option<Deadline> {
+        let due = timestamp(self.due_at.as_deref())?.with_timezone(&timezone);
+        let Ok(id) = i32::try_from(self.id) else {
+            warn!(
+                "Skipping Canvas assignment {}, its id is too large",
+                self.id
+            );
+            return None;
+        };
+        let midnight = due
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .and_then(|midnight| midnight.and_local_timezone(timezone).earliest())
+            .map_or(due.timestamp(), |midnight| midnight.timestamp());
+        Some(Deadline {
+            id,
+            name: self.name,
+            timeusermidnight: midnight,
+            formattedtime: format!(
+                r#"<a href="{}">{}</a>, {}"#,
+                self.html_url,
+                due.format("%A, %-d %B"),
+                due.format("%H:%M")
+            ),
+            coursename: None,
+            courseid: Some(self.course_id),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CanvasEnrollment {
+    course_id: i64,
+    #[serde(default)]
+    grades: Option<CanvasEnrollmentGrades>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CanvasEnrollmentGrades {
+    #[serde(default)]
+    current_score: Option<f64>,
+}
+
+impl From<CanvasEnrollment> for GradeOverview {
+    fn from(enrollment: CanvasEnrollment) -> Self {
+        let score = enrollment.grades.and_then(|grades| grades.current_score);
+        Self {
+            course_name: None,
+            courseid: enrollment.course_id,
+            grade: score.map_or_else(|| "-".to_owned(), |score| format!("{:.2}", score)),
+            rawgrade: score.map(|score| score.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl DataProviderAbstract for CanvasClient {
+    async fn get_user(&self, token: &str) -> Result<User, ResponseError> {
+        let user: CanvasUser = self.get("users", token, "users/self").await?;
+        Ok(user.into())
+    }
+
+    async fn valid_token(&self, token: &str) -> Result<(), ResponseError> {
+        self.get_user(token).await?;
+        Ok(())
+    }
+
+    /// Courses of the token's user, Canvas has no per-user lookup for other users.
+    async fn get_courses(&self, token: &str, _user_id: i64) -> Result<Vec<Course>, ResponseError> {
+        let courses: Vec<CanvasCourse> = self
+            .get_all("courses", token, "courses?enrollment_state=active")
+            .await?;
+        Ok(courses.into_iter().map(Course::from).collect())
+    }
+
+    async fn get_grades_by_course_id(
+        &self,
+        token: &str,
+        _user_id: i64,
+        course_id: i64,
+    ) -> Result<UserGrades, ResponseError> {
+        let assignments = self.assignments(token, course_id).await?;
+        let gradeitems = assignments
+            .iter()
+            .map(|assignment| GradeItems {
+                id: assignment.id,
+                itemname: assignment.name.clone(),
+                percentageformatted: assignment.percentage(),
+            })
+            .collect();
+        Ok(UserGrades {
+            usergrades: vec![Grade {
+                coursename: None,
+                courseid: course_id,
+                gradeitems,
+            }],
+        })
+    }
+
+    async fn get_deadline_by_course_id(
+        &self,
+        token: &str,
+        course_id: i64,
+    ) -> Result<Events, ResponseError> {
+        let mut deadlines = self.get_deadlines_by_courses(token, &[course_id]).await?;
+        Ok(deadlines
+            .remove(&course_id)
+            .unwrap_or(Events { events: Vec::new() }))
+    }
+
+    /// Assignments are listed per course, the requests go out together.
+    async fn get_grades_by_courses(
+        &self,
+        token: &str,
+        user_id: i64,
+        course_ids: &[i64],
+    ) -> Result<HashMap<i64, UserGrades>, ResponseError> {
+        let requests = course_ids.iter().map(|&course_id| async move {
+            let grades = self
+                .get_grades_by_course_id(token, user_id, course_id)
+                .await?;
+            Ok::<_, ResponseError>((course_id, grades))
+        });
+        Ok(try_join_all(requests).await?.into_iter().collect())
+    }
+
+    /// Upcoming assignments of all courses from one `calendar_events` listing.
+    async fn get_deadlines_by_courses(
+        &self,
+        token: &str,
+        course_ids: &[i64],
+    ) -> Result<HashMap<i64, Events>, ResponseError> {
+        if course_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let now = Utc::now();
+        let window = format!(
+            "start_date={}&end_date={}",
+            now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            (now + TimeDelta::days(DEADLINE_HORIZON_DAYS))
+                .to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+        let pages = try_join_all(course_ids.chunks(CONTEXTS_PER_REQUEST).map(|chunk| {
+            let contexts: Vec<String> = chunk
+                .iter()
+                .map(|course_id| format!("course_{}", course_id))
+                .collect();
+            let path = format!(
+                "calendar_events?type=assignment&{}&{}",
+                window,
+                array_param("context_codes", &contexts)
+            );
+            async move {
+                self.get_all::<CanvasEvent>("calendar_events", token, &path)
+                    .await
+            }
+        }))
+        .await?;
+        let events = pages.into_iter().flatten();
+
+        let mut deadlines: HashMap<i64, Events> = HashMap::new();
+        for event in events {
+            if let Some(deadline) = event.assignment.deadline(self.timezone) {
+                let course_id = deadline.courseid.unwrap_or_default();
+                deadlines
+                    .entry(course_id)
+                    .or_insert_with(|| Events { events: Vec::new() })
+                    .events
+                    .push(deadline);
+            }
+        }
+        Ok(deadlines)
+    }
+
+    async fn deadline_exists(
+        &self,
+        token: &str,
+        course_id: i64,
+        deadline_id: i64,
+    ) -> Result<bool, ResponseError> {
+        let path = format!("courses/{}/assignments/{}", course_id, deadline_id);
+        match self
+            .get::<serde_json::Value>("assignments", token, &path)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(ResponseError::Moodle(MoodleError::NotFound(_))) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_grades_overview(&self, token: &str) -> Result<GradesOverview, ResponseError> {
+        let enrollments: Vec<CanvasEnrollment> = self
+            .get_all(
+                "enrollments",
+                token,
+                "users/self/enrollments?type[]=StudentEnrollment&state[]=active",
+            )
+            .await?;
+        Ok(GradesOverview {
+            grades: enrollments.into_iter().map(GradeOverview::from).collect(),
+        })
+    }
+
+    /// Canvas has no change feed, every course is reported as changed.
+    async fn check_course_updates(
+        &self,
+        _token: &str,
+        cursors: &[SyncCursor],
+    ) -> Result<HashMap<i64, CourseUpdates>, ResponseError> {
+        Ok(cursors
+            .iter()
+            .map(|cursor| {
+                let updates = CourseUpdates {
+                    instances: vec![UpdatedInstance {
+                        contextlevel: "course".to_owned(),
+                        id: cursor.courseid,
+                        updates: vec![InstanceUpdate {
+                            name: "canvas".to_owned(),
+                            timeupdated: None,
+                        }],
+                    }],
+                };
+                (cursor.courseid, updates)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    use super::*;
+
+    const TOKEN: &str = "canvas-token";
+
+    fn authorized(request: &HttpRequest) -> bool {
+        request
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            == Some("Bearer canvas-token")
+    }
+
+    async fn stub(request: HttpRequest) -> HttpResponse {
+        if !authorized(&request) {
+            return HttpResponse::Unauthorized()
+                .json(json!({"errors": [{"message": "Invalid access token."}]}));
+        }
+        let base = format!("http://{}", request.connection_info().host().to_owned());
+        match (request.path(), request.query_string()) {
+            ("/api/v1/users/self", _) => HttpResponse::Ok().json(json!({
+                "id": 7, "name": "Student One", "short_name": "Student", "login_id": "s1@school.edu"
+            })),
+            ("/api/v1/courses", query) if !query.contains("page=2") => HttpResponse::Ok()
+                .insert_header((
+                    "Link",
+                    format!(
+                        r#"<{}/api/v1/courses?page=2&per_page=100>; rel="next", <{}/api/v1/courses?page=1>; rel="first""#,
+                        base, base
+                    ),
+                ))
+                .json(json!([{"id": 1, "name": "Math", "end_at": "2030-06-01T00:00:00Z"}])),
+            ("/api/v1/courses", _) => {
+                HttpResponse::Ok().json(json!([{"id": 2, "name": "Physics", "end_at": null}]))
+            }
+            ("/api/v1/courses/1/assignments", _) => HttpResponse::Ok().json(json!([
+                {"id": 10, "name": "Quiz", "points_possible": 20.0, "submission": {"score": 18.0}},
+                {"id": 11, "name": "Essay", "points_possible": 50.0, "submission": {"score": null}}
+            ])),
+            ("/api/v1/calendar_events", query) => {
+                let params: Vec<&str> = query.split('&').collect();
+                let contexts = params
+                    .iter()
+                    .filter(|param| param.starts_with("context_codes[]="))
+                    .count();
+                if contexts > CONTEXTS_PER_REQUEST
+                    || !params.iter().any(|param| param.starts_with("start_date="))
+                    || !params.iter().any(|param| param.starts_with("end_date="))
+                {
+                    return HttpResponse::BadRequest().finish();
+                }
+                if !params.contains(&"context_codes[]=course_1") {
+                    return HttpResponse::Ok().json(json!([]));
+                }
+                HttpResponse::Ok().json(json!([
+                    {"id": "assignment_11", "assignment": {
+                        "id": 11, "name": "Essay", "course_id": 1,
+                        "due_at": "2030-05-20T18:30:00Z",
+                        "html_url": "https://canvas.example/courses/1/assignments/11"
+                    }},
+                    {"id": "assignment_12", "assignment": {
+                        "id": 12, "name": "Reading", "course_id": 1, "due_at": null
+                    }}
+                ]))
+            }
+            ("/api/v1/users/self/enrollments", _) => HttpResponse::Ok().json(json!([
+                {"course_id": 1, "grades": {"current_score": 90.0}},
+                {"course_id": 2, "grades": {"current_score": null}}
+            ])),
+            _ => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    fn start_stub() -> String {
+        let server = HttpServer::new(|| App::new().default_service(web::to(stub)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        format!("http://{}", address)
+    }
+
+    #[actix_web::test]
+    async fn test_maps_canvas_responses() {
+        let client = CanvasClient::new(start_stub(), Tz::UTC, &RequestLimits::default());
+
+        let user = client.get_user(TOKEN).await.unwrap();
+        assert_eq!(user.userid, 7);
+        assert_eq!(user.username, "s1@school.edu");
+
+        let courses = client.get_courses(TOKEN, 7).await.unwrap();
+        assert_eq!(courses.len(), 2);
+        assert_eq!(courses[0].enddate, 1_906_502_400);
+        assert_eq!(courses[1].enddate, 0);
+
+        let grades = client.get_grades_by_courses(TOKEN, 7, &[1]).await.unwrap();
+        let items = &grades[&1].usergrades[0].gradeitems;
+        assert_eq!(items[0].percentageformatted, "90.00 %");
+        assert_eq!(items[1].percentageformatted, "-");
+
+        let deadlines = client.get_deadlines_by_courses(TOKEN, &[1]).await.unwrap();
+        let essay = &deadlines[&1].events;
+        assert_eq!(essay.len(), 1);
+        assert_eq!(essay[0].timeusermidnight, 1_905_465_600);
+        assert!(essay[0].formattedtime.ends_with("</a>, 18:30"));
+
+        let overview = client.get_grades_overview(TOKEN).await.unwrap();
+        assert_eq!(overview.grades[0].grade, "90.00");
+        assert_eq!(overview.grades[1].rawgrade, None);
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_token() {
+        let client = CanvasClient::new(start_stub(), Tz::UTC, &RequestLimits::default());
+        assert!(matches!(
+            client.valid_token("revoked").await,
+            Err(ResponseError::Moodle(MoodleError::InvalidToken))
+        ));
+    }
+
+    #[test]
+    fn test_deadline_in_tenant_timezone() {
+        let assignment = || CanvasEventAssignment {
+            id: 11,
+            name: "Essay".to_owned(),
+            course_id: 1,
+            due_at: Some("2030-05-20T18:30:00Z".to_owned()),
+            html_url: "https://c.example/a/11".to_owned(),
+        };
+
+        let utc = assignment().deadline(Tz::UTC).unwrap();
+        assert_eq!(utc.timeusermidnight, 1_905_465_600);
+        assert!(utc.formattedtime.contains("Monday, 20 May"));
+
+        let tokyo = assignment().deadline(Tz::Asia__Tokyo).unwrap();
+        assert_eq!(tokyo.timeusermidnight, 1_905_465_600 + 15 * 3600);
+        assert!(tokyo.formattedtime.contains("Tuesday, 21 May"));
+        assert!(tokyo.formattedtime.ends_with("</a>, 03:30"));
+
+        let oversized = CanvasEventAssignment {
+            id: i64::from(i32::MAX) + 1,
+            ..assignment()
+        };
+        assert!(oversized.deadline(Tz::UTC).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_deadlines_of_many_courses_are_chunked() {
+        let client = CanvasClient::new(start_stub(), Tz::UTC, &RequestLimits::default());
+        let course_ids: Vec<i64> = (1..=25).collect();
+
+        let deadlines = client
+            .get_deadlines_by_courses(TOKEN, &course_ids)
+            .await
+            .unwrap();
+        assert_eq!(deadlines.len(), 1);
+        assert_eq!(deadlines[&1].events[0].name, "Essay");
+    }
+
+    #[test]
+    fn test_same_origin() {
+        let base = "https://c.example";
+        assert!(same_origin(base, "https://c.example/api/v1/courses?page=2"));
+        assert!(!same_origin(
+            base,
+            "https://evil.example/api/v1/courses?page=2"
+        ));
+        assert!(!same_origin(base, "http://c.example/api/v1/courses?page=2"));
+        assert!(!same_origin(base, "https://c.example:8443/api/v1/courses"));
+        assert!(!same_origin(base, "/api/v1/courses?page=2"));
+    }
+
+    #[test]
+    fn test_next_link() {
+        assert_eq!(
+            next_link(
+                r#"<https://c.example/api/v1/courses?page=1>; rel="current", <https://c.example/api/v1/courses?page=2>; rel="next""#
+            )
+            .as_deref(),
+            Some("https://c.example/api/v1/courses?page=2")
+        );
+        assert_eq!(
+            next_link(r#"<https://c.example/api/v1/courses?page=1>; rel="last""#),
+            None
+        );
+    }
+}
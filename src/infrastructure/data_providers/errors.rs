@@ -1,25 +1,110 @@
+use serde::Deserialize;
+use serde_json::Value;
 use thiserror::Error;
 
-use crate::domain::entities::errors::{NotificationError, ServiceError};
+use crate::{
+    domain::entities::errors::{NotificationError, ServiceError},
+    infrastructure::repositories::errors::DbError,
+};
 
 #[derive(Error, Debug)]
 pub enum ResponseError {
     #[error("Reqwest error: `{0}`")]
     ReqwestError(#[from] reqwest::Error),
 
-    #[error("Invalid token: `{0}`")]
-    InvalidToken(String),
+    #[error("Moodle error: {0}")]
+    Moodle(#[from] MoodleError),
+
+    #[error("Unexpected response: `{0}`")]
+    Decode(String),
 
     #[error("Empty body: `{0}`")]
     EmptyBody(String),
+
+    #[error("Moodle is overloaded, gave up on `{0}`")]
+    Overloaded(String),
+
+    #[error("Tenant lookup failed: {0}")]
+    Storage(#[from] DbError),
+
+    #[error("Tenant `{0}` is not configured")]
+    UnknownTenant(String),
+}
+
+/// Failure Moodle reported in its `{"exception", "errorcode", "message"}` envelope.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MoodleError {
+    #[error("token is invalid or revoked")]
+    InvalidToken,
+
+    #[error("access denied: `{0}`")]
+    AccessDenied(String),
+
+    #[error("web service function not available: `{0}`")]
+    ServiceNotAvailable(String),
+
+    #[error("invalid parameter: `{0}`")]
+    InvalidParameter(String),
+
+    #[error("site is in maintenance mode")]
+    Maintenance,
+
+    #[error("record not found: `{0}`")]
+    NotFound(String),
+
+    #[error("`{errorcode}`: `{message}`")]
+    Other { errorcode: String, message: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct MoodleException {
+    errorcode: String,
+    #[serde(default)]
+    message: String,
+}
+
+impl MoodleError {
+    /// The error in `response` when it is an exception envelope.
+    pub fn from_response(response: &Value) -> Option<Self> {
+        response.get("exception")?;
+        let exception = MoodleException::deserialize(response).ok()?;
+        Some(exception.into())
+    }
+}
+
+impl From<MoodleException> for MoodleError {
+    fn from(value: MoodleException) -> Self {
+        match value.errorcode.as_str() {
+            "invalidtoken" => Self::InvalidToken,
+            "accessexception" | "nopermissions" | "requireloginerror" => {
+                Self::AccessDenied(value.message)
+            }
+            "servicenotavailable" | "webservicesnotenabled" => {
+                Self::ServiceNotAvailable(value.message)
+            }
+            "invalidparameter" => Self::InvalidParameter(value.message),
+            "sitemaintenance" => Self::Maintenance,
+            "invalidrecord" | "invalidrecordunknown" => Self::NotFound(value.message),
+            _ => Self::Other {
+                errorcode: value.errorcode,
+                message: value.message,
+            },
+        }
+    }
 }
 
 impl From<ResponseError> for ServiceError {
     fn from(value: ResponseError) -> Self {
         match value {
             ResponseError::ReqwestError(error) => Self::ReqwestError(error.to_string()),
-            ResponseError::InvalidToken(token) => Self::InvalidToken(token),
+            ResponseError::Moodle(error) => Self::Moodle(error),
+            ResponseError::Decode(err) => Self::UnexpectedResponse(err),
             ResponseError::EmptyBody(err) => Self::DataNotFound(err),
+            ResponseError::Overloaded(function) => Self::Unavailable(function),
+            ResponseError::Storage(error) => error.into(),
+            ResponseError::UnknownTenant(tenant) => {
+                Self::Unavailable(format!("tenant `{}` is not configured", tenant))
+            }
         }
     }
 }
@@ -28,8 +113,62 @@ impl From<ResponseError> for NotificationError {
     fn from(value: ResponseError) -> Self {
         match value {
             ResponseError::ReqwestError(error) => Self::Data(error.to_string()),
-            ResponseError::InvalidToken(token) => Self::Data(token),
+            ResponseError::Moodle(MoodleError::InvalidToken) => Self::TokenRevoked,
+            ResponseError::Moodle(error) => Self::Data(error.to_string()),
+            ResponseError::Decode(error) => Self::Data(error),
             ResponseError::EmptyBody(error) => Self::Data(error),
+            ResponseError::Overloaded(function) => Self::Data(function),
+            ResponseError::Storage(error) => Self::Data(error.to_string()),
+            ResponseError::UnknownTenant(tenant) => {
+                Self::Data(format!("tenant `{}` is not configured", tenant))
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_moodle_error_from_envelope() {
+        let invalid_token = json!({
+            "exception": "moodle_exception",
+            "errorcode": "invalidtoken",
+            "message": "Invalid token - token not found"
+        });
+        assert_eq!(
+            MoodleError::from_response(&invalid_token),
+            Some(MoodleError::InvalidToken)
+        );
+
+        let access = json!({
+            "exception": "webservice_access_exception",
+            "errorcode": "accessexception",
+            "message": "Access control exception"
+        });
+        assert_eq!(
+            MoodleError::from_response(&access),
+            Some(MoodleError::AccessDenied(
+                "Access control exception".to_owned()
+            ))
+        );
+
+        let unknown = json!({"exception": "dml_exception", "errorcode": "dmlreadexception"});
+        assert_eq!(
+            MoodleError::from_response(&unknown),
+            Some(MoodleError::Other {
+                errorcode: "dmlreadexception".to_owned(),
+                message: String::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_regular_response_is_not_an_error() {
+        assert_eq!(MoodleError::from_response(&json!({"instances": []})), None);
+        assert_eq!(MoodleError::from_response(&json!([{"id": 1}])), None);
+    }
+}
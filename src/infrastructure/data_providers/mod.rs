@@ -1,2 +1,5 @@
+pub mod canvas_client;
 pub mod errors;
 pub mod moodle_client;
+pub mod request_limiter;
+pub mod tenant_router;
@@ -1,28 +1,35 @@
 use async_trait::async_trait;
-use reqwest::Client;
-use std::time::Duration;
+use futures::future::{join_all, try_join_all};
+use log::warn;
+use reqwest::{header::RETRY_AFTER, Client, Response, StatusCode};
+use std::{collections::HashMap, time::Duration};
 
 use crate::domain::{
     data_providers::data_provider_abstract::DataProviderAbstract,
     entities::{
-        course::Course,
-        deadline::Events,
+        course::{Course, CourseUpdates},
+        deadline::{Events, EventsByCourses},
         grade::{GradesOverview, UserGrades},
+        sync_cursor::SyncCursor,
         user::User,
     },
 };
 
-use super::errors::ResponseError;
+use super::{
+    errors::{MoodleError, ResponseError},
+    request_limiter::{RequestLimiter, RequestLimits, DEFAULT_BACKOFF},
+};
 
 #[derive(Debug)]
 pub struct MoodleClient {
     client: Client,
     base_url: String,
     format: String,
+    limiter: RequestLimiter,
 }
 
 impl MoodleClient {
-    pub fn new(base_url: String, format: String) -> Self {
+    pub fn new(base_url: String, format: String, limits: &RequestLimits) -> Self {
         Self {
             client: Client::builder()
                 .timeout(Duration::from_secs(15))
@@ -30,18 +37,32 @@ impl MoodleClient {
                 .unwrap(),
             base_url,
             format,
+            limiter: RequestLimiter::new(limits),
         }
     }
 
     async fn send_request<T: serde::de::DeserializeOwned>(
         &self,
+        function: &str,
         url: &str,
-        token: &str,
     ) -> Result<T, ResponseError> {
         let mut attempt = 0;
         loop {
+            let permit = self.limiter.acquire(function).await;
             let response = self.client.get(url).send().await;
             match response {
+                Ok(resp) if is_overloaded(resp.status()) => {
+                    drop(permit);
+                    let wait = retry_after(&resp).unwrap_or(DEFAULT_BACKOFF);
+                    warn!("Moodle is overloaded, pausing requests for {:?}", wait);
+                    self.limiter.pause(wait);
+                    if attempt >= 2 {
+                        return Err(ResponseError::Overloaded(function.to_owned()));
+                    }
+                    // The pause already delays the retry.
+                    attempt += 1;
+                    continue;
+                }
                 Ok(resp) => {
                     let body_text = resp.text().await.map_err(ResponseError::ReqwestError)?;
                     if body_text.is_empty() {
@@ -49,12 +70,11 @@ impl MoodleClient {
                             "Empty response from Moodle".to_owned(),
                         ));
                     };
-                    match serde_json::from_str::<T>(&body_text) {
-                        Ok(value) => return Ok(value),
-                        Err(_) => {
-                            return Err(ResponseError::InvalidToken(token.to_string()));
-                        }
+                    let result = decode(&body_text);
+                    if let Err(ResponseError::Moodle(MoodleError::Maintenance)) = result {
+                        self.limiter.pause(DEFAULT_BACKOFF);
                     }
+                    return result;
                 }
                 Err(e) => {
                     if attempt >= 2 {
@@ -62,12 +82,54 @@ impl MoodleClient {
                     }
                 }
             }
+            drop(permit);
             attempt += 1;
             tokio::time::sleep(Duration::from_secs(2)).await;
         }
     }
 }
 
+/// Reads a web service response. Moodle answers failures with HTTP 200 and an exception
+/// envelope, which is checked first since it can also fit response types with optional fields.
+fn decode<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, ResponseError> {
+    let value: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| ResponseError::Decode(e.to_string()))?;
+    if let Some(error) = MoodleError::from_response(&value) {
+        return Err(error.into());
+    }
+    serde_json::from_value(value).map_err(|e| ResponseError::Decode(e.to_string()))
+}
+
+/// Events the per-course calendar function returns by default, the multi-course one only 10.
+const EVENTS_PER_COURSE: usize = 20;
+
+/// `name[0]=..&name[1]=..`, how Moodle takes array parameters in a query string.
+fn array_param(name: &str, values: &[i64]) -> String {
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| format!("&{}[{}]={}", name, index, value))
+        .collect()
+}
+
+/// Statuses Moodle, or the proxy in front of it, answers with when it is throttling us.
+pub(super) fn is_overloaded(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// `Retry-After` in seconds, the HTTP-date form is not used by Moodle.
+pub(super) fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
 #[async_trait]
 impl DataProviderAbstract for MoodleClient {
     async fn get_user(&self, token: &str) -> Result<User, ResponseError> {
@@ -75,7 +137,8 @@ impl DataProviderAbstract for MoodleClient {
             "{}wstoken={}&wsfunction=core_webservice_get_site_info{}",
             self.base_url, token, self.format
         );
-        self.send_request(&url, token).await
+        self.send_request("core_webservice_get_site_info", &url)
+            .await
     }
 
     async fn valid_token(&self, token: &str) -> Result<(), ResponseError> {
@@ -83,7 +146,8 @@ impl DataProviderAbstract for MoodleClient {
             "{}wstoken={}&wsfunction=core_webservice_get_site_info{}",
             self.base_url, token, self.format
         );
-        self.send_request::<User>(&url, token).await?;
+        self.send_request::<User>("core_webservice_get_site_info", &url)
+            .await?;
         Ok(())
     }
 
@@ -92,7 +156,8 @@ impl DataProviderAbstract for MoodleClient {
             "{}wstoken={}&wsfunction=core_enrol_get_users_courses{}&userid={}",
             self.base_url, token, self.format, user_id,
         );
-        self.send_request(&url, token).await
+        self.send_request("core_enrol_get_users_courses", &url)
+            .await
     }
 
     async fn get_grades_by_course_id(
@@ -105,7 +170,8 @@ impl DataProviderAbstract for MoodleClient {
             "{}wstoken={}&wsfunction=gradereport_user_get_grade_items{}&userid={}&courseid={}",
             self.base_url, token, self.format, user_id, course_id
         );
-        self.send_request(&url, token).await
+        self.send_request("gradereport_user_get_grade_items", &url)
+            .await
     }
 
     async fn get_deadline_by_course_id(
@@ -117,7 +183,78 @@ impl DataProviderAbstract for MoodleClient {
             "{}wstoken={}&wsfunction=core_calendar_get_action_events_by_course{}&courseid={}",
             self.base_url, token, self.format, course_id,
         );
-        self.send_request(&url, token).await
+        self.send_request("core_calendar_get_action_events_by_course", &url)
+            .await
+    }
+
+    /// `gradereport_user_get_grade_items` takes a single course, the requests go out together
+    /// and the limiter keeps them within the configured rate.
+    async fn get_grades_by_courses(
+        &self,
+        token: &str,
+        user_id: i64,
+        course_ids: &[i64],
+    ) -> Result<HashMap<i64, UserGrades>, ResponseError> {
+        let requests = course_ids.iter().map(|&course_id| async move {
+            let grades = self
+                .get_grades_by_course_id(token, user_id, course_id)
+                .await?;
+            Ok::<_, ResponseError>((course_id, grades))
+        });
+        Ok(try_join_all(requests).await?.into_iter().collect())
+    }
+
+    async fn get_deadlines_by_courses(
+        &self,
+        token: &str,
+        course_ids: &[i64],
+    ) -> Result<HashMap<i64, Events>, ResponseError> {
+        if course_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let url = format!(
+            "{}wstoken={}&wsfunction=core_calendar_get_action_events_by_courses{}&limitnum={}{}",
+            self.base_url,
+            token,
+            self.format,
+            EVENTS_PER_COURSE,
+            array_param("courseids", course_ids),
+        );
+        let response: EventsByCourses = self
+            .send_request("core_calendar_get_action_events_by_courses", &url)
+            .await?;
+        Ok(response
+            .groupedbycourse
+            .into_iter()
+            .map(|course| {
+                (
+                    course.courseid,
+                    Events {
+                        events: course.events,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn deadline_exists(
+        &self,
+        token: &str,
+        _course_id: i64,
+        deadline_id: i64,
+    ) -> Result<bool, ResponseError> {
+        let url = format!(
+            "{}wstoken={}&wsfunction=core_calendar_get_calendar_event_by_id{}&eventid={}",
+            self.base_url, token, self.format, deadline_id
+        );
+        match self
+            .send_request::<serde_json::Value>("core_calendar_get_calendar_event_by_id", &url)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(ResponseError::Moodle(MoodleError::NotFound(_))) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 
     async fn get_grades_overview(&self, token: &str) -> Result<GradesOverview, ResponseError> {
@@ -125,6 +262,97 @@ impl DataProviderAbstract for MoodleClient {
             "{}wstoken={}&wsfunction=gradereport_overview_get_course_grades{}",
             self.base_url, token, self.format
         );
-        self.send_request(&url, token).await
+        self.send_request("gradereport_overview_get_course_grades", &url)
+            .await
+    }
+
+    /// `core_course_get_updates_since` takes a single course, the requests go out together and
+    /// the limiter keeps them within the configured rate. A course that fails its check is left
+    /// out, only a revoked token fails them all.
+    async fn check_course_updates(
+        &self,
+        token: &str,
+        cursors: &[SyncCursor],
+    ) -> Result<HashMap<i64, CourseUpdates>, ResponseError> {
+        let requests = cursors.iter().map(|cursor| async move {
+            let url = format!(
+                "{}wstoken={}&wsfunction=core_course_get_updates_since{}&courseid={}&since={}",
+                self.base_url, token, self.format, cursor.courseid, cursor.synced_at
+            );
+            let updates = self
+                .send_request::<CourseUpdates>("core_course_get_updates_since", &url)
+                .await;
+            (cursor.courseid, updates)
+        });
+
+        let mut checked = HashMap::new();
+        for (course_id, updates) in join_all(requests).await {
+            match updates {
+                Ok(updates) => {
+                    checked.insert(course_id, updates);
+                }
+                Err(ResponseError::Moodle(MoodleError::InvalidToken)) => {
+                    return Err(MoodleError::InvalidToken.into());
+                }
+                Err(e) => warn!("Update check of course {} failed: {}", course_id, e),
+            }
+        }
+        Ok(checked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_tells_errors_apart() {
+        let courses: Vec<Course> =
+            decode(r#"[{"id": 1, "fullname": "Math", "enddate": 0}]"#).unwrap();
+        assert_eq!(courses[0].fullname, "Math");
+
+        let revoked = decode::<CourseUpdates>(
+            r#"{"exception": "moodle_exception", "errorcode": "invalidtoken", "message": "Invalid token"}"#,
+        );
+        assert!(matches!(
+            revoked,
+            Err(ResponseError::Moodle(MoodleError::InvalidToken))
+        ));
+
+        let mismatch = decode::<Vec<Course>>(r#"{"courses": []}"#);
+        assert!(matches!(mismatch, Err(ResponseError::Decode(_))));
+    }
+
+    #[test]
+    fn test_events_by_courses() {
+        assert_eq!(
+            array_param("courseids", &[4, 9]),
+            "&courseids[0]=4&courseids[1]=9"
+        );
+
+        let response: EventsByCourses = decode(
+            r#"{"groupedbycourse": [{"courseid": 4, "firstid": 1, "lastid": 1, "events": [
+                {"id": 1, "name": "Quiz", "timeusermidnight": 1700000000, "formattedtime": "10:00"}
+            ]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.groupedbycourse[0].courseid, 4);
+        assert_eq!(response.groupedbycourse[0].events[0].name, "Quiz");
+    }
+
+    #[test]
+    fn test_updates_since() {
+        // Moodle reports modules, here an edited assignment with a new submission.
+        let updated: CourseUpdates = decode(
+            r#"{"instances":[{"contextlevel":"module","id":1285,"updates":[
+                {"name":"configuration","timeupdated":1711029418,"itemids":[]},
+                {"name":"submissions","itemids":[215]}
+            ]}],"warnings":[]}"#,
+        )
+        .unwrap();
+        assert!(updated.has_updates());
+
+        let unchanged: CourseUpdates = decode(r#"{"instances":[],"warnings":[]}"#).unwrap();
+        assert!(!unchanged.has_updates());
     }
 }
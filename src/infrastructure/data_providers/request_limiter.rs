@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Wait applied when Moodle reports overload without a `Retry-After` header.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(30);
+/// Longest `Retry-After` that is honoured, so a bogus header can't stall polling for hours.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Limits on the requests sent to Moodle, see `MOODLE_*` in the README.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestLimits {
+    /// Requests in flight at once, across all users.
+    pub max_concurrent: usize,
+    /// Sustained requests per second, across all endpoints.
+    pub requests_per_sec: f64,
+    /// Requests that may go out back to back after an idle period.
+    pub burst: u32,
+    /// Requests per second of single web service functions, on top of the global rate.
+    pub endpoints: HashMap<String, f64>,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 8,
+            requests_per_sec: 10.0,
+            burst: 10,
+            endpoints: HashMap::new(),
+        }
+    }
+}
+
+/// Parses a requests per second rate, which must be a positive number.
+pub fn parse_rate(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| rate.is_finite() && *rate > 0.0)
+}
+
+/// Parses `function=rate` pairs separated by commas, e.g. `core_course_get_updates_since=2`.
+pub fn parse_endpoint_limits(value: &str) -> Result<HashMap<String, f64>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (function, rate) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected function=rate, got `{}`", pair))?;
+            let rate =
+                parse_rate(rate).ok_or_else(|| format!("Invalid rate for `{}`", function))?;
+            Ok((function.trim().to_owned(), rate))
+        })
+        .collect()
+}
+
+/// Token bucket refilled at `rate` per second up to `capacity`.
+///
+/// Callers reserve a token even when the bucket is empty and wait for it to be refilled,
+/// so concurrent requests are spaced out in arrival order.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        Self {
+            rate,
+            capacity,
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes one token at `now`, returns how long to wait until it is available.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = now.saturating_duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.rate).min(self.capacity) - 1.0;
+        state.updated = now;
+
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.rate)
+        }
+    }
+
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Gate every Moodle request passes: a concurrency limit, the global and per-endpoint
+/// rates, and a shared pause after Moodle reported overload.
+#[derive(Debug)]
+pub struct RequestLimiter {
+    permits: Semaphore,
+    global: TokenBucket,
+    endpoints: HashMap<String, TokenBucket>,
+    paused_until: Mutex<Option<Instant>>,
+}
+
+impl RequestLimiter {
+    pub fn new(limits: &RequestLimits) -> Self {
+        Self {
+            permits: Semaphore::new(limits.max_concurrent.max(1)),
+            global: TokenBucket::new(limits.requests_per_sec, limits.burst),
+            endpoints: limits
+                .endpoints
+                .iter()
+                .map(|(function, rate)| (function.clone(), TokenBucket::new(*rate, 1)))
+                .collect(),
+            paused_until: Mutex::new(None),
+        }
+    }
+
+    /// Waits until a request to `function` may be sent. The request is in flight while the
+    /// returned permit is held.
+    pub async fn acquire(&self, function: &str) -> SemaphorePermit<'_> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("request semaphore is never closed");
+
+        if let Some(wait) = self.pause_remaining(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+        if let Some(bucket) = self.endpoints.get(function) {
+            bucket.acquire().await;
+        }
+        self.global.acquire().await;
+
+        permit
+    }
+
+    /// Holds back every request for `duration`, an earlier pause is only ever extended.
+    pub fn pause(&self, duration: Duration) {
+        let until = Instant::now() + duration.min(MAX_BACKOFF);
+        let mut paused_until = self.paused_until.lock().unwrap_or_else(|e| e.into_inner());
+        if paused_until.is_none_or(|current| current < until) {
+            *paused_until = Some(until);
+        }
+    }
+
+    fn pause_remaining(&self, now: Instant) -> Option<Duration> {
+        let paused_until = self.paused_until.lock().unwrap_or_else(|e| e.into_inner());
+        paused_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_spaces_out_requests_after_burst() {
+        let bucket = TokenBucket::new(2.0, 2);
+        let start = Instant::now();
+
+        assert_eq!(bucket.reserve(start), Duration::ZERO);
+        assert_eq!(bucket.reserve(start), Duration::ZERO);
+        assert_eq!(bucket.reserve(start), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(start), Duration::from_secs(1));
+
+        // Two seconds refill four tokens, two of them pay back the reservations.
+        assert_eq!(
+            bucket.reserve(start + Duration::from_secs(2)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_parse_endpoint_limits() {
+        let limits = parse_endpoint_limits(
+            "core_course_get_updates_since=2, gradereport_user_get_grade_items=0.5",
+        )
+        .unwrap();
+        assert_eq!(limits.get("core_course_get_updates_since"), Some(&2.0));
+        assert_eq!(limits.get("gradereport_user_get_grade_items"), Some(&0.5));
+
+        assert!(parse_endpoint_limits("").unwrap().is_empty());
+        assert!(parse_endpoint_limits("core_course_get_updates_since").is_err());
+        assert!(parse_endpoint_limits("core_course_get_updates_since=0").is_err());
+        assert!(parse_endpoint_limits("core_course_get_updates_since=inf").is_err());
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate(" 2.5 "), Some(2.5));
+        for invalid in ["0", "-1", "NaN", "inf", "-inf", "1e400", "fast"] {
+            assert_eq!(parse_rate(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_pause_is_only_extended() {
+        let limiter = RequestLimiter::new(&RequestLimits::default());
+        limiter.pause(Duration::from_secs(60));
+        limiter.pause(Duration::from_secs(10));
+
+        let remaining = limiter.pause_remaining(Instant::now()).unwrap();
+        assert!(remaining > Duration::from_secs(50));
+
+        limiter.pause(Duration::from_secs(3600));
+        assert!(limiter.pause_remaining(Instant::now()).unwrap() <= MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,476 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::{self, Debug},
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    domain::{
+        data_providers::data_provider_abstract::DataProviderAbstract,
+        entities::{
+            course::{Course, CourseUpdates},
+            deadline::Events,
+            errors::ServiceError,
+            grade::{GradesOverview, UserGrades},
+            sync_cursor::SyncCursor,
+            tenant::{LmsProvider, Tenant},
+            user::User,
+        },
+        repositories::data_repository_abstract::TokenRepositoryAbstract,
+    },
+    infrastructure::repositories::errors::DbError,
+};
+
+use super::{
+    canvas_client::CanvasClient, errors::ResponseError, moodle_client::MoodleClient,
+    request_limiter::RequestLimits,
+};
+
+/// Routes every call to the LMS instance of the token's tenant.
+///
+/// The tenant of a token is remembered once known: assigned at registration, or read from
+/// storage on first use. Registered tokens without a stored tenant belong to the first
+/// tenant, unregistered ones are neither routed nor remembered. Grades come back with the
+/// tenant's letter grades appended.
+pub struct TenantRouter {
+    tenants: Vec<Tenant>,
+    clients: HashMap<String, Box<dyn DataProviderAbstract>>,
+    repository: Arc<dyn TokenRepositoryAbstract>,
+    assigned: RwLock<HashMap<String, String>>,
+}
+
+impl Debug for TenantRouter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantRouter")
+            .field("tenants", &self.tenants)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TenantRouter {
+    /// Every tenant gets its own client, so limits apply per LMS instance.
+    pub fn new(
+        tenants: Vec<Tenant>,
+        limits: &RequestLimits,
+        repository: Arc<dyn TokenRepositoryAbstract>,
+    ) -> Result<Self, String> {
+        if tenants.is_empty() {
+            return Err("At least one tenant must be configured".to_owned());
+        }
+        let mut clients = HashMap::new();
+        for tenant in &tenants {
+            let client: Box<dyn DataProviderAbstract> = match tenant.provider {
+                LmsProvider::Moodle => Box::new(MoodleClient::new(
+                    tenant.base_url.clone(),
+                    tenant.format_url.clone(),
+                    limits,
+                )),
+                LmsProvider::Canvas => Box::new(CanvasClient::new(
+                    tenant.base_url.clone(),
+                    tenant.timezone,
+                    limits,
+                )),
+            };
+            if clients.insert(tenant.id.clone(), client).is_some() {
+                return Err(format!("Duplicate tenant id `{}`", tenant.id));
+            }
+        }
+        Ok(Self {
+            tenants,
+            clients,
+            repository,
+            assigned: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub fn tenants(&self) -> &[Tenant] {
+        &self.tenants
+    }
+
+    fn default_tenant(&self) -> &Tenant {
+        &self.tenants[0]
+    }
+
+    pub fn tenant(&self, id: &str) -> Option<&Tenant> {
+        self.tenants.iter().find(|tenant| tenant.id == id)
+    }
+
+    /// Routes `token` to tenant `id`, or the default tenant, ahead of its registration.
+    /// Tokens that are registered or being registered keep their routing.
+    pub async fn assign(&self, token: &str, id: Option<&str>) -> Result<&Tenant, ServiceError> {
+        let tenant = match id {
+            Some(id) => self
+                .tenant(id)
+                .ok_or_else(|| ServiceError::UnknownTenant(id.to_owned()))?,
+            None => self.default_tenant(),
+        };
+        match self.repository.find_tenant(token).await {
+            Ok(_) => return Err(ServiceError::UserAlreadyExists(token.to_owned())),
+            Err(DbError::DataNotFound(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+        match self
+            .assigned
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(token.to_owned())
+        {
+            Entry::Occupied(_) => Err(ServiceError::UserAlreadyExists(token.to_owned())),
+            Entry::Vacant(entry) => {
+                entry.insert(tenant.id.clone());
+                Ok(tenant)
+            }
+        }
+    }
+
+    /// Drops the remembered tenant of `token`, after a failed registration or a deletion.
+    pub fn forget(&self, token: &str) {
+        self.assigned
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(token);
+    }
+
+    /// Tenant of a registered token. Fails for unregistered tokens, failed lookups and tenants
+    /// no longer configured, none of them is remembered. Credentials are never sent to another
+    /// tenant's LMS.
+    pub async fn tenant_of(&self, token: &str) -> Result<&Tenant, ResponseError> {
+        let assigned = self
+            .assigned
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(token)
+            .cloned();
+        let id = match assigned {
+            Some(id) => Some(id),
+            None => self.repository.find_tenant(token).await?,
+        };
+
+        let tenant = match id {
+            Some(id) => self.tenant(&id).ok_or(ResponseError::UnknownTenant(id))?,
+            None => self.default_tenant(),
+        };
+        self.assigned
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(token.to_owned(), tenant.id.clone());
+        Ok(tenant)
+    }
+
+    async fn client(&self, token: &str) -> Result<&dyn DataProviderAbstract, ResponseError> {
+        let tenant = self.tenant_of(token).await?;
+        Ok(self.clients[&tenant.id].as_ref())
+    }
+
+    fn format_grades(tenant: &Tenant, grades: &mut UserGrades) {
+        for item in grades
+            .usergrades
+            .iter_mut()
+            .flat_map(|grade| grade.gradeitems.iter_mut())
+        {
+            item.percentageformatted = tenant.grade_scale.format(&item.percentageformatted);
+        }
+    }
+}
+
+#[async_trait]
+impl DataProviderAbstract for TenantRouter {
+    async fn assign_tenant(
+        &self,
+        token: &str,
+        tenant: Option<String>,
+    ) -> Result<Option<String>, ServiceError> {
+        let tenant = self.assign(token, tenant.as_deref()).await?;
+        Ok(Some(tenant.id.clone()))
+    }
+
+    async fn tenant_id(&self, token: &str) -> Result<Option<String>, ServiceError> {
+        let tenant = self.tenant_of(token).await?;
+        Ok(Some(tenant.id.clone()))
+    }
+
+    fn forget_tenant(&self, token: &str) {
+        self.forget(token);
+    }
+
+    async fn get_user(&self, token: &str) -> Result<User, ResponseError> {
+        self.client(token).await?.get_user(token).await
+    }
+
+    async fn valid_token(&self, token: &str) -> Result<(), ResponseError> {
+        self.client(token).await?.valid_token(token).await
+    }
+
+    async fn get_courses(&self, token: &str, user_id: i64) -> Result<Vec<Course>, ResponseError> {
+        self.client(token).await?.get_courses(token, user_id).await
+    }
+
+    async fn get_grades_by_course_id(
+        &self,
+        token: &str,
+        user_id: i64,
+        course_id: i64,
+    ) -> Result<UserGrades, ResponseError> {
+        let tenant = self.tenant_of(token).await?;
+        let mut grades = self.clients[&tenant.id]
+            .get_grades_by_course_id(token, user_id, course_id)
+            .await?;
+        Self::format_grades(tenant, &mut grades);
+        Ok(grades)
+    }
+
+    async fn get_deadline_by_course_id(
+        &self,
+        token: &str,
+        course_id: i64,
+    ) -> Result<Events, ResponseError> {
+        self.client(token)
+            .await?
+            .get_deadline_by_course_id(token, course_id)
+            .await
+    }
+
+    async fn get_grades_by_courses(
+        &self,
+        token: &str,
+        user_id: i64,
+        course_ids: &[i64],
+    ) -> Result<HashMap<i64, UserGrades>, ResponseError> {
+        let tenant = self.tenant_of(token).await?;
+        let mut grades = self.clients[&tenant.id]
+            .get_grades_by_courses(token, user_id, course_ids)
+            .await?;
+        for grades in grades.values_mut() {
+            Self::format_grades(tenant, grades);
+        }
+        Ok(grades)
+    }
+
+    async fn get_deadlines_by_courses(
+        &self,
+        token: &str,
+        course_ids: &[i64],
+    ) -> Result<HashMap<i64, Events>, ResponseError> {
+        self.client(token)
+            .await?
+            .get_deadlines_by_courses(token, course_ids)
+            .await
+    }
+
+    async fn deadline_exists(
+        &self,
+        token: &str,
+        course_id: i64,
+        deadline_id: i64,
+    ) -> Result<bool, ResponseError> {
+        self.client(token)
+            .await?
+            .deadline_exists(token, course_id, deadline_id)
+            .await
+    }
+
+    async fn get_grades_overview(&self, token: &str) -> Result<GradesOverview, ResponseError> {
+        self.client(token).await?.get_grades_overview(token).await
+    }
+
+    async fn check_course_updates(
+        &self,
+        token: &str,
+        cursors: &[SyncCursor],
+    ) -> Result<HashMap<i64, CourseUpdates>, ResponseError> {
+        self.client(token)
+            .await?
+            .check_course_updates(token, cursors)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        domain::{
+            data_providers::data_provider_abstract::MockDataProviderAbstract,
+            entities::{
+                grade::{Grade, GradeItems},
+                registration::Registration,
+                tenant::{GradeBand, GradeScale},
+                token::Token,
+            },
+            repositories::data_repository_abstract::MockTokenRepositoryAbstract,
+        },
+        infrastructure::repositories::in_memory_repository::InMemoryRepository,
+    };
+
+    use super::*;
+
+    fn tenant(id: &str) -> Tenant {
+        Tenant {
+            id: id.to_owned(),
+            ..Tenant::single(format!("https://{}.example/?", id), String::new())
+        }
+    }
+
+    async fn registered(repository: &InMemoryRepository, token: &str, tenant: Option<&str>) {
+        let mut registration = Registration {
+            token: Token::new(token.to_owned(), None),
+            user: User {
+                username: "student".to_owned(),
+                fullname: "Student".to_owned(),
+                userid: 1,
+            },
+            courses: Vec::new(),
+            grades: Vec::new(),
+            grades_overview: GradesOverview { grades: Vec::new() },
+            deadlines: Vec::new(),
+        };
+        registration.token.tenant = tenant.map(str::to_owned);
+        repository.save_registration(&registration).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_stored_tenant() {
+        let repository = Arc::new(InMemoryRepository::new());
+        registered(&repository, "a", Some("enu")).await;
+        registered(&repository, "b", None).await;
+        registered(&repository, "c", Some("closed")).await;
+
+        let router = TenantRouter::new(
+            vec![tenant("aitu"), tenant("enu")],
+            &RequestLimits::default(),
+            repository,
+        )
+        .unwrap();
+
+        assert_eq!(router.tenant_of("a").await.unwrap().id, "enu");
+        assert_eq!(router.tenant_of("b").await.unwrap().id, "aitu");
+        // A tenant that was removed from the configuration is never replaced by another.
+        assert!(matches!(
+            router.tenant_of("c").await,
+            Err(ResponseError::UnknownTenant(_))
+        ));
+        assert!(matches!(
+            router.tenant_of("unknown").await,
+            Err(ResponseError::Storage(DbError::DataNotFound(_)))
+        ));
+        assert_eq!(router.assigned.read().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_lookup_is_not_remembered() {
+        let mut repository = MockTokenRepositoryAbstract::new();
+        let mut calls = 0;
+        repository
+            .expect_find_tenant()
+            .times(2)
+            .returning(move |_| {
+                calls += 1;
+                match calls {
+                    1 => Err(DbError::Storage("connection reset".to_owned())),
+                    _ => Ok(Some("enu".to_owned())),
+                }
+            });
+        let router = TenantRouter::new(
+            vec![tenant("aitu"), tenant("enu")],
+            &RequestLimits::default(),
+            Arc::new(repository),
+        )
+        .unwrap();
+
+        assert!(router.tenant_of("a").await.is_err());
+        assert_eq!(router.tenant_of("a").await.unwrap().id, "enu");
+        assert_eq!(router.tenant_of("a").await.unwrap().id, "enu");
+    }
+
+    #[tokio::test]
+    async fn test_grades_use_tenant_scale() {
+        let mut enu = tenant("enu");
+        enu.grade_scale = GradeScale {
+            bands: vec![GradeBand {
+                min_percentage: 90.0,
+                letter: "A".to_owned(),
+            }],
+        };
+        let repository = Arc::new(InMemoryRepository::new());
+        registered(&repository, "a", Some("enu")).await;
+        let mut router = TenantRouter::new(
+            vec![tenant("aitu"), enu],
+            &RequestLimits::default(),
+            repository,
+        )
+        .unwrap();
+        let mut client = MockDataProviderAbstract::new();
+        client
+            .expect_get_grades_by_course_id()
+            .returning(|_, _, _| {
+                Ok(UserGrades {
+                    usergrades: vec![Grade {
+                        coursename: None,
+                        courseid: 1,
+                        gradeitems: vec![GradeItems {
+                            id: 1,
+                            itemname: "Quiz".to_owned(),
+                            percentageformatted: "95,00 %".to_owned(),
+                        }],
+                    }],
+                })
+            });
+        router.clients.insert("enu".to_owned(), Box::new(client));
+
+        let grades = router.get_grades_by_course_id("a", 1, 1).await.unwrap();
+        assert_eq!(
+            grades.usergrades[0].gradeitems[0].percentageformatted,
+            "95,00 % (A)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assign_keeps_the_routing_of_registered_tokens() {
+        let repository = Arc::new(InMemoryRepository::new());
+        registered(&repository, "a", Some("enu")).await;
+        let router = TenantRouter::new(
+            vec![tenant("aitu"), tenant("enu")],
+            &RequestLimits::default(),
+            repository,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            router.assign("a", Some("aitu")).await,
+            Err(ServiceError::UserAlreadyExists(_))
+        ));
+        assert!(router.assigned.read().unwrap().is_empty());
+        assert_eq!(router.tenant_of("a").await.unwrap().id, "enu");
+    }
+
+    #[tokio::test]
+    async fn test_assign_validates_tenant() {
+        let router = TenantRouter::new(
+            vec![tenant("aitu"), tenant("enu")],
+            &RequestLimits::default(),
+            Arc::new(InMemoryRepository::new()),
+        )
+        .unwrap();
+
+        assert_eq!(router.assign("new", Some("enu")).await.unwrap().id, "enu");
+        assert_eq!(router.tenant_of("new").await.unwrap().id, "enu");
+        assert!(matches!(
+            router.assign("new", None).await,
+            Err(ServiceError::UserAlreadyExists(_))
+        ));
+        assert_eq!(router.tenant_of("new").await.unwrap().id, "enu");
+        router.forget("new");
+        assert!(router.tenant_of("new").await.is_err());
+        assert!(matches!(
+            router.assign("other", Some("missing")).await,
+            Err(ServiceError::UnknownTenant(_))
+        ));
+        assert!(TenantRouter::new(
+            vec![tenant("aitu"), tenant("aitu")],
+            &RequestLimits::default(),
+            Arc::new(InMemoryRepository::new()),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use mongodb::bson::{Bson, Document};
+
+/// Field every stored document carries with the schema version it was written with.
+pub const SCHEMA_VERSION_FIELD: &str = "schema_version";
+
+/// Version written by this build, the highest version in [`MIGRATIONS`].
+pub const SCHEMA_VERSION: i32 = 1;
+
+/// Forward migration of the documents in one collection.
+#[derive(Debug)]
+pub struct Migration {
+    /// Version the document has after `upgrade`.
+    pub version: i32,
+    pub collection: &'static str,
+    pub description: &'static str,
+    pub upgrade: fn(&mut Document),
+}
+
+/// Every migration, ordered by version. Append only, released migrations never change.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    collection: "deadlines",
+    description: "store the course id of deadlines, null when unknown",
+    upgrade: add_deadline_courseid,
+}];
+
+fn add_deadline_courseid(doc: &mut Document) {
+    if !doc.contains_key("courseid") {
+        doc.insert("courseid", Bson::Null);
+    }
+}
+
+/// Version `doc` was written with, documents from before versioning are version 0.
+/// A version beyond `i32` can only come from a newer build and reads as the highest.
+pub fn document_version(doc: &Document) -> i32 {
+    match doc.get(SCHEMA_VERSION_FIELD) {
+        Some(Bson::Int32(version)) => *version,
+        Some(Bson::Int64(version)) => i32::try_from(*version).unwrap_or(i32::MAX),
+        _ => 0,
+    }
+}
+
+/// Marks a freshly written document as current.
+pub fn stamp(doc: &mut Document) {
+    doc.insert(SCHEMA_VERSION_FIELD, SCHEMA_VERSION);
+}
+
+/// Runs the pending migrations of `collection` over `doc`.
+///
+/// Returns whether the document changed. Documents written by a newer build are left alone.
+pub fn upgrade(collection: &str, doc: &mut Document) -> bool {
+    let version = document_version(doc);
+    if version >= SCHEMA_VERSION {
+        return false;
+    }
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.collection == collection && migration.version > version)
+    {
+        (migration.upgrade)(doc);
+    }
+    stamp(doc);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered_and_current() {
+        assert!(MIGRATIONS
+            .windows(2)
+            .all(|pair| pair[0].version <= pair[1].version));
+        assert_eq!(
+            MIGRATIONS.last().map(|migration| migration.version),
+            Some(SCHEMA_VERSION)
+        );
+    }
+
+    #[test]
+    fn test_upgrade_unversioned_deadline() {
+        let mut deadline = doc! {"id": 1, "name": "Quiz"};
+        assert!(upgrade("deadlines", &mut deadline));
+        assert_eq!(deadline.get("courseid"), Some(&Bson::Null));
+        assert_eq!(document_version(&deadline), SCHEMA_VERSION);
+
+        assert!(!upgrade("deadlines", &mut deadline));
+    }
+
+    #[test]
+    fn test_document_version_does_not_wrap() {
+        let course = doc! {"id": 1, SCHEMA_VERSION_FIELD: i64::from(u32::MAX) + 1};
+        assert_eq!(document_version(&course), i32::MAX);
+    }
+
+    #[test]
+    fn test_upgrade_keeps_newer_documents() {
+        let mut course = doc! {"id": 1, SCHEMA_VERSION_FIELD: SCHEMA_VERSION + 1};
+        assert!(!upgrade("courses", &mut course));
+        assert_eq!(document_version(&course), SCHEMA_VERSION + 1);
+    }
+}
@@ -0,0 +1,104 @@
+use std::borrow::Cow;
+
+use crate::domain::entities::transcript::Transcript;
+
+use super::errors::ExportError;
+
+/// Spreadsheets run cells starting with these as formulas.
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Quotes `cell` so a spreadsheet shows it as text instead of evaluating it, names and grades
+/// come from the LMS and are not trusted.
+fn text_cell(cell: &str) -> Cow<'_, str> {
+    if cell.starts_with(FORMULA_PREFIXES) {
+        Cow::Owned(format!("'{}", cell))
+    } else {
+        Cow::Borrowed(cell)
+    }
+}
+
+/// One row per grade item, course totals repeated on each row of their course.
+pub fn render_csv(transcript: &Transcript) -> Result<Vec<u8>, ExportError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "course_id",
+        "course",
+        "item_id",
+        "item",
+        "grade",
+        "course_total",
+    ])?;
+
+    for course in &transcript.courses {
+        let course_id = course.courseid.to_string();
+        let course_name = text_cell(&course.course_name);
+        let total = text_cell(course.total.as_deref().unwrap_or(""));
+        for item in &course.items {
+            writer.write_record([
+                course_id.as_str(),
+                &course_name,
+                &item.id.to_string(),
+                &text_cell(&item.itemname),
+                &text_cell(&item.percentageformatted),
+                &total,
+            ])?;
+        }
+        if course.items.is_empty() {
+            writer.write_record([course_id.as_str(), &course_name, "", "", "", &total])?;
+        }
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| ExportError::Buffer(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::{grade::GradeItems, transcript::TranscriptCourse};
+
+    #[test]
+    fn test_render_csv() {
+        let transcript = Transcript {
+            courses: vec![TranscriptCourse {
+                courseid: 1,
+                course_name: "Math, advanced".to_string(),
+                total: Some("91.00".to_string()),
+                items: vec![GradeItems {
+                    id: 5,
+                    itemname: "Midterm".to_string(),
+                    percentageformatted: "95.00 %".to_string(),
+                }],
+            }],
+        };
+        let csv = String::from_utf8(render_csv(&transcript).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "course_id,course,item_id,item,grade,course_total\n\
+             1,\"Math, advanced\",5,Midterm,95.00 %,91.00\n"
+        );
+    }
+
+    #[test]
+    fn test_render_csv_quotes_formulas() {
+        let transcript = Transcript {
+            courses: vec![TranscriptCourse {
+                courseid: 1,
+                course_name: "=HYPERLINK(\"http://evil\")".to_string(),
+                total: Some("-".to_string()),
+                items: vec![GradeItems {
+                    id: 5,
+                    itemname: "@SUM(A1)".to_string(),
+                    percentageformatted: "+1".to_string(),
+                }],
+            }],
+        };
+        let csv = String::from_utf8(render_csv(&transcript).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "course_id,course,item_id,item,grade,course_total\n\
+             1,\"'=HYPERLINK(\"\"http://evil\"\")\",5,'@SUM(A1),'+1,'-\n"
+        );
+    }
+}
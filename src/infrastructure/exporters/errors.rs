@@ -0,0 +1,27 @@
+use thiserror::Error;
+
+use crate::domain::entities::errors::ServiceError;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("CSV error: `{0}`")]
+    Csv(#[from] csv::Error),
+
+    #[error("XLSX error: `{0}`")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+
+    #[error("PDF error: `{0}`")]
+    Pdf(#[from] printpdf::Error),
+
+    #[error("Font error: `{0}`")]
+    Font(#[from] ttf_parser::FaceParsingError),
+
+    #[error("Buffer error: `{0}`")]
+    Buffer(String),
+}
+
+impl From<ExportError> for ServiceError {
+    fn from(value: ExportError) -> Self {
+        Self::Export(value.to_string())
+    }
+}
@@ -0,0 +1,4 @@
+pub mod csv_exporter;
+pub mod errors;
+pub mod pdf_exporter;
+pub mod xlsx_exporter;
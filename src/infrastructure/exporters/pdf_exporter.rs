@@ -0,0 +1,362 @@
+use chrono::{DateTime, Utc};
+use printpdf::{IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference};
+use ttf_parser::Face;
+
+use crate::domain::entities::{transcript::Transcript, user::User};
+
+use super::errors::ExportError;
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const LINE_HEIGHT: f32 = 6.0;
+const GRADE_COLUMN: f32 = 160.0;
+/// Space kept free between a name and its grade.
+const COLUMN_GAP: f32 = 4.0;
+const ITEM_INDENT: f32 = 6.0;
+const MM_PER_POINT: f32 = 25.4 / 72.0;
+
+/// DejaVu Sans, since the builtin PDF fonts only cover Latin-1 and names are mostly Cyrillic
+/// or Kazakh. See `assets/fonts/LICENSE`.
+const REGULAR_FONT: &[u8] = include_bytes!("../../../assets/fonts/DejaVuSans.ttf");
+const BOLD_FONT: &[u8] = include_bytes!("../../../assets/fonts/DejaVuSans-Bold.ttf");
+
+/// Width of `text` in millimeters.
+fn text_width(face: &Face, text: &str, size: f32) -> f32 {
+    let units: u32 = text
+        .chars()
+        .filter_map(|c| face.glyph_index(c))
+        .filter_map(|glyph| face.glyph_hor_advance(glyph))
+        .map(u32::from)
+        .sum();
+    units as f32 / f32::from(face.units_per_em()) * size * MM_PER_POINT
+}
+
+/// Breaks `text` into lines of at most `width` millimeters, between words where possible.
+fn wrap(face: &Face, text: &str, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let joined = if line.is_empty() {
+            word.to_owned()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if text_width(face, &joined, size) <= width {
+            line = joined;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            if line.chars().count() > 1 && text_width(face, &line, size) > width {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, c.to_string()));
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+struct Writer {
+    document: PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    regular_face: Face<'static>,
+    bold_face: Face<'static>,
+    y: f32,
+}
+
+impl Writer {
+    fn line(&mut self, left: &str, right: Option<&str>, size: f32, bold: bool) {
+        self.indented_line(0.0, left, right, size, bold);
+    }
+
+    /// Writes `left` wrapped before the grade column, or the right margin without `right`,
+    /// and `right` next to its first line.
+    fn indented_line(
+        &mut self,
+        indent: f32,
+        left: &str,
+        right: Option<&str>,
+        size: f32,
+        bold: bool,
+    ) {
+        let end = match right {
+            Some(_) => GRADE_COLUMN - COLUMN_GAP,
+            None => PAGE_WIDTH - MARGIN,
+        };
+        let face = if bold {
+            &self.bold_face
+        } else {
+            &self.regular_face
+        };
+        let lines = wrap(face, left, size, end - MARGIN - indent);
+        for (index, text) in lines.iter().enumerate() {
+            if self.y < MARGIN {
+                let (page, layer) =
+                    self.document
+                        .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Transcript");
+                self.layer = self.document.get_page(page).get_layer(layer);
+                self.y = PAGE_HEIGHT - MARGIN;
+            }
+            let font = if bold { &self.bold } else { &self.regular };
+            self.layer
+                .use_text(text, size, Mm(MARGIN + indent), Mm(self.y), font);
+            if let (0, Some(right)) = (index, right) {
+                self.layer
+                    .use_text(right, size, Mm(GRADE_COLUMN), Mm(self.y), font);
+            }
+            self.y -= LINE_HEIGHT * size / 10.0;
+        }
+    }
+
+    fn gap(&mut self) {
+        self.y -= LINE_HEIGHT / 2.0;
+    }
+}
+
+/// Simple "unofficial transcript": every course with its total and grade items.
+pub fn render_pdf(
+    user: &User,
+    transcript: &Transcript,
+    generated_at: DateTime<Utc>,
+) -> Result<Vec<u8>, ExportError> {
+    let (document, page, layer) = PdfDocument::new(
+        "Unofficial transcript",
+        Mm(PAGE_WIDTH),
+        Mm(PAGE_HEIGHT),
+        "Transcript",
+    );
+    let regular = document.add_external_font(REGULAR_FONT)?;
+    let bold = document.add_external_font(BOLD_FONT)?;
+    let layer = document.get_page(page).get_layer(layer);
+
+    let mut writer = Writer {
+        document,
+        layer,
+        regular,
+        bold,
+        regular_face: Face::parse(REGULAR_FONT, 0)?,
+        bold_face: Face::parse(BOLD_FONT, 0)?,
+        y: PAGE_HEIGHT - MARGIN,
+    };
+
+    writer.line("Unofficial transcript", None, 18.0, true);
+    writer.gap();
+    writer.line(&format!("Student: {}", user.fullname), None, 11.0, false);
+    writer.line(&format!("Login: {}", user.username), None, 11.0, false);
+    writer.line(
+        &format!("Generated: {} UTC", generated_at.format("%Y-%m-%d %H:%M")),
+        None,
+        11.0,
+        false,
+    );
+    writer.line(
+        "Not an official document, grades are copied from Moodle.",
+        None,
+        9.0,
+        false,
+    );
+
+    for course in &transcript.courses {
+        writer.gap();
+        writer.line(
+            &course.course_name,
+            Some(course.total.as_deref().unwrap_or("-")),
+            12.0,
+            true,
+        );
+        for item in &course.items {
+            writer.indented_line(
+                ITEM_INDENT,
+                &item.itemname,
+                Some(&item.percentageformatted),
+                10.0,
+                false,
+            );
+        }
+    }
+
+    Ok(writer.document.save_to_bytes()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lopdf::{content::Content, Dictionary, Object};
+    use regex::Regex;
+
+    use super::*;
+    use crate::domain::entities::{grade::GradeItems, transcript::TranscriptCourse};
+
+    /// Text shown on the first page, decoded through the fonts' `ToUnicode` maps.
+    fn first_page_text(bytes: &[u8]) -> String {
+        let mut document = lopdf::Document::load_mem(bytes).unwrap();
+        document.decompress();
+        let page = document.get_pages()[&1];
+        let dictionary = |object: &Object| -> Dictionary {
+            document
+                .dereference(object)
+                .and_then(|(_, object)| object.as_dict())
+                .unwrap()
+                .clone()
+        };
+
+        let bfchar = Regex::new(r"<([0-9a-f]{4})> <([0-9a-f]{4,})>").unwrap();
+        let resources = dictionary(
+            document
+                .get_dictionary(page)
+                .unwrap()
+                .get(b"Resources")
+                .unwrap(),
+        );
+        let mut glyphs: HashMap<Vec<u8>, HashMap<u16, char>> = HashMap::new();
+        for (name, font) in dictionary(resources.get(b"Font").unwrap()).iter() {
+            let to_unicode = dictionary(font).get(b"ToUnicode").unwrap().clone();
+            let (_, stream) = document.dereference(&to_unicode).unwrap();
+            let cmap = String::from_utf8_lossy(&stream.as_stream().unwrap().content).into_owned();
+            let map = bfchar
+                .captures_iter(&cmap)
+                .map(|pair| {
+                    let glyph = u16::from_str_radix(&pair[1], 16).unwrap();
+                    let unicode = u32::from_str_radix(&pair[2], 16).unwrap();
+                    (glyph, char::from_u32(unicode).unwrap())
+                })
+                .collect();
+            glyphs.insert(name.clone(), map);
+        }
+
+        let content = Content::decode(&document.get_page_content(page).unwrap()).unwrap();
+        let mut font = Vec::new();
+        let mut text = String::new();
+        for operation in content.operations {
+            match (operation.operator.as_str(), operation.operands.as_slice()) {
+                ("Tf", [Object::Name(name), ..]) => font = name.clone(),
+                ("Tj", [Object::String(bytes, _)]) => {
+                    // Glyphs without an outline, like the space, are missing from the map.
+                    text.extend(bytes.chunks(2).map(|glyph| {
+                        let glyph = u16::from_be_bytes([glyph[0], glyph[1]]);
+                        glyphs[&font].get(&glyph).copied().unwrap_or(' ')
+                    }));
+                    text.push('\n');
+                }
+                _ => {}
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn test_render_pdf_keeps_cyrillic() {
+        let user = User {
+            username: "student".to_string(),
+            fullname: "Әлия Сәрсенова".to_string(),
+            userid: 1,
+        };
+        let transcript = Transcript {
+            courses: vec![TranscriptCourse {
+                courseid: 1,
+                course_name: "Математический анализ".to_string(),
+                total: Some("91,00".to_string()),
+                items: vec![GradeItems {
+                    id: 1,
+                    itemname: "Контрольная работа".to_string(),
+                    percentageformatted: "95,00 %".to_string(),
+                }],
+            }],
+        };
+
+        let bytes = render_pdf(&user, &transcript, Utc::now()).unwrap();
+        let text = first_page_text(&bytes);
+        assert!(text.contains("Student: Әлия Сәрсенова"), "{}", text);
+        assert!(text.contains("Математический анализ"), "{}", text);
+        assert!(text.contains("Контрольная работа"), "{}", text);
+        assert!(!text.contains('?'), "{}", text);
+    }
+
+    #[test]
+    fn test_wrap_keeps_names_left_of_the_grade_column() {
+        let face = Face::parse(REGULAR_FONT, 0).unwrap();
+        let width = GRADE_COLUMN - COLUMN_GAP - MARGIN;
+        let name = "Теория вероятностей и математическая статистика для инженеров \
+                    информационных систем, продвинутый курс";
+        let lines = wrap(&face, name, 12.0, width);
+        assert!(lines.len() > 1, "{:?}", lines);
+        assert!(lines
+            .iter()
+            .all(|line| text_width(&face, line, 12.0) <= width));
+        assert_eq!(
+            lines.join(" "),
+            name.split_whitespace().collect::<Vec<_>>().join(" ")
+        );
+
+        let word = "x".repeat(200);
+        let lines = wrap(&face, &word, 12.0, width);
+        assert!(lines.len() > 1);
+        assert!(lines
+            .iter()
+            .all(|line| text_width(&face, line, 12.0) <= width));
+        assert_eq!(lines.concat(), word);
+    }
+
+    #[test]
+    fn test_render_pdf_wraps_long_names() {
+        let user = User {
+            username: "student".to_string(),
+            fullname: "Test Student".to_string(),
+            userid: 1,
+        };
+        let course_name = "Introduction to the Theory of Computation and Formal \
+                           Languages for Engineers, Advanced Track";
+        let transcript = Transcript {
+            courses: vec![TranscriptCourse {
+                courseid: 1,
+                course_name: course_name.to_string(),
+                total: Some("91.00".to_string()),
+                items: Vec::new(),
+            }],
+        };
+
+        let bytes = render_pdf(&user, &transcript, Utc::now()).unwrap();
+        let text = first_page_text(&bytes);
+        assert!(!text.contains(course_name), "{}", text);
+        assert!(text.contains("Introduction to the Theory"), "{}", text);
+        assert!(text.contains("Advanced Track"), "{}", text);
+        assert!(text.contains("91.00"), "{}", text);
+    }
+
+    #[test]
+    fn test_render_pdf_paginates() {
+        let user = User {
+            username: "student".to_string(),
+            fullname: "Test Student".to_string(),
+            userid: 1,
+        };
+        let items = (0..80)
+            .map(|id| GradeItems {
+                id,
+                itemname: format!("Quiz {}", id),
+                percentageformatted: "100.00 %".to_string(),
+            })
+            .collect();
+        let transcript = Transcript {
+            courses: vec![TranscriptCourse {
+                courseid: 1,
+                course_name: "Math".to_string(),
+                total: Some("99.00".to_string()),
+                items,
+            }],
+        };
+
+        let bytes = render_pdf(&user, &transcript, Utc::now()).unwrap();
+        assert!(bytes.starts_with(b"%PDF"));
+    }
+}
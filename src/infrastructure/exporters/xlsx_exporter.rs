@@ -0,0 +1,77 @@
+use rust_xlsxwriter::{Format, Workbook};
+
+use crate::domain::entities::transcript::Transcript;
+
+use super::errors::ExportError;
+
+/// Workbook with a "Grades" sheet of all items and a "Course totals" sheet.
+pub fn render_xlsx(transcript: &Transcript) -> Result<Vec<u8>, ExportError> {
+    let mut workbook = Workbook::new();
+    let header = Format::new().set_bold();
+
+    let grades = workbook.add_worksheet().set_name("Grades")?;
+    for (col, title) in ["Course", "Item", "Grade"].iter().enumerate() {
+        grades.write_string_with_format(0, col as u16, *title, &header)?;
+    }
+    let mut row = 1;
+    for course in &transcript.courses {
+        for item in &course.items {
+            grades.write_string(row, 0, &course.course_name)?;
+            grades.write_string(row, 1, &item.itemname)?;
+            grades.write_string(row, 2, &item.percentageformatted)?;
+            row += 1;
+        }
+    }
+    grades.autofit();
+
+    let totals = workbook.add_worksheet().set_name("Course totals")?;
+    for (col, title) in ["Course", "Total"].iter().enumerate() {
+        totals.write_string_with_format(0, col as u16, *title, &header)?;
+    }
+    for (index, course) in transcript.courses.iter().enumerate() {
+        let row = index as u32 + 1;
+        totals.write_string(row, 0, &course.course_name)?;
+        match course.total.as_deref() {
+            Some(total) => match parse_grade(total) {
+                Some(number) => totals.write_number(row, 1, number)?,
+                None => totals.write_string(row, 1, total)?,
+            },
+            None => totals.write_string(row, 1, "-")?,
+        };
+    }
+    totals.autofit();
+
+    Ok(workbook.save_to_buffer()?)
+}
+
+/// Moodle formats totals as "88.50" or "88,50" depending on the user's locale.
+fn parse_grade(grade: &str) -> Option<f64> {
+    grade.trim().replace(',', ".").parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::transcript::TranscriptCourse;
+
+    #[test]
+    fn test_parse_grade() {
+        assert_eq!(parse_grade("88.50"), Some(88.5));
+        assert_eq!(parse_grade("88,50"), Some(88.5));
+        assert_eq!(parse_grade("-"), None);
+    }
+
+    #[test]
+    fn test_render_xlsx_is_zip() {
+        let transcript = Transcript {
+            courses: vec![TranscriptCourse {
+                courseid: 1,
+                course_name: "Math".to_string(),
+                total: Some("91,00".to_string()),
+                items: vec![],
+            }],
+        };
+        let bytes = render_xlsx(&transcript).unwrap();
+        assert_eq!(&bytes[..2], b"PK");
+    }
+}
@@ -0,0 +1 @@
+pub mod redis_live_update_bus;
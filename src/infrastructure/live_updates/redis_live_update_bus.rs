@@ -0,0 +1,166 @@
+use std::{collections::HashSet, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use log::warn;
+use redis::{
+    aio::{ConnectionManager, PubSubSink},
+    AsyncCommands, Client, Msg,
+};
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_providers::live_update_bus_abstract::{BusError, LiveUpdateBusAbstract},
+    entities::change_event::ChangeEvent,
+};
+
+const NAMESPACE: &str = "aitu-keeper:live:";
+const TICKETS: &str = "aitu-keeper:ticket:";
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+fn namespaced(channel: &str) -> String {
+    format!("{}{}", NAMESPACE, channel)
+}
+
+/// Live update bus over Redis pub/sub, one channel per streamed user.
+///
+/// Redis drops the subscriptions of an instance that goes away, so `PUBSUB NUMSUB` only
+/// counts instances that still stream the user.
+pub struct RedisLiveUpdateBus {
+    client: Client,
+    connection: ConnectionManager,
+    /// Sink of the current subscriber connection, `None` until [`RedisLiveUpdateBus::run`]
+    /// has connected.
+    sink: RwLock<Option<PubSubSink>>,
+    /// Channels to listen on, subscribed again after a reconnect.
+    channels: Mutex<HashSet<String>>,
+}
+
+impl std::fmt::Debug for RedisLiveUpdateBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisLiveUpdateBus").finish_non_exhaustive()
+    }
+}
+
+impl RedisLiveUpdateBus {
+    pub async fn connect(url: &str) -> Result<Self, BusError> {
+        let client = Client::open(url)?;
+        let connection = ConnectionManager::new(client.clone()).await?;
+        Ok(Self {
+            client,
+            connection,
+            sink: RwLock::new(None),
+            channels: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Hands the events of the listened channels to `deliver` for as long as the process runs,
+    /// reconnecting and listening again whenever the subscriber connection drops.
+    pub async fn run<Deliver>(&self, deliver: Deliver)
+    where
+        Deliver: Fn(&str, ChangeEvent) + Send,
+    {
+        loop {
+            match self.client.get_async_pubsub().await {
+                Ok(pubsub) => {
+                    let (sink, mut messages) = pubsub.split();
+                    if self.resubscribe(sink).await {
+                        while let Some(message) = messages.next().await {
+                            match decode(&message) {
+                                Ok((channel, event)) => deliver(channel, event),
+                                Err(e) => warn!("Dropping unreadable live update: {}", e),
+                            }
+                        }
+                        *self.sink.write().await = None;
+                        warn!("Live update bus disconnected, reconnecting");
+                    }
+                }
+                Err(e) => warn!("Failed to connect the live update bus: {}", e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    /// Listens on every known channel through `sink` and makes it the current one. Holding the
+    /// sink lock keeps channels added meanwhile from being missed.
+    async fn resubscribe(&self, mut sink: PubSubSink) -> bool {
+        let mut current = self.sink.write().await;
+        let channels: Vec<String> = self.channels.lock().unwrap().iter().cloned().collect();
+        for channel in channels {
+            if let Err(e) = sink.subscribe(namespaced(&channel)).await {
+                warn!("Failed to listen for live updates: {}", e);
+                return false;
+            }
+        }
+        *current = Some(sink);
+        true
+    }
+}
+
+fn decode(message: &Msg) -> Result<(&str, ChangeEvent), BusError> {
+    let channel = message
+        .get_channel_name()
+        .strip_prefix(NAMESPACE)
+        .ok_or("foreign channel")?;
+    let payload: String = message.get_payload()?;
+    Ok((channel, serde_json::from_str(&payload)?))
+}
+
+#[async_trait]
+impl LiveUpdateBusAbstract for RedisLiveUpdateBus {
+    async fn listen(&self, channel: &str) -> Result<(), BusError> {
+        self.channels.lock().unwrap().insert(channel.to_owned());
+        // Without a connection the channel is subscribed once `run` reconnects.
+        if let Some(mut sink) = self.sink.read().await.clone() {
+            sink.subscribe(namespaced(channel)).await?;
+        }
+        Ok(())
+    }
+
+    async fn unlisten(&self, channel: &str) -> Result<(), BusError> {
+        self.channels.lock().unwrap().remove(channel);
+        if let Some(mut sink) = self.sink.read().await.clone() {
+            sink.unsubscribe(namespaced(channel)).await?;
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, channel: &str, event: &ChangeEvent) -> Result<(), BusError> {
+        let mut connection = self.connection.clone();
+        let _: i64 = connection
+            .publish(namespaced(channel), serde_json::to_string(event)?)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_listened(&self, channel: &str) -> Result<bool, BusError> {
+        let mut connection = self.connection.clone();
+        let counts: Vec<(String, i64)> = redis::cmd("PUBSUB")
+            .arg("NUMSUB")
+            .arg(namespaced(channel))
+            .query_async(&mut connection)
+            .await?;
+        Ok(counts.first().is_some_and(|(_, count)| *count > 0))
+    }
+
+    async fn store_ticket(&self, ticket: &str, token: &str, ttl: Duration) -> Result<(), BusError> {
+        let mut connection = self.connection.clone();
+        let _: () = connection
+            .set_ex(
+                format!("{}{}", TICKETS, ticket),
+                token,
+                ttl.as_secs().max(1),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn take_ticket(&self, ticket: &str) -> Result<Option<String>, BusError> {
+        let mut connection = self.connection.clone();
+        let token: Option<String> = redis::cmd("GETDEL")
+            .arg(format!("{}{}", TICKETS, ticket))
+            .query_async(&mut connection)
+            .await?;
+        Ok(token)
+    }
+}
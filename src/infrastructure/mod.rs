@@ -1,5 +1,8 @@
 pub mod app_setup;
+pub mod cache;
 pub mod data_providers;
 pub mod db;
+pub mod exporters;
+pub mod live_updates;
 pub mod notification_provider;
 pub mod repositories;
@@ -1 +1,2 @@
 pub mod firebase_messages_client;
+pub mod recording_notification_provider;
@@ -0,0 +1,187 @@
+use std::{collections::VecDeque, error::Error, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use fcm_rs::models::{Message, Notification};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::domain::data_providers::notification_provider_abstract::NotificationProviderAbstract;
+
+/// Pushes kept by default, older ones are dropped first.
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+/// A push as it would have been sent to Firebase.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordedPush {
+    pub device_token: Option<String>,
+    pub title: String,
+    pub body: String,
+    pub data: Option<Value>,
+    /// Unix seconds.
+    pub sent_at: i64,
+}
+
+/// Keeps pushes in memory instead of sending them, for tests and `NOTIFICATIONS=recording`.
+#[derive(Debug)]
+pub struct RecordingNotificationProvider {
+    capacity: usize,
+    pushes: Mutex<VecDeque<RecordedPush>>,
+}
+
+impl Default for RecordingNotificationProvider {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl RecordingNotificationProvider {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            pushes: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Recorded pushes, oldest first.
+    pub fn pushes(&self) -> Vec<RecordedPush> {
+        self.lock().iter().cloned().collect()
+    }
+
+    pub fn pushes_to(&self, device_token: &str) -> Vec<RecordedPush> {
+        self.lock()
+            .iter()
+            .filter(|push| push.device_token.as_deref() == Some(device_token))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the recorded pushes and starts over.
+    pub fn take(&self) -> Vec<RecordedPush> {
+        self.lock().drain(..).collect()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Panics unless exactly `count` pushes were recorded.
+    #[track_caller]
+    pub fn assert_count(&self, count: usize) {
+        let pushes = self.pushes();
+        assert_eq!(
+            pushes.len(),
+            count,
+            "expected {} pushes, recorded {:#?}",
+            count,
+            pushes
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_none_sent(&self) {
+        self.assert_count(0);
+    }
+
+    /// Returns the first push titled `title`, panics when there is none.
+    #[track_caller]
+    pub fn assert_sent(&self, title: &str) -> RecordedPush {
+        let pushes = self.pushes();
+        match pushes.iter().find(|push| push.title == title) {
+            Some(push) => push.clone(),
+            None => panic!("no push titled {:?}, recorded {:#?}", title, pushes),
+        }
+    }
+
+    /// Panics unless the pushes carry exactly these titles, in order.
+    #[track_caller]
+    pub fn assert_titles(&self, titles: &[&str]) {
+        let pushes = self.pushes();
+        let recorded: Vec<&str> = pushes.iter().map(|push| push.title.as_str()).collect();
+        assert_eq!(recorded, titles, "recorded {:#?}", pushes);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<RecordedPush>> {
+        self.pushes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl NotificationProviderAbstract for RecordingNotificationProvider {
+    async fn send_notification(&self, message: Message) -> Result<(), Box<dyn Error>> {
+        let (title, body) = message
+            .notification
+            .map(|notification| (notification.title, notification.body))
+            .unwrap_or_default();
+        let mut pushes = self.lock();
+        if pushes.len() == self.capacity {
+            pushes.pop_front();
+        }
+        pushes.push_back(RecordedPush {
+            device_token: message.token,
+            title: title.unwrap_or_default(),
+            body: body.unwrap_or_default(),
+            data: message.data,
+            sent_at: Utc::now().timestamp(),
+        });
+        Ok(())
+    }
+
+    fn create_message(&self, device_token: &str, title: &str, body: &str) -> Message {
+        Message {
+            data: None,
+            token: Some(device_token.to_owned()),
+            notification: Some(Notification {
+                title: Some(title.to_owned()),
+                body: Some(body.to_owned()),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    async fn send(provider: &RecordingNotificationProvider, device: &str, title: &str) {
+        let mut message = provider.create_message(device, title, "body");
+        message.data = Some(json!({"kind": "grade"}));
+        provider.send_notification(message).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_records_pushes() {
+        let provider = RecordingNotificationProvider::default();
+        provider.assert_none_sent();
+
+        send(&provider, "a", "New grade").await;
+        send(&provider, "b", "New deadline").await;
+
+        provider.assert_count(2);
+        provider.assert_titles(&["New grade", "New deadline"]);
+        let grade = provider.assert_sent("New grade");
+        assert_eq!(grade.device_token.as_deref(), Some("a"));
+        assert_eq!(grade.data, Some(json!({"kind": "grade"})));
+        assert_eq!(provider.pushes_to("b").len(), 1);
+
+        assert_eq!(provider.take().len(), 2);
+        provider.assert_none_sent();
+    }
+
+    #[tokio::test]
+    async fn test_drops_oldest_beyond_capacity() {
+        let provider = RecordingNotificationProvider::new(2);
+        for title in ["1", "2", "3"] {
+            send(&provider, "a", title).await;
+        }
+        provider.assert_titles(&["2", "3"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no push titled")]
+    fn test_assert_sent_panics_without_match() {
+        RecordingNotificationProvider::default().assert_sent("New grade");
+    }
+}
@@ -0,0 +1,425 @@
+use std::{future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::domain::entities::course::Course;
+use crate::domain::entities::deadline::Deadline;
+use crate::domain::entities::grade::{Grade, GradeOverview, GradesOverview};
+use crate::domain::entities::query::{CourseQuery, DeadlineQuery, GradeQuery, Page};
+use crate::domain::entities::registration::Registration;
+use crate::domain::entities::sync_cursor::SyncCursor;
+use crate::domain::entities::token::{token_digest, Token};
+use crate::domain::entities::user::User;
+use crate::domain::repositories::data_repository_abstract::{
+    CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
+    TokenRepositoryAbstract, UserRepositoryAbstract,
+};
+use crate::infrastructure::cache::cache_abstract::CacheAbstract;
+
+use super::errors::DbError;
+
+const USER: &str = "user";
+const COURSES: &str = "courses";
+const GRADES: &str = "grades";
+const GRADES_OVERVIEW: &str = "grades_overview";
+const DEADLINES: &str = "deadlines";
+
+/// Keys carry the token digest, the cache never sees raw tokens.
+fn key(kind: &str, token: &str) -> String {
+    format!("{}:{}", kind, token_digest(token))
+}
+
+fn all_keys(token: &str) -> Vec<String> {
+    [USER, COURSES, GRADES, GRADES_OVERVIEW, DEADLINES]
+        .iter()
+        .map(|kind| key(kind, token))
+        .collect()
+}
+
+/// Read-through cache in front of the `find_*_by_token` methods.
+///
+/// Every write through this repository drops the entries it touches before and after writing,
+/// so a read that loaded the old data while the write ran can't keep it cached. The cache TTL
+/// bounds staleness for writes made elsewhere. Cache failures are logged and fall through to
+/// `inner`.
+#[derive(Debug)]
+pub struct CachedRepository<Repository> {
+    inner: Repository,
+    cache: Option<Arc<dyn CacheAbstract>>,
+}
+
+impl<Repository> CachedRepository<Repository> {
+    pub fn new(inner: Repository, cache: Option<Arc<dyn CacheAbstract>>) -> Self {
+        Self { inner, cache }
+    }
+
+    pub fn inner(&self) -> &Repository {
+        &self.inner
+    }
+
+    async fn cached<T, Load>(&self, key: String, load: Load) -> Result<T, DbError>
+    where
+        T: Serialize + DeserializeOwned,
+        Load: Future<Output = Result<T, DbError>>,
+    {
+        let Some(cache) = &self.cache else {
+            return load.await;
+        };
+
+        match cache.get(&key).await {
+            Ok(Some(value)) => match serde_json::from_str(&value) {
+                Ok(value) => return Ok(value),
+                Err(e) => warn!("Dropping unreadable cache entry {}: {}", key, e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Cache read failed: {}", e),
+        }
+
+        let value = load.await?;
+        match serde_json::to_string(&value) {
+            Ok(json) => {
+                if let Err(e) = cache.set(&key, json).await {
+                    warn!("Cache write failed: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to serialize cache entry {}: {}", key, e),
+        }
+        Ok(value)
+    }
+
+    /// Runs `write` between two invalidations of `keys`.
+    async fn written<T, Write>(&self, keys: Vec<String>, write: Write) -> Result<T, DbError>
+    where
+        Write: Future<Output = Result<T, DbError>>,
+    {
+        self.invalidate(&keys).await;
+        let result = write.await;
+        self.invalidate(&keys).await;
+        result
+    }
+
+    async fn invalidate(&self, keys: &[String]) {
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.delete(keys).await {
+                warn!("Cache invalidation failed: {}", e);
+            }
+        }
+    }
+
+    async fn invalidate_kind(&self, kind: &str) {
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.delete_prefix(&format!("{}:", kind)).await {
+                warn!("Cache invalidation failed: {}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<Repository: TokenRepositoryAbstract> TokenRepositoryAbstract for CachedRepository<Repository> {
+    async fn find_token(&self, token: &Token) -> Result<(), DbError> {
+        self.inner.find_token(token).await
+    }
+
+    async fn save_tokens(&self, token: &Token) -> Result<(), DbError> {
+        self.written(all_keys(&token.token), self.inner.save_tokens(token))
+            .await
+    }
+
+    async fn find_all_device_tokens(&self, limit: i64, skip: u64) -> Result<Vec<Token>, DbError> {
+        self.inner.find_all_device_tokens(limit, skip).await
+    }
+
+    async fn delete(&self, token: &str) -> Result<(), DbError> {
+        self.written(all_keys(token), self.inner.delete(token))
+            .await
+    }
+
+    async fn save_feed_token(&self, token: &str, feed_token: &str) -> Result<(), DbError> {
+        self.inner.save_feed_token(token, feed_token).await
+    }
+
+    async fn find_token_by_feed_token(&self, feed_token: &str) -> Result<String, DbError> {
+        self.inner.find_token_by_feed_token(feed_token).await
+    }
+
+    async fn delete_feed_token(&self, token: &str) -> Result<(), DbError> {
+        self.inner.delete_feed_token(token).await
+    }
+
+    async fn save_registration(&self, registration: &Registration) -> Result<(), DbError> {
+        self.written(
+            all_keys(&registration.token.token),
+            self.inner.save_registration(registration),
+        )
+        .await
+    }
+
+    async fn claim_due_tokens(
+        &self,
+        worker: &str,
+        now: i64,
+        lease_until: i64,
+        limit: i64,
+    ) -> Result<Vec<Token>, DbError> {
+        self.inner
+            .claim_due_tokens(worker, now, lease_until, limit)
+            .await
+    }
+
+    async fn renew_lease(
+        &self,
+        token: &str,
+        worker: &str,
+        lease_until: i64,
+    ) -> Result<bool, DbError> {
+        self.inner.renew_lease(token, worker, lease_until).await
+    }
+
+    async fn schedule_poll(
+        &self,
+        token: &str,
+        worker: &str,
+        next_poll_at: i64,
+    ) -> Result<(), DbError> {
+        self.inner.schedule_poll(token, worker, next_poll_at).await
+    }
+
+    async fn suspend(&self, token: &str, suspended_at: i64) -> Result<bool, DbError> {
+        self.inner.suspend(token, suspended_at).await
+    }
+
+    async fn find_tenant(&self, token: &str) -> Result<Option<String>, DbError> {
+        self.inner.find_tenant(token).await
+    }
+
+    async fn relink(&self, old: &str, new: &str) -> Result<(), DbError> {
+        let mut keys = all_keys(old);
+        keys.extend(all_keys(new));
+        self.written(keys, self.inner.relink(old, new)).await
+    }
+}
+
+#[async_trait]
+impl<Repository: UserRepositoryAbstract> UserRepositoryAbstract for CachedRepository<Repository> {
+    async fn find_user_by_token(&self, token: &str) -> Result<User, DbError> {
+        self.cached(key(USER, token), self.inner.find_user_by_token(token))
+            .await
+    }
+
+    async fn save_user(&self, user: &User, token: &str) -> Result<(), DbError> {
+        self.written(vec![key(USER, token)], self.inner.save_user(user, token))
+            .await
+    }
+}
+
+#[async_trait]
+impl<Repository: CourseRepositoryAbstract> CourseRepositoryAbstract
+    for CachedRepository<Repository>
+{
+    async fn save_courses(&self, token: &str, courses: &[Course]) -> Result<(), DbError> {
+        self.written(
+            vec![key(COURSES, token)],
+            self.inner.save_courses(token, courses),
+        )
+        .await
+    }
+
+    async fn find_courses_by_token(&self, token: &str) -> Result<Vec<Course>, DbError> {
+        self.cached(key(COURSES, token), self.inner.find_courses_by_token(token))
+            .await
+    }
+
+    async fn find_courses_page(
+        &self,
+        token: &str,
+        query: &CourseQuery,
+    ) -> Result<Page<Course>, DbError> {
+        self.inner.find_courses_page(token, query).await
+    }
+
+    async fn find_sync_cursors(&self, token: &str) -> Result<Vec<SyncCursor>, DbError> {
+        self.inner.find_sync_cursors(token).await
+    }
+
+    async fn save_sync_cursors(&self, token: &str, cursors: &[SyncCursor]) -> Result<(), DbError> {
+        self.inner.save_sync_cursors(token, cursors).await
+    }
+}
+
+#[async_trait]
+impl<Repository: GradeRepositoryAbstract> GradeRepositoryAbstract for CachedRepository<Repository> {
+    async fn save_grades(&self, token: &str, grades: &[Grade]) -> Result<(), DbError> {
+        self.written(
+            vec![key(GRADES, token)],
+            self.inner.save_grades(token, grades),
+        )
+        .await
+    }
+
+    async fn find_grades_by_token(&self, token: &str) -> Result<Vec<Grade>, DbError> {
+        self.cached(key(GRADES, token), self.inner.find_grades_by_token(token))
+            .await
+    }
+
+    async fn find_grades_page(
+        &self,
+        token: &str,
+        query: &GradeQuery,
+    ) -> Result<Page<Grade>, DbError> {
+        self.inner.find_grades_page(token, query).await
+    }
+
+    async fn save_grades_overview(
+        &self,
+        token: &str,
+        grades_overview: &GradesOverview,
+    ) -> Result<(), DbError> {
+        self.written(
+            vec![key(GRADES_OVERVIEW, token)],
+            self.inner.save_grades_overview(token, grades_overview),
+        )
+        .await
+    }
+
+    async fn find_grades_overview_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Vec<GradeOverview>, DbError> {
+        self.cached(
+            key(GRADES_OVERVIEW, token),
+            self.inner.find_grades_overview_by_token(token),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<Repository: DeadlineRepositoryAbstract> DeadlineRepositoryAbstract
+    for CachedRepository<Repository>
+{
+    async fn save_deadlines(&self, token: &str, deadlines: &[Deadline]) -> Result<(), DbError> {
+        self.written(
+            vec![key(DEADLINES, token)],
+            self.inner.save_deadlines(token, deadlines),
+        )
+        .await
+    }
+
+    async fn find_deadlines_by_token(&self, token: &str) -> Result<Vec<Deadline>, DbError> {
+        self.cached(
+            key(DEADLINES, token),
+            self.inner.find_deadlines_by_token(token),
+        )
+        .await
+    }
+
+    async fn find_deadlines_page(
+        &self,
+        token: &str,
+        query: &DeadlineQuery,
+    ) -> Result<Page<Deadline>, DbError> {
+        self.inner.find_deadlines_page(token, query).await
+    }
+
+    async fn delete_expired_deadlines(&self, unix_date: u64) -> Result<(), DbError> {
+        self.invalidate_kind(DEADLINES).await;
+        let result = self.inner.delete_expired_deadlines(unix_date).await;
+        self.invalidate_kind(DEADLINES).await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, time::Duration};
+
+    use super::*;
+    use crate::infrastructure::{
+        cache::lru_cache::LruCache, repositories::in_memory_repository::InMemoryRepository,
+    };
+
+    fn course(id: i64) -> Course {
+        Course {
+            id,
+            fullname: "Math".to_string(),
+            enddate: 0,
+        }
+    }
+
+    async fn repository() -> CachedRepository<InMemoryRepository> {
+        let cache = LruCache::new(NonZeroUsize::new(16).unwrap(), Duration::from_secs(60));
+        let repository = CachedRepository::new(InMemoryRepository::new(), Some(Arc::new(cache)));
+        repository
+            .save_tokens(&Token::new("token".to_string(), None))
+            .await
+            .unwrap();
+        repository
+    }
+
+    #[tokio::test]
+    async fn test_reads_are_cached_until_save() {
+        let repository = repository().await;
+        repository
+            .save_courses("token", &[course(1)])
+            .await
+            .unwrap();
+        assert_eq!(
+            repository
+                .find_courses_by_token("token")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Bypassing the cache leaves the cached copy in place.
+        repository
+            .inner()
+            .save_courses("token", &[course(1), course(2)])
+            .await
+            .unwrap();
+        assert_eq!(
+            repository
+                .find_courses_by_token("token")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        repository
+            .save_courses("token", &[course(1), course(2), course(3)])
+            .await
+            .unwrap();
+        assert_eq!(
+            repository
+                .find_courses_by_token("token")
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_keys_do_not_contain_the_token() {
+        let key = key(COURSES, "moodle-token");
+        assert!(key.starts_with("courses:"));
+        assert!(!key.contains("moodle-token"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_drops_cached_entries() {
+        let repository = repository().await;
+        repository.find_courses_by_token("token").await.unwrap();
+
+        repository.delete("token").await.unwrap();
+        assert!(matches!(
+            repository.find_courses_by_token("token").await,
+            Err(DbError::DataNotFound(_))
+        ));
+    }
+}
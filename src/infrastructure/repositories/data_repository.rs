@@ -1,11 +1,24 @@
 use async_trait::async_trait;
-use futures::TryStreamExt;
+use chrono::Utc;
+use futures::{
+    future::{try_join_all, BoxFuture},
+    FutureExt, TryFutureExt, TryStreamExt,
+};
 use mongodb::bson::{doc, from_bson, to_bson, Bson, Document};
-use mongodb::{bson, Collection};
+use mongodb::options::IndexOptions;
+use mongodb::{bson, Client, ClientSession, Collection, Database, IndexModel};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Mutex;
 
 use crate::domain::entities::course::Course;
 use crate::domain::entities::deadline::Deadline;
 use crate::domain::entities::grade::{Grade, GradeOverview, GradesOverview};
+use crate::domain::entities::query::{
+    CourseQuery, CourseSort, Cursor, CursorValue, DeadlineQuery, DeadlineSort, GradeQuery,
+    GradeSort, GradeStatus, Page, PageQuery, SortOrder, UNGRADED,
+};
+use crate::domain::entities::registration::Registration;
+use crate::domain::entities::sync_cursor::SyncCursor;
 use crate::domain::entities::token::Token;
 use crate::domain::entities::user::User;
 use crate::domain::repositories::data_repository_abstract::{
@@ -13,19 +26,524 @@ use crate::domain::repositories::data_repository_abstract::{
     TokenRepositoryAbstract, UserRepositoryAbstract,
 };
 
+use crate::infrastructure::db::migrations::{self, SCHEMA_VERSION, SCHEMA_VERSION_FIELD};
+
 use super::errors::DbError;
 
-#[derive(Debug)]
+/// Arrays that were embedded in the `users` documents before each got its own collection.
+const EMBEDDED_ARRAYS: [&str; 4] = ["courses", "grades", "grades_overview", "deadlines"];
+
+/// Token, device token, feed token and profile stay in `users`, every other
+/// aggregate lives in its own collection with one document per user and entity id.
+#[derive(Debug, Clone)]
 pub struct DataRepository {
+    client: Client,
     collection: Collection<Document>,
+    courses: Collection<Document>,
+    grades: Collection<Document>,
+    grades_overview: Collection<Document>,
+    deadlines: Collection<Document>,
+    sync_cursors: Collection<Document>,
 }
 
 impl DataRepository {
-    pub fn new(collection: Collection<Document>) -> Self {
-        Self { collection }
+    pub fn new(db: &Database) -> Self {
+        Self {
+            client: db.client().clone(),
+            collection: db.collection("users"),
+            courses: db.collection("courses"),
+            grades: db.collection("grades"),
+            grades_overview: db.collection("grades_overview"),
+            deadlines: db.collection("deadlines"),
+            sync_cursors: db.collection("sync_cursors"),
+        }
+    }
+
+    /// Registrations and re-links are written in transactions, which a standalone server
+    /// rejects only once a user registers. Checked at startup to fail early instead.
+    pub async fn require_transactions(&self) -> Result<(), DbError> {
+        let hello = self
+            .client
+            .database("admin")
+            .run_command(doc! {"hello": 1})
+            .await?;
+        let replica_set = hello.contains_key("setName");
+        let sharded = hello.get_str("msg") == Ok("isdbgrid");
+        if replica_set || sharded {
+            Ok(())
+        } else {
+            Err(DbError::Storage(
+                "MongoDB runs as a standalone server, transactions need a replica set \
+                 (start mongod with --replSet and run rs.initiate())"
+                    .to_owned(),
+            ))
+        }
+    }
+
+    /// Runs `write` in a transaction. The driver retries it on `TransientTransactionError`,
+    /// e.g. a write conflict with a concurrent registration, and the commit on an unknown result.
+    async fn transaction<C, F>(&self, context: C, mut write: F) -> Result<(), DbError>
+    where
+        F: for<'b> FnMut(&'b mut ClientSession, &'b mut C) -> BoxFuture<'b, Result<(), DbError>>,
+    {
+        let mut session = self.client.start_session().await?;
+        session
+            .start_transaction()
+            .and_run(context, |session, context| {
+                write(session, context)
+                    .map_err(|e| match e {
+                        DbError::InternalError(e) => e,
+                        other => mongodb::error::Error::custom(Mutex::new(Some(other))),
+                    })
+                    .boxed()
+            })
+            .await
+            .map_err(|e| {
+                // Errors of our own travel through the driver as a custom payload.
+                let own = e
+                    .get_custom::<Mutex<Option<DbError>>>()
+                    .and_then(|own| own.lock().unwrap().take());
+                own.unwrap_or(DbError::InternalError(e))
+            })
+    }
+
+    pub async fn create_indexes(&self) -> Result<(), DbError> {
+        let feed_token_index = IndexModel::builder()
+            .keys(doc! {"feed_token": 1})
+            .options(IndexOptions::builder().unique(true).sparse(true).build())
+            .build();
+        self.collection.create_index(feed_token_index).await?;
+
+        for (collection, key) in [
+            (&self.courses, "id"),
+            (&self.grades, "courseid"),
+            (&self.grades_overview, "courseid"),
+            (&self.deadlines, "id"),
+            (&self.sync_cursors, "courseid"),
+        ] {
+            let entry_index = IndexModel::builder()
+                .keys(doc! {"token": 1, key: 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build();
+            collection.create_index(entry_index).await?;
+        }
+
+        let due_index = IndexModel::builder()
+            .keys(doc! {"timeusermidnight": 1})
+            .build();
+        self.deadlines.create_index(due_index).await?;
+
+        let poll_index = IndexModel::builder().keys(doc! {"next_poll_at": 1}).build();
+        self.collection.create_index(poll_index).await?;
+        Ok(())
+    }
+
+    /// Moves arrays still embedded in `users` documents into their collections.
+    /// Safe to run repeatedly, returns the number of converted users.
+    pub async fn split_embedded_arrays(&self) -> Result<u64, DbError> {
+        let filter = doc! {
+            "$or": EMBEDDED_ARRAYS
+                .iter()
+                .map(|array| doc! {*array: {"$exists": true}})
+                .collect::<Vec<_>>()
+        };
+        let mut cursor = self.collection.find(filter).await?;
+        let mut converted = 0;
+
+        while let Some(doc) = cursor.try_next().await? {
+            let token = doc.get_str("_id")?;
+            if let Ok(courses) = doc.get_array("courses") {
+                let courses: Vec<Course> = from_bson(Bson::from(courses))?;
+                self.save_courses(token, &courses).await?;
+            }
+            if let Ok(grades) = doc.get_array("grades") {
+                let grades: Vec<Grade> = from_bson(Bson::from(grades))?;
+                self.save_grades(token, &grades).await?;
+            }
+            if let Ok(grades) = doc.get_array("grades_overview") {
+                let grades: Vec<GradeOverview> = from_bson(Bson::from(grades))?;
+                self.save_grades_overview(token, &GradesOverview { grades })
+                    .await?;
+            }
+            if let Ok(deadlines) = doc.get_array("deadlines") {
+                let deadlines: Vec<Deadline> = from_bson(Bson::from(deadlines))?;
+                self.save_deadlines(token, &deadlines).await?;
+            }
+
+            let mut unset = Document::new();
+            for array in EMBEDDED_ARRAYS {
+                unset.insert(array, "");
+            }
+            self.collection
+                .update_one(doc! {"_id": token}, doc! {"$unset": unset})
+                .await?;
+            converted += 1;
+        }
+        Ok(converted)
+    }
+
+    /// Upgrades every document older than [`SCHEMA_VERSION`], returns the number upgraded.
+    /// Reads upgrade lazily as well, so this only has to finish before old builds are gone.
+    pub async fn migrate_documents(&self) -> Result<u64, DbError> {
+        let mut upgraded = 0;
+        for collection in [
+            &self.collection,
+            &self.courses,
+            &self.grades,
+            &self.grades_overview,
+            &self.deadlines,
+            &self.sync_cursors,
+        ] {
+            let mut cursor = collection.find(outdated(None)).await?;
+            while let Some(mut doc) = cursor.try_next().await? {
+                if self.write_upgrade(collection, &mut doc).await? {
+                    upgraded += 1;
+                }
+            }
+        }
+        Ok(upgraded)
+    }
+
+    /// Upgrades `doc` and stores it, unless someone else already replaced it.
+    /// Returns whether `doc` needed an upgrade.
+    async fn write_upgrade(
+        &self,
+        collection: &Collection<Document>,
+        doc: &mut Document,
+    ) -> Result<bool, DbError> {
+        if !migrations::upgrade(collection.name(), doc) {
+            return Ok(false);
+        }
+        let id = doc.get("_id").cloned().unwrap_or(Bson::Null);
+        collection.replace_one(outdated(Some(id)), &*doc).await?;
+        Ok(true)
+    }
+
+    /// Replaces the user's entries in `collection`, keeping their order in `position`. Runs in a
+    /// transaction like registration, so readers never see the entries half replaced.
+    async fn replace_entries<T: Serialize>(
+        &self,
+        collection: &Collection<Document>,
+        token: &str,
+        key: &str,
+        items: &[T],
+    ) -> Result<(), DbError> {
+        let entries = entry_documents(token, items)?;
+        self.transaction(
+            (collection, token, key, &entries),
+            |session, (collection, token, key, entries)| {
+                write_entries(collection, token, key, entries, session).boxed()
+            },
+        )
+        .await
+    }
+
+    /// Re-link writes, run inside the session's transaction. `_id` is immutable, so the user
+    /// document is copied to the new token and the old one deleted.
+    async fn write_relink(
+        &self,
+        old: &str,
+        new: &str,
+        session: &mut ClientSession,
+    ) -> Result<(), DbError> {
+        let existing = self
+            .collection
+            .find_one(doc! {"_id": new})
+            .session(&mut *session)
+            .await?;
+        if existing.is_some_and(|doc| doc.contains_key("user")) {
+            return Err(DbError::UserAlreadyExist(new.to_owned()));
+        }
+        let mut user = self
+            .collection
+            .find_one(doc! {"_id": old})
+            .session(&mut *session)
+            .await?
+            .ok_or(DbError::DataNotFound(old.to_owned()))?;
+
+        for field in ["suspended_at", "next_poll_at", "lease_owner", "lease_until"] {
+            user.remove(field);
+        }
+        user.insert("_id", new);
+        self.collection
+            .delete_one(doc! {"_id": old})
+            .session(&mut *session)
+            .await?;
+        self.collection
+            .replace_one(doc! {"_id": new}, user)
+            .upsert(true)
+            .session(&mut *session)
+            .await?;
+
+        for collection in [
+            &self.courses,
+            &self.grades,
+            &self.grades_overview,
+            &self.deadlines,
+            &self.sync_cursors,
+        ] {
+            collection
+                .update_many(doc! {"token": old}, doc! {"$set": {"token": new}})
+                .session(&mut *session)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Registration writes, run inside the session's transaction.
+    async fn write_registration(
+        &self,
+        registration: &Registration,
+        session: &mut ClientSession,
+    ) -> Result<(), DbError> {
+        let token = &registration.token.token;
+        let existing = self
+            .collection
+            .find_one(doc! {"_id": token})
+            .session(&mut *session)
+            .await?;
+        if existing.is_some_and(|doc| doc.contains_key("user")) {
+            return Err(DbError::UserAlreadyExist(token.to_owned()));
+        }
+
+        self.collection
+            .replace_one(
+                doc! {"_id": token},
+                doc! {
+                    "device_token": &registration.token.device_token,
+                    "tenant": &registration.token.tenant,
+                    "user": to_bson(&registration.user)?,
+                    SCHEMA_VERSION_FIELD: SCHEMA_VERSION,
+                },
+            )
+            .upsert(true)
+            .session(&mut *session)
+            .await?;
+
+        let entries = [
+            (
+                &self.courses,
+                entry_documents(token, &registration.courses)?,
+            ),
+            (&self.grades, entry_documents(token, &registration.grades)?),
+            (
+                &self.grades_overview,
+                entry_documents(token, &registration.grades_overview.grades)?,
+            ),
+            (
+                &self.deadlines,
+                entry_documents(token, &registration.deadlines)?,
+            ),
+            (&self.sync_cursors, Vec::new()),
+        ];
+        for (collection, entries) in entries {
+            collection
+                .delete_many(doc! {"token": token})
+                .session(&mut *session)
+                .await?;
+            if !entries.is_empty() {
+                collection
+                    .insert_many(entries)
+                    .session(&mut *session)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// All entries of the user in `collection`, in the order they were saved.
+    async fn find_entries<T: DeserializeOwned>(
+        &self,
+        collection: &Collection<Document>,
+        token: &str,
+    ) -> Result<Vec<T>, DbError> {
+        let mut docs: Vec<Document> = collection
+            .find(doc! {"token": token})
+            .sort(doc! {"position": 1})
+            .await?
+            .try_collect()
+            .await?;
+        for doc in &mut docs {
+            self.write_upgrade(collection, doc).await?;
+        }
+        self.decode_entries(collection, token, docs).await
+    }
+
+    /// Runs `stages` over the user's entries in `collection`.
+    async fn aggregate_entries<T: DeserializeOwned>(
+        &self,
+        collection: &Collection<Document>,
+        token: &str,
+        stages: Vec<Document>,
+    ) -> Result<Vec<T>, DbError> {
+        let mut pipeline = vec![doc! {"$match": {"token": token}}];
+        pipeline.extend(stages);
+
+        let docs: Vec<Document> = collection.aggregate(pipeline).await?.try_collect().await?;
+        let stale: Vec<Bson> = docs
+            .iter()
+            .filter(|doc| migrations::document_version(doc) < SCHEMA_VERSION)
+            .filter_map(|doc| doc.get("_id").cloned())
+            .collect();
+        if !stale.is_empty() {
+            self.upgrade_stored(collection, stale).await?;
+        }
+        self.decode_entries(collection, token, docs).await
+    }
+
+    /// Upgrades the stored documents with the given `_id`s. Aggregation results can be
+    /// reshaped by their stages, so the stored copies are read again instead of written back.
+    async fn upgrade_stored(
+        &self,
+        collection: &Collection<Document>,
+        ids: Vec<Bson>,
+    ) -> Result<(), DbError> {
+        let mut cursor = collection.find(doc! {"_id": {"$in": ids}}).await?;
+        while let Some(mut doc) = cursor.try_next().await? {
+            self.write_upgrade(collection, &mut doc).await?;
+        }
+        Ok(())
+    }
+
+    /// No entries is only an error when the user itself is unknown.
+    /// Outdated documents are upgraded again here, callers persist the upgrade.
+    async fn decode_entries<T: DeserializeOwned>(
+        &self,
+        collection: &Collection<Document>,
+        token: &str,
+        docs: Vec<Document>,
+    ) -> Result<Vec<T>, DbError> {
+        if docs.is_empty() && self.collection.count_documents(doc! {"_id": token}).await? == 0 {
+            return Err(DbError::DataNotFound(token.to_owned()));
+        }
+
+        let mut items = Vec::with_capacity(docs.len());
+        for mut doc in docs {
+            migrations::upgrade(collection.name(), &mut doc);
+            items.push(bson::from_document(doc)?);
+        }
+        Ok(items)
+    }
+}
+
+/// Entry writes of `replace_entries`, run inside the session's transaction. Entries no longer
+/// in `entries` are deleted, the others upserted by `key`.
+async fn write_entries(
+    collection: &Collection<Document>,
+    token: &str,
+    key: &str,
+    entries: &[Document],
+    session: &mut ClientSession,
+) -> Result<(), DbError> {
+    let keys: Vec<Bson> = entries
+        .iter()
+        .filter_map(|entry| entry.get(key).cloned())
+        .collect();
+    collection
+        .delete_many(doc! {"token": token, key: {"$nin": keys}})
+        .session(&mut *session)
+        .await?;
+    for entry in entries {
+        let filter = doc! {"token": token, key: entry.get(key).cloned().unwrap_or(Bson::Null)};
+        collection
+            .replace_one(filter, entry)
+            .upsert(true)
+            .session(&mut *session)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Entry documents tagged with the owning token and their position in `items`.
+fn entry_documents<T: Serialize>(token: &str, items: &[T]) -> Result<Vec<Document>, DbError> {
+    let mut entries = Vec::with_capacity(items.len());
+    for (position, item) in items.iter().enumerate() {
+        let mut entry = bson::to_document(item)?;
+        entry.insert("token", token);
+        entry.insert("position", position as i64);
+        migrations::stamp(&mut entry);
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Token and device token of a `users` document.
+fn token_from_document(doc: &Document) -> Option<Token> {
+    let token = doc.get_str("_id").ok()?;
+    let device_token = doc.get_str("device_token").ok().map(str::to_string);
+    Some(Token::new(token.to_string(), device_token))
+}
+
+/// Documents written before [`SCHEMA_VERSION`], optionally narrowed to one `_id`.
+fn outdated(id: Option<Bson>) -> Document {
+    let mut filter = doc! {SCHEMA_VERSION_FIELD: {"$not": {"$gte": SCHEMA_VERSION}}};
+    if let Some(id) = id {
+        filter.insert("_id", id);
+    }
+    filter
+}
+
+fn range_filter(filter: &mut Document, field: &str, from: Option<i64>, to: Option<i64>) {
+    let mut range = Document::new();
+    if let Some(from) = from {
+        range.insert("$gte", from);
+    }
+    if let Some(to) = to {
+        range.insert("$lt", to);
+    }
+    if !range.is_empty() {
+        filter.insert(field, range);
     }
 }
 
+/// Entries after `cursor`. Comparisons only match values of the same type, while the sort puts
+/// null (or missing) before every string, so the null part of the order is matched separately.
+fn keyset(field: &str, id_field: &str, order: SortOrder, cursor: &Cursor) -> Vec<Document> {
+    let op = match order {
+        SortOrder::Asc => "$gt",
+        SortOrder::Desc => "$lt",
+    };
+    let value = match &cursor.value {
+        CursorValue::Null => Bson::Null,
+        CursorValue::Int(value) => Bson::Int64(*value),
+        CursorValue::Str(value) => Bson::String(value.clone()),
+    };
+    let mut after = vec![doc! { field: value.clone(), id_field: { op: cursor.id } }];
+    match (&cursor.value, order) {
+        (CursorValue::Null, SortOrder::Asc) => after.push(doc! { field: { "$ne": Bson::Null } }),
+        (CursorValue::Null, SortOrder::Desc) => {}
+        (CursorValue::Str(_), SortOrder::Desc) => {
+            after.push(doc! { field: { op: value } });
+            after.push(doc! { field: Bson::Null });
+        }
+        _ => after.push(doc! { field: { op: value } }),
+    }
+    after
+}
+
+/// `$match`, keyset, `$sort` and `$limit` stages for one page ordered by `field` then `id_field`.
+fn page_stages(
+    filter: Document,
+    field: &str,
+    id_field: &str,
+    order: SortOrder,
+    cursor: Option<Cursor>,
+    limit: u32,
+) -> Vec<Document> {
+    let direction = order.as_i32();
+    let mut stages = vec![doc! {"$match": filter}];
+
+    if let Some(cursor) = cursor {
+        stages.push(doc! {"$match": {"$or": keyset(field, id_field, order, &cursor)}});
+    }
+
+    let mut sort = doc! { field: direction };
+    sort.insert(id_field, direction);
+    stages.push(doc! {"$sort": sort});
+    stages.push(doc! {"$limit": limit as i64 + 1});
+    stages
+}
+
 #[async_trait]
 impl TokenRepositoryAbstract for DataRepository {
     async fn find_token(&self, token: &Token) -> Result<(), DbError> {
@@ -36,7 +554,11 @@ impl TokenRepositoryAbstract for DataRepository {
         Ok(())
     }
     async fn save_tokens(&self, token: &Token) -> Result<(), DbError> {
-        let doc = doc! {"_id": &token.token, "device_token": &token.device_token };
+        let doc = doc! {
+            "_id": &token.token,
+            "device_token": &token.device_token,
+            SCHEMA_VERSION_FIELD: SCHEMA_VERSION,
+        };
         self.find_token(token).await?;
 
         self.collection.insert_one(doc).await?;
@@ -50,15 +572,7 @@ impl TokenRepositoryAbstract for DataRepository {
         let mut cursor = self.collection.find(filter).skip(skip).limit(limit).await?;
 
         while let Some(doc) = cursor.try_next().await? {
-            if let Ok(token) = doc.get_str("_id") {
-                match doc.get_str("device_token") {
-                    Ok(device_token) => batch.push(Token::new(
-                        token.to_string(),
-                        Some(device_token.to_string()),
-                    )),
-                    Err(_) => batch.push(Token::new(token.to_string(), None)),
-                }
-            }
+            batch.extend(token_from_document(&doc));
         }
 
         Ok(batch)
@@ -73,18 +587,181 @@ impl TokenRepositoryAbstract for DataRepository {
         }
 
         self.collection.delete_one(doc).await?;
+        for collection in [
+            &self.courses,
+            &self.grades,
+            &self.grades_overview,
+            &self.deadlines,
+            &self.sync_cursors,
+        ] {
+            collection.delete_many(doc! {"token": token}).await?;
+        }
+        Ok(())
+    }
+
+    async fn save_feed_token(&self, token: &str, feed_token: &str) -> Result<(), DbError> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {"_id": token},
+                doc! {
+                    "$set": {"feed_token": feed_token}
+                },
+            )
+            .await?;
+        if result.matched_count == 0 {
+            return Err(DbError::DataNotFound(token.to_owned()));
+        }
         Ok(())
     }
+
+    async fn find_token_by_feed_token(&self, feed_token: &str) -> Result<String, DbError> {
+        let doc = self
+            .collection
+            .find_one(doc! {"feed_token": feed_token})
+            .await?
+            .ok_or(DbError::DataNotFound(feed_token.to_owned()))?;
+        Ok(doc.get_str("_id")?.to_owned())
+    }
+
+    async fn delete_feed_token(&self, token: &str) -> Result<(), DbError> {
+        self.collection
+            .update_one(
+                doc! {"_id": token},
+                doc! {
+                    "$unset": {"feed_token": ""}
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn save_registration(&self, registration: &Registration) -> Result<(), DbError> {
+        self.transaction(
+            (self, registration),
+            |session, (repository, registration)| {
+                repository.write_registration(registration, session).boxed()
+            },
+        )
+        .await
+    }
+
+    async fn claim_due_tokens(
+        &self,
+        worker: &str,
+        now: i64,
+        lease_until: i64,
+        limit: i64,
+    ) -> Result<Vec<Token>, DbError> {
+        // Missing `next_poll_at` sorts first, those tokens were never polled.
+        let filter = doc! {
+            "$and": [
+                {"$or": [
+                    {"next_poll_at": {"$exists": false}},
+                    {"next_poll_at": {"$lte": now}},
+                ]},
+                {"$or": [
+                    {"lease_until": {"$exists": false}},
+                    {"lease_until": {"$lte": now}},
+                ]},
+                {"suspended_at": {"$exists": false}},
+            ]
+        };
+        let lease = doc! {"$set": {"lease_owner": worker, "lease_until": lease_until}};
+
+        // Each claim is atomic per document, concurrent workers never get the same token.
+        let mut claimed = Vec::new();
+        while (claimed.len() as i64) < limit {
+            let Some(doc) = self
+                .collection
+                .find_one_and_update(filter.clone(), lease.clone())
+                .sort(doc! {"next_poll_at": 1})
+                .await?
+            else {
+                break;
+            };
+            claimed.extend(token_from_document(&doc));
+        }
+        Ok(claimed)
+    }
+
+    async fn renew_lease(
+        &self,
+        token: &str,
+        worker: &str,
+        lease_until: i64,
+    ) -> Result<bool, DbError> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {"_id": token, "lease_owner": worker},
+                doc! {"$set": {"lease_until": lease_until}},
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    async fn schedule_poll(
+        &self,
+        token: &str,
+        worker: &str,
+        next_poll_at: i64,
+    ) -> Result<(), DbError> {
+        self.collection
+            .update_one(
+                doc! {"_id": token, "lease_owner": worker},
+                doc! {
+                    "$set": {"next_poll_at": next_poll_at},
+                    "$unset": {"lease_owner": "", "lease_until": ""},
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn suspend(&self, token: &str, suspended_at: i64) -> Result<bool, DbError> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {"_id": token, "suspended_at": {"$exists": false}},
+                doc! {"$set": {"suspended_at": suspended_at}},
+            )
+            .await?;
+        if result.modified_count > 0 {
+            return Ok(true);
+        }
+        match self.collection.find_one(doc! {"_id": token}).await? {
+            Some(_) => Ok(false),
+            None => Err(DbError::DataNotFound(token.to_owned())),
+        }
+    }
+
+    async fn find_tenant(&self, token: &str) -> Result<Option<String>, DbError> {
+        let doc = self
+            .collection
+            .find_one(doc! {"_id": token})
+            .await?
+            .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        Ok(doc.get_str("tenant").ok().map(str::to_owned))
+    }
+
+    async fn relink(&self, old: &str, new: &str) -> Result<(), DbError> {
+        self.transaction((self, old, new), |session, (repository, old, new)| {
+            repository.write_relink(old, new, session).boxed()
+        })
+        .await
+    }
 }
 
 #[async_trait]
 impl UserRepositoryAbstract for DataRepository {
     async fn find_user_by_token(&self, token: &str) -> Result<User, DbError> {
-        let doc = self
+        let mut doc = self
             .collection
             .find_one(doc! {"_id": token})
             .await?
             .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        self.write_upgrade(&self.collection, &mut doc).await?;
 
         let user_doc = doc.get_document("user")?;
         let user: User = bson::from_document(user_doc.to_owned())?;
@@ -104,58 +781,141 @@ impl UserRepositoryAbstract for DataRepository {
 #[async_trait]
 impl CourseRepositoryAbstract for DataRepository {
     async fn save_courses(&self, token: &str, courses: &[Course]) -> Result<(), DbError> {
-        let courses_doc = to_bson(courses)?;
-        self.collection
-            .update_one(
-                doc! {"_id": token},
-                doc! {
-                    "$set": {"courses": courses_doc}
-                },
-            )
-            .await?;
-        Ok(())
+        self.replace_entries(&self.courses, token, "id", courses)
+            .await
     }
 
     async fn find_courses_by_token(&self, token: &str) -> Result<Vec<Course>, DbError> {
-        let doc = self
-            .collection
-            .find_one(doc! {"_id": token})
-            .await?
-            .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        self.find_entries(&self.courses, token).await
+    }
+
+    async fn find_courses_page(
+        &self,
+        token: &str,
+        query: &CourseQuery,
+    ) -> Result<Page<Course>, DbError> {
+        let mut filter = Document::new();
+        if let Some(course_id) = query.course_id {
+            filter.insert("id", course_id);
+        }
+        let (from, to) = query.enddate_range(Utc::now().timestamp());
+        range_filter(&mut filter, "enddate", from, to);
 
-        let courses_doc = doc.get_array("courses")?;
-        let bson = Bson::from(courses_doc);
-        let courses = from_bson::<Vec<Course>>(bson)?;
-        Ok(courses)
+        let field = match query.sort {
+            CourseSort::Id => "id",
+            CourseSort::Name => "fullname",
+            CourseSort::Enddate => "enddate",
+        };
+        let stages = page_stages(
+            filter,
+            field,
+            "id",
+            query.order,
+            query.cursor(),
+            query.limit(),
+        );
+        let courses = self.aggregate_entries(&self.courses, token, stages).await?;
+
+        Ok(Page::from_overfetch(
+            courses,
+            query.limit(),
+            |course: &Course| {
+                let value = match query.sort {
+                    CourseSort::Id => CursorValue::Int(course.id),
+                    CourseSort::Name => CursorValue::Str(course.fullname.clone()),
+                    CourseSort::Enddate => CursorValue::Int(course.enddate),
+                };
+                Cursor::new(value, course.id)
+            },
+        ))
+    }
+
+    async fn find_sync_cursors(&self, token: &str) -> Result<Vec<SyncCursor>, DbError> {
+        self.find_entries(&self.sync_cursors, token).await
+    }
+
+    async fn save_sync_cursors(&self, token: &str, cursors: &[SyncCursor]) -> Result<(), DbError> {
+        let writes = cursors.iter().map(|cursor| {
+            let filter = doc! {"token": token, "courseid": cursor.courseid};
+            let update = doc! {"$set": {
+                "synced_at": cursor.synced_at,
+                SCHEMA_VERSION_FIELD: SCHEMA_VERSION,
+            }};
+            async move {
+                self.sync_cursors
+                    .update_one(filter, update)
+                    .upsert(true)
+                    .await
+            }
+        });
+        try_join_all(writes).await?;
+        Ok(())
     }
 }
 
 #[async_trait]
 impl GradeRepositoryAbstract for DataRepository {
     async fn save_grades(&self, token: &str, grades: &[Grade]) -> Result<(), DbError> {
-        let grades_doc = to_bson(grades)?;
-        self.collection
-            .update_one(
-                doc! {"_id": token},
-                doc! {
-                    "$set": {"grades": grades_doc}
-                },
-            )
-            .await?;
-        Ok(())
+        self.replace_entries(&self.grades, token, "courseid", grades)
+            .await
     }
 
     async fn find_grades_by_token(&self, token: &str) -> Result<Vec<Grade>, DbError> {
-        let doc = self
-            .collection
-            .find_one(doc! {"_id": token})
-            .await?
-            .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        self.find_entries(&self.grades, token).await
+    }
 
-        let grades_doc = doc.get_array("grades")?;
-        let bson = Bson::from(grades_doc);
-        let grades = from_bson::<Vec<Grade>>(bson)?;
-        Ok(grades)
+    async fn find_grades_page(
+        &self,
+        token: &str,
+        query: &GradeQuery,
+    ) -> Result<Page<Grade>, DbError> {
+        let mut filter = Document::new();
+        if let Some(course_id) = query.course_id {
+            filter.insert("courseid", course_id);
+        }
+
+        let field = match query.sort {
+            GradeSort::Course => "coursename",
+            GradeSort::CourseId => "courseid",
+        };
+        let mut stages = Vec::new();
+        if let Some(status) = query.status {
+            let cond = match status {
+                GradeStatus::Graded => doc! {"$ne": ["$$item.percentageformatted", UNGRADED]},
+                GradeStatus::Ungraded => doc! {"$eq": ["$$item.percentageformatted", UNGRADED]},
+            };
+            stages.push(doc! {
+                "$set": {
+                    "gradeitems": {
+                        "$filter": {"input": "$gradeitems", "as": "item", "cond": cond}
+                    }
+                }
+            });
+        }
+        stages.extend(page_stages(
+            filter,
+            field,
+            "courseid",
+            query.order,
+            query.cursor(),
+            query.limit(),
+        ));
+        let grades = self.aggregate_entries(&self.grades, token, stages).await?;
+
+        Ok(Page::from_overfetch(
+            grades,
+            query.limit(),
+            |grade: &Grade| {
+                let value = match query.sort {
+                    GradeSort::Course => grade
+                        .coursename
+                        .clone()
+                        .map_or(CursorValue::Null, CursorValue::Str),
+                    GradeSort::CourseId => CursorValue::Int(grade.courseid),
+                };
+                Cursor::new(value, grade.courseid)
+            },
+        ))
     }
 
     async fn save_grades_overview(
@@ -163,77 +923,345 @@ impl GradeRepositoryAbstract for DataRepository {
         token: &str,
         grades_overview: &GradesOverview,
     ) -> Result<(), DbError> {
-        let grades_overview_doc = to_bson(&grades_overview.grades)?;
-        self.collection
-            .update_one(
-                doc! {"_id": token},
-                doc! {
-                    "$set": {"grades_overview": grades_overview_doc}
-                },
-            )
-            .await?;
-        Ok(())
+        self.replace_entries(
+            &self.grades_overview,
+            token,
+            "courseid",
+            &grades_overview.grades,
+        )
+        .await
     }
 
     async fn find_grades_overview_by_token(
         &self,
         token: &str,
     ) -> Result<Vec<GradeOverview>, DbError> {
-        let doc = self
-            .collection
-            .find_one(doc! {"_id": token})
-            .await?
-            .ok_or(DbError::DataNotFound(token.to_owned()))?;
-
-        let grades_doc = doc.get_array("grades_overview")?;
-        let bson = Bson::from(grades_doc);
-        let grades_overview = from_bson::<Vec<GradeOverview>>(bson)?;
-        Ok(grades_overview)
+        self.find_entries(&self.grades_overview, token).await
     }
 }
 
 #[async_trait]
 impl DeadlineRepositoryAbstract for DataRepository {
     async fn save_deadlines(&self, token: &str, deadlines: &[Deadline]) -> Result<(), DbError> {
-        let deadlines_doc = to_bson(deadlines)?;
-        self.collection
-            .update_one(
-                doc! {"_id": token},
-                doc! {
-                    "$set": {"deadlines": deadlines_doc}
-                },
-            )
-            .await?;
-        Ok(())
+        self.replace_entries(&self.deadlines, token, "id", deadlines)
+            .await
     }
 
     async fn find_deadlines_by_token(&self, token: &str) -> Result<Vec<Deadline>, DbError> {
-        let doc = self
-            .collection
-            .find_one(doc! {"_id": token})
-            .await?
-            .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        self.find_entries(&self.deadlines, token).await
+    }
 
-        let deadlines_doc = doc.get_array("deadlines")?;
+    async fn find_deadlines_page(
+        &self,
+        token: &str,
+        query: &DeadlineQuery,
+    ) -> Result<Page<Deadline>, DbError> {
+        let mut filter = Document::new();
+        if let Some(course_id) = query.course_id {
+            filter.insert("courseid", course_id);
+        }
+        let (from, to) = query.due_range(Utc::now().timestamp());
+        range_filter(&mut filter, "timeusermidnight", from, to);
+
+        let field = match query.sort {
+            DeadlineSort::Due => "timeusermidnight",
+            DeadlineSort::Name => "name",
+        };
+        let stages = page_stages(
+            filter,
+            field,
+            "id",
+            query.order,
+            query.cursor(),
+            query.limit(),
+        );
+        let deadlines = self
+            .aggregate_entries(&self.deadlines, token, stages)
+            .await?;
 
-        let bson = Bson::from(deadlines_doc);
-        let deadlines = from_bson::<Vec<Deadline>>(bson)?;
-        Ok(deadlines)
+        Ok(Page::from_overfetch(
+            deadlines,
+            query.limit(),
+            |deadline: &Deadline| {
+                let value = match query.sort {
+                    DeadlineSort::Due => CursorValue::Int(deadline.timeusermidnight),
+                    DeadlineSort::Name => CursorValue::Str(deadline.name.clone()),
+                };
+                Cursor::new(value, deadline.id as i64)
+            },
+        ))
     }
 
     async fn delete_expired_deadlines(&self, unix_date: u64) -> Result<(), DbError> {
-        self.collection
-            .update_many(
-                doc! {},
-                doc! {
-                    "$pull": {
-                        "deadlines": {
-                            "timeusermidnight": { "$lt": unix_date as i64 }
-                        }
-                    }
-                },
-            )
+        self.deadlines
+            .delete_many(doc! {"timeusermidnight": { "$lt": unix_date as i64 }})
             .await?;
         Ok(())
     }
 }
+
+/// These run against the MongoDB replica set in `MONGODB_TEST_URI`, each test on a database of
+/// its own. Locally they pass without running when it is unset, in CI they fail.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Repository on a fresh database, `None` without a test server.
+    async fn repository() -> Option<(DataRepository, Database)> {
+        let Ok(uri) = std::env::var("MONGODB_TEST_URI") else {
+            assert!(
+                std::env::var_os("CI").is_none(),
+                "MONGODB_TEST_URI must be set in CI"
+            );
+            return None;
+        };
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database(&format!("test_{:08x}", rand::random::<u32>()));
+        let repository = DataRepository::new(&db);
+        repository.require_transactions().await.unwrap();
+        repository.create_indexes().await.unwrap();
+        Some((repository, db))
+    }
+
+    #[tokio::test]
+    async fn test_paged_read_persists_the_upgrade() {
+        let Some((repository, db)) = repository().await else {
+            return;
+        };
+        let due = Utc::now().timestamp() + 86_400;
+        repository
+            .collection
+            .insert_one(
+                doc! {"_id": "token", "user": {"username": "s", "fullname": "S", "userid": 1}},
+            )
+            .await
+            .unwrap();
+        repository
+            .deadlines
+            .insert_one(doc! {
+                "token": "token",
+                "position": 0_i64,
+                "id": 1,
+                "name": "Quiz",
+                "timeusermidnight": due,
+                "formattedtime": "10:00",
+                "coursename": "Math",
+            })
+            .await
+            .unwrap();
+
+        let page = repository
+            .find_deadlines_page("token", &DeadlineQuery::default())
+            .await
+            .unwrap();
+
+        assert_eq!(page.items[0].name, "Quiz");
+        assert_eq!(page.items[0].courseid, None);
+        let stored = repository
+            .deadlines
+            .find_one(doc! {"token": "token"})
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(migrations::document_version(&stored), SCHEMA_VERSION);
+        assert_eq!(stored.get("courseid"), Some(&Bson::Null));
+        db.drop().await.unwrap();
+    }
+
+    fn course(id: i64, fullname: &str) -> Course {
+        Course {
+            id,
+            fullname: fullname.to_owned(),
+            enddate: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replace_entries() {
+        let Some((repository, db)) = repository().await else {
+            return;
+        };
+        repository
+            .save_tokens(&Token::new("token".to_owned(), None))
+            .await
+            .unwrap();
+
+        repository
+            .save_courses("token", &[course(1, "Math"), course(2, "Physics")])
+            .await
+            .unwrap();
+        repository
+            .save_courses("token", &[course(3, "History"), course(2, "Physics II")])
+            .await
+            .unwrap();
+
+        let courses = repository.find_courses_by_token("token").await.unwrap();
+        assert_eq!(courses, vec![course(3, "History"), course(2, "Physics II")]);
+        let stored = repository
+            .courses
+            .count_documents(doc! {"token": "token"})
+            .await
+            .unwrap();
+        assert_eq!(stored, 2);
+        db.drop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_claim_leases_each_due_user_once() {
+        let Some((repository, db)) = repository().await else {
+            return;
+        };
+        for token in ["first", "second", "suspended"] {
+            repository
+                .save_tokens(&Token::new(token.to_owned(), None))
+                .await
+                .unwrap();
+        }
+        assert!(repository.suspend("suspended", 100).await.unwrap());
+
+        let claimed = repository
+            .claim_due_tokens("one", 1_000, 1_120, 10)
+            .await
+            .unwrap();
+        let mut claimed: Vec<_> = claimed.into_iter().map(|token| token.token).collect();
+        claimed.sort();
+        assert_eq!(claimed, ["first", "second"]);
+        assert!(repository
+            .claim_due_tokens("two", 1_000, 1_120, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(!repository.renew_lease("first", "two", 1_240).await.unwrap());
+        assert!(repository.renew_lease("first", "one", 1_240).await.unwrap());
+        repository
+            .schedule_poll("first", "one", 2_000)
+            .await
+            .unwrap();
+        let claimed = repository
+            .claim_due_tokens("two", 1_500, 1_620, 10)
+            .await
+            .unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].token, "second");
+        db.drop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_split_embedded_arrays() {
+        let Some((repository, db)) = repository().await else {
+            return;
+        };
+        repository
+            .collection
+            .insert_one(doc! {
+                "_id": "token",
+                "user": {"username": "s", "fullname": "S", "userid": 1},
+                "courses": [{"id": 1, "fullname": "Math", "enddate": 0}],
+                "grades_overview": [],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(repository.split_embedded_arrays().await.unwrap(), 1);
+        assert_eq!(repository.split_embedded_arrays().await.unwrap(), 0);
+
+        let courses = repository.find_courses_by_token("token").await.unwrap();
+        assert_eq!(courses, vec![course(1, "Math")]);
+        let user = repository
+            .collection
+            .find_one(doc! {"_id": "token"})
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(EMBEDDED_ARRAYS
+            .iter()
+            .all(|array| !user.contains_key(array)));
+        assert!(user.contains_key("user"));
+        db.drop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_grade_pages_keep_null_course_names_first() {
+        let Some((repository, db)) = repository().await else {
+            return;
+        };
+        repository
+            .save_tokens(&Token::new("token".to_owned(), None))
+            .await
+            .unwrap();
+        let grade = |courseid, coursename: Option<&str>| Grade {
+            coursename: coursename.map(str::to_owned),
+            courseid,
+            gradeitems: Vec::new(),
+        };
+        repository
+            .save_grades(
+                "token",
+                &[
+                    grade(4, Some("Biology")),
+                    grade(2, None),
+                    grade(3, Some("Algebra")),
+                    grade(1, None),
+                ],
+            )
+            .await
+            .unwrap();
+
+        for (order, expected) in [
+            (SortOrder::Asc, vec![1, 2, 3, 4]),
+            (SortOrder::Desc, vec![4, 3, 2, 1]),
+        ] {
+            let mut query = GradeQuery {
+                order,
+                limit: Some(1),
+                ..Default::default()
+            };
+            let mut seen = Vec::new();
+            loop {
+                let page = repository.find_grades_page("token", &query).await.unwrap();
+                seen.extend(page.items.iter().map(|grade| grade.courseid));
+                match page.next_cursor {
+                    Some(cursor) => query.cursor = Some(cursor),
+                    None => break,
+                }
+            }
+            assert_eq!(seen, expected, "{:?}", order);
+        }
+        db.drop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_course_pages_by_name() {
+        let Some((repository, db)) = repository().await else {
+            return;
+        };
+        repository
+            .save_tokens(&Token::new("token".to_owned(), None))
+            .await
+            .unwrap();
+        repository
+            .save_courses(
+                "token",
+                &[course(1, "Math"), course(2, "Art"), course(3, "Math")],
+            )
+            .await
+            .unwrap();
+
+        let query = CourseQuery {
+            sort: CourseSort::Name,
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = repository.find_courses_page("token", &query).await.unwrap();
+        assert_eq!(first.items, vec![course(2, "Art"), course(1, "Math")]);
+        let query = CourseQuery {
+            cursor: first.next_cursor,
+            ..query
+        };
+        let second = repository.find_courses_page("token", &query).await.unwrap();
+        assert_eq!(second.items, vec![course(3, "Math")]);
+        assert!(second.next_cursor.is_none());
+        db.drop().await.unwrap();
+    }
+}
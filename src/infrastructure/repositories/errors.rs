@@ -16,6 +16,12 @@ pub enum DbError {
     #[error("BSON document value access error")]
     ValueAccessError(#[from] mongodb::bson::document::ValueAccessError),
 
+    #[error("SQL database error")]
+    SqlError(#[from] sqlx::Error),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+
     #[error("User already exist with token: `{0}`")]
     UserAlreadyExist(String),
 
@@ -26,7 +32,9 @@ pub enum DbError {
 impl From<DbError> for ServiceError {
     fn from(value: DbError) -> Self {
         match value {
-            DbError::InternalError(_) => Self::InternalServerError,
+            DbError::InternalError(_) | DbError::SqlError(_) | DbError::Storage(_) => {
+                Self::InternalServerError
+            }
             DbError::SerializationError(error) => Self::DataNotFound(error.to_string()),
             DbError::DeserializationError(error) => Self::DataNotFound(error.to_string()),
             DbError::ValueAccessError(value_access_error) => {
@@ -0,0 +1,856 @@
+use std::{collections::BTreeMap, sync::RwLock};
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::domain::entities::course::Course;
+use crate::domain::entities::deadline::Deadline;
+use crate::domain::entities::grade::{Grade, GradeOverview, GradesOverview};
+use crate::domain::entities::query::{
+    CourseQuery, CourseSort, Cursor, CursorValue, DeadlineQuery, DeadlineSort, GradeQuery,
+    GradeSort, GradeStatus, Page, PageQuery, SortOrder, UNGRADED,
+};
+use crate::domain::entities::registration::Registration;
+use crate::domain::entities::sync_cursor::SyncCursor;
+use crate::domain::entities::token::Token;
+use crate::domain::entities::user::User;
+use crate::domain::repositories::data_repository_abstract::{
+    CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
+    TokenRepositoryAbstract, UserRepositoryAbstract,
+};
+
+use super::errors::DbError;
+
+#[derive(Debug, Default, Clone)]
+struct UserEntry {
+    device_token: Option<String>,
+    feed_token: Option<String>,
+    user: Option<User>,
+    courses: Vec<Course>,
+    grades: Vec<Grade>,
+    grades_overview: Vec<GradeOverview>,
+    deadlines: Vec<Deadline>,
+    sync_cursors: BTreeMap<i64, i64>,
+    next_poll_at: Option<i64>,
+    /// Owning worker and lease expiry.
+    lease: Option<(String, i64)>,
+    suspended_at: Option<i64>,
+    tenant: Option<String>,
+}
+
+/// Keeps everything in process memory, for local runs and tests without MongoDB.
+/// Behaves like `DataRepository`, including its `UserAlreadyExist` and `DataNotFound` errors.
+#[derive(Debug, Default)]
+pub struct InMemoryRepository {
+    users: RwLock<BTreeMap<String, UserEntry>>,
+}
+
+impl InMemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read<T>(
+        &self,
+        token: &str,
+        read: impl FnOnce(&UserEntry) -> Result<T, DbError>,
+    ) -> Result<T, DbError> {
+        let users = self.users.read().map_err(|_| poisoned())?;
+        let entry = users
+            .get(token)
+            .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        read(entry)
+    }
+
+    /// Like an update without upsert, writes for unknown users are dropped.
+    fn update(&self, token: &str, update: impl FnOnce(&mut UserEntry)) -> Result<bool, DbError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        Ok(users.get_mut(token).map(update).is_some())
+    }
+}
+
+fn poisoned() -> DbError {
+    DbError::Storage("in-memory storage lock poisoned".to_owned())
+}
+
+/// Sorts by `key` then id, skips past `cursor` and keeps `limit + 1` entries.
+fn paginate<T>(
+    mut items: Vec<T>,
+    order: SortOrder,
+    cursor: Option<Cursor>,
+    limit: u32,
+    key: impl Fn(&T) -> (CursorValue, i64),
+) -> Page<T> {
+    items.sort_by(|a, b| {
+        let ordering = key(a)
+            .partial_cmp(&key(b))
+            .unwrap_or(std::cmp::Ordering::Equal);
+        match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    });
+    if let Some(cursor) = cursor {
+        let position = (cursor.value, cursor.id);
+        items.retain(|item| match order {
+            SortOrder::Asc => key(item) > position,
+            SortOrder::Desc => key(item) < position,
+        });
+    }
+    items.truncate(limit as usize + 1);
+
+    Page::from_overfetch(items, limit, |item| {
+        let (value, id) = key(item);
+        Cursor::new(value, id)
+    })
+}
+
+fn in_range(value: i64, from: Option<i64>, to: Option<i64>) -> bool {
+    from.is_none_or(|from| value >= from) && to.is_none_or(|to| value < to)
+}
+
+#[async_trait]
+impl TokenRepositoryAbstract for InMemoryRepository {
+    async fn find_token(&self, token: &Token) -> Result<(), DbError> {
+        let users = self.users.read().map_err(|_| poisoned())?;
+        if users.contains_key(&token.token) {
+            return Err(DbError::UserAlreadyExist(token.token.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn save_tokens(&self, token: &Token) -> Result<(), DbError> {
+        self.find_token(token).await?;
+
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        users.insert(
+            token.token.clone(),
+            UserEntry {
+                device_token: token.device_token.clone(),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    async fn find_all_device_tokens(&self, limit: i64, skip: u64) -> Result<Vec<Token>, DbError> {
+        let users = self.users.read().map_err(|_| poisoned())?;
+        Ok(users
+            .iter()
+            .skip(skip as usize)
+            .take(limit.max(0) as usize)
+            .map(|(token, entry)| Token::new(token.clone(), entry.device_token.clone()))
+            .collect())
+    }
+
+    async fn delete(&self, token: &str) -> Result<(), DbError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        users
+            .remove(token)
+            .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        Ok(())
+    }
+
+    async fn save_feed_token(&self, token: &str, feed_token: &str) -> Result<(), DbError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        if users
+            .iter()
+            .any(|(other, entry)| other != token && entry.feed_token.as_deref() == Some(feed_token))
+        {
+            return Err(DbError::Storage(format!(
+                "feed token {} is already in use",
+                feed_token
+            )));
+        }
+        let entry = users
+            .get_mut(token)
+            .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        entry.feed_token = Some(feed_token.to_owned());
+        Ok(())
+    }
+
+    async fn find_token_by_feed_token(&self, feed_token: &str) -> Result<String, DbError> {
+        let users = self.users.read().map_err(|_| poisoned())?;
+        users
+            .iter()
+            .find(|(_, entry)| entry.feed_token.as_deref() == Some(feed_token))
+            .map(|(token, _)| token.clone())
+            .ok_or(DbError::DataNotFound(feed_token.to_owned()))
+    }
+
+    async fn delete_feed_token(&self, token: &str) -> Result<(), DbError> {
+        self.update(token, |entry| entry.feed_token = None)?;
+        Ok(())
+    }
+
+    async fn save_registration(&self, registration: &Registration) -> Result<(), DbError> {
+        let token = &registration.token.token;
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        if users.get(token).is_some_and(|entry| entry.user.is_some()) {
+            return Err(DbError::UserAlreadyExist(token.to_owned()));
+        }
+
+        users.insert(
+            token.clone(),
+            UserEntry {
+                device_token: registration.token.device_token.clone(),
+                feed_token: None,
+                user: Some(registration.user.clone()),
+                courses: registration.courses.clone(),
+                grades: registration.grades.clone(),
+                grades_overview: registration.grades_overview.grades.clone(),
+                deadlines: registration.deadlines.clone(),
+                sync_cursors: BTreeMap::new(),
+                next_poll_at: None,
+                lease: None,
+                suspended_at: None,
+                tenant: registration.token.tenant.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn claim_due_tokens(
+        &self,
+        worker: &str,
+        now: i64,
+        lease_until: i64,
+        limit: i64,
+    ) -> Result<Vec<Token>, DbError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        let mut due: Vec<(&String, &mut UserEntry)> = users
+            .iter_mut()
+            .filter(|(_, entry)| {
+                entry.suspended_at.is_none()
+                    && entry.next_poll_at.is_none_or(|at| at <= now)
+                    && entry.lease.as_ref().is_none_or(|(_, until)| *until <= now)
+            })
+            .collect();
+        due.sort_by_key(|(_, entry)| entry.next_poll_at);
+
+        Ok(due
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|(token, entry)| {
+                entry.lease = Some((worker.to_owned(), lease_until));
+                Token::new(token.clone(), entry.device_token.clone())
+            })
+            .collect())
+    }
+
+    async fn renew_lease(
+        &self,
+        token: &str,
+        worker: &str,
+        lease_until: i64,
+    ) -> Result<bool, DbError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        match users.get_mut(token).and_then(|entry| entry.lease.as_mut()) {
+            Some((owner, until)) if owner == worker => {
+                *until = lease_until;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn schedule_poll(
+        &self,
+        token: &str,
+        worker: &str,
+        next_poll_at: i64,
+    ) -> Result<(), DbError> {
+        self.update(token, |entry| {
+            if entry
+                .lease
+                .as_ref()
+                .is_some_and(|(owner, _)| owner == worker)
+            {
+                entry.lease = None;
+                entry.next_poll_at = Some(next_poll_at);
+            }
+        })?;
+        Ok(())
+    }
+
+    async fn suspend(&self, token: &str, suspended_at: i64) -> Result<bool, DbError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        let entry = users
+            .get_mut(token)
+            .ok_or(DbError::DataNotFound(token.to_owned()))?;
+        if entry.suspended_at.is_some() {
+            return Ok(false);
+        }
+        entry.suspended_at = Some(suspended_at);
+        Ok(true)
+    }
+
+    async fn find_tenant(&self, token: &str) -> Result<Option<String>, DbError> {
+        self.read(token, |entry| Ok(entry.tenant.clone()))
+    }
+
+    async fn relink(&self, old: &str, new: &str) -> Result<(), DbError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        if users.get(new).is_some_and(|entry| entry.user.is_some()) {
+            return Err(DbError::UserAlreadyExist(new.to_owned()));
+        }
+        let mut entry = users
+            .remove(old)
+            .ok_or(DbError::DataNotFound(old.to_owned()))?;
+        entry.suspended_at = None;
+        entry.next_poll_at = None;
+        entry.lease = None;
+        users.insert(new.to_owned(), entry);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepositoryAbstract for InMemoryRepository {
+    async fn find_user_by_token(&self, token: &str) -> Result<User, DbError> {
+        self.read(token, |entry| {
+            entry
+                .user
+                .clone()
+                .ok_or(DbError::DataNotFound(token.to_owned()))
+        })
+    }
+
+    async fn save_user(&self, user: &User, token: &str) -> Result<(), DbError> {
+        self.update(token, |entry| entry.user = Some(user.clone()))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CourseRepositoryAbstract for InMemoryRepository {
+    async fn save_courses(&self, token: &str, courses: &[Course]) -> Result<(), DbError> {
+        self.update(token, |entry| entry.courses = courses.to_vec())?;
+        Ok(())
+    }
+
+    async fn find_courses_by_token(&self, token: &str) -> Result<Vec<Course>, DbError> {
+        self.read(token, |entry| Ok(entry.courses.clone()))
+    }
+
+    async fn find_courses_page(
+        &self,
+        token: &str,
+        query: &CourseQuery,
+    ) -> Result<Page<Course>, DbError> {
+        let (from, to) = query.enddate_range(Utc::now().timestamp());
+        let courses = self.read(token, |entry| {
+            Ok(entry
+                .courses
+                .iter()
+                .filter(|course| query.course_id.is_none_or(|id| course.id == id))
+                .filter(|course| in_range(course.enddate, from, to))
+                .cloned()
+                .collect())
+        })?;
+
+        Ok(paginate(
+            courses,
+            query.order,
+            query.cursor(),
+            query.limit(),
+            |course: &Course| {
+                let value = match query.sort {
+                    CourseSort::Id => CursorValue::Int(course.id),
+                    CourseSort::Name => CursorValue::Str(course.fullname.clone()),
+                    CourseSort::Enddate => CursorValue::Int(course.enddate),
+                };
+                (value, course.id)
+            },
+        ))
+    }
+
+    async fn find_sync_cursors(&self, token: &str) -> Result<Vec<SyncCursor>, DbError> {
+        self.read(token, |entry| {
+            Ok(entry
+                .sync_cursors
+                .iter()
+                .map(|(courseid, synced_at)| SyncCursor::new(*courseid, *synced_at))
+                .collect())
+        })
+    }
+
+    async fn save_sync_cursors(&self, token: &str, cursors: &[SyncCursor]) -> Result<(), DbError> {
+        self.update(token, |entry| {
+            for cursor in cursors {
+                entry.sync_cursors.insert(cursor.courseid, cursor.synced_at);
+            }
+        })?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GradeRepositoryAbstract for InMemoryRepository {
+    async fn save_grades(&self, token: &str, grades: &[Grade]) -> Result<(), DbError> {
+        self.update(token, |entry| entry.grades = grades.to_vec())?;
+        Ok(())
+    }
+
+    async fn find_grades_by_token(&self, token: &str) -> Result<Vec<Grade>, DbError> {
+        self.read(token, |entry| Ok(entry.grades.clone()))
+    }
+
+    async fn find_grades_page(
+        &self,
+        token: &str,
+        query: &GradeQuery,
+    ) -> Result<Page<Grade>, DbError> {
+        let grades = self.read(token, |entry| {
+            Ok(entry
+                .grades
+                .iter()
+                .filter(|grade| query.course_id.is_none_or(|id| grade.courseid == id))
+                .cloned()
+                .map(|mut grade| {
+                    if let Some(status) = query.status {
+                        grade.gradeitems.retain(|item| {
+                            let graded = item.percentageformatted != UNGRADED;
+                            graded == (status == GradeStatus::Graded)
+                        });
+                    }
+                    grade
+                })
+                .collect())
+        })?;
+
+        Ok(paginate(
+            grades,
+            query.order,
+            query.cursor(),
+            query.limit(),
+            |grade: &Grade| {
+                let value = match query.sort {
+                    GradeSort::Course => {
+                        CursorValue::Str(grade.coursename.clone().unwrap_or_default())
+                    }
+                    GradeSort::CourseId => CursorValue::Int(grade.courseid),
+                };
+                (value, grade.courseid)
+            },
+        ))
+    }
+
+    async fn save_grades_overview(
+        &self,
+        token: &str,
+        grades_overview: &GradesOverview,
+    ) -> Result<(), DbError> {
+        self.update(token, |entry| {
+            entry.grades_overview = grades_overview.grades.clone()
+        })?;
+        Ok(())
+    }
+
+    async fn find_grades_overview_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Vec<GradeOverview>, DbError> {
+        self.read(token, |entry| Ok(entry.grades_overview.clone()))
+    }
+}
+
+#[async_trait]
+impl DeadlineRepositoryAbstract for InMemoryRepository {
+    async fn save_deadlines(&self, token: &str, deadlines: &[Deadline]) -> Result<(), DbError> {
+        self.update(token, |entry| entry.deadlines = deadlines.to_vec())?;
+        Ok(())
+    }
+
+    async fn find_deadlines_by_token(&self, token: &str) -> Result<Vec<Deadline>, DbError> {
+        self.read(token, |entry| Ok(entry.deadlines.clone()))
+    }
+
+    async fn find_deadlines_page(
+        &self,
+        token: &str,
+        query: &DeadlineQuery,
+    ) -> Result<Page<Deadline>, DbError> {
+        let (from, to) = query.due_range(Utc::now().timestamp());
+        let deadlines = self.read(token, |entry| {
+            Ok(entry
+                .deadlines
+                .iter()
+                .filter(|deadline| {
+                    query
+                        .course_id
+                        .is_none_or(|id| deadline.courseid == Some(id))
+                })
+                .filter(|deadline| in_range(deadline.timeusermidnight, from, to))
+                .cloned()
+                .collect())
+        })?;
+
+        Ok(paginate(
+            deadlines,
+            query.order,
+            query.cursor(),
+            query.limit(),
+            |deadline: &Deadline| {
+                let value = match query.sort {
+                    DeadlineSort::Due => CursorValue::Int(deadline.timeusermidnight),
+                    DeadlineSort::Name => CursorValue::Str(deadline.name.clone()),
+                };
+                (value, deadline.id as i64)
+            },
+        ))
+    }
+
+    async fn delete_expired_deadlines(&self, unix_date: u64) -> Result<(), DbError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        for entry in users.values_mut() {
+            entry
+                .deadlines
+                .retain(|deadline| deadline.timeusermidnight >= unix_date as i64);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn course(id: i64, fullname: &str) -> Course {
+        Course {
+            id,
+            fullname: fullname.to_string(),
+            enddate: 0,
+        }
+    }
+
+    fn registration(token: &str) -> Registration {
+        Registration {
+            token: Token::new(token.to_string(), None),
+            user: User {
+                username: "student".to_string(),
+                fullname: "Test Student".to_string(),
+                userid: 7,
+            },
+            courses: vec![Course {
+                id: 1,
+                fullname: "Math".to_string(),
+                enddate: 0,
+            }],
+            grades: Vec::new(),
+            grades_overview: GradesOverview { grades: Vec::new() },
+            deadlines: Vec::new(),
+        }
+    }
+
+    async fn registered(token: &str) -> InMemoryRepository {
+        let repository = InMemoryRepository::new();
+        repository
+            .save_tokens(&Token::new(token.to_string(), None))
+            .await
+            .unwrap();
+        repository
+    }
+
+    #[tokio::test]
+    async fn test_sync_cursors_upsert_by_course() {
+        let repository = registered("token").await;
+        repository
+            .save_sync_cursors("token", &[SyncCursor::new(1, 100), SyncCursor::new(2, 100)])
+            .await
+            .unwrap();
+        repository
+            .save_sync_cursors("token", &[SyncCursor::new(2, 200)])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repository.find_sync_cursors("token").await.unwrap(),
+            vec![SyncCursor::new(1, 100), SyncCursor::new(2, 200)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_errors_match_data_repository() {
+        let repository = registered("token").await;
+        let token = Token::new("token".to_string(), None);
+
+        assert!(matches!(
+            repository.save_tokens(&token).await,
+            Err(DbError::UserAlreadyExist(_))
+        ));
+        assert!(matches!(
+            repository.find_courses_by_token("unknown").await,
+            Err(DbError::DataNotFound(_))
+        ));
+        assert!(repository
+            .find_courses_by_token("token")
+            .await
+            .unwrap()
+            .is_empty());
+
+        repository.delete("token").await.unwrap();
+        assert!(matches!(
+            repository.delete("token").await,
+            Err(DbError::DataNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_find_all_device_tokens_batches() {
+        let repository = InMemoryRepository::new();
+        for token in ["a", "b", "c"] {
+            repository
+                .save_tokens(&Token::new(token.to_string(), Some("device".to_string())))
+                .await
+                .unwrap();
+        }
+
+        let first = repository.find_all_device_tokens(2, 0).await.unwrap();
+        let second = repository.find_all_device_tokens(2, 2).await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].token, "c");
+    }
+
+    #[tokio::test]
+    async fn test_claim_due_tokens() {
+        let repository = InMemoryRepository::new();
+        for token in ["a", "b", "c"] {
+            repository
+                .save_tokens(&Token::new(token.to_string(), None))
+                .await
+                .unwrap();
+        }
+        repository.claim_due_tokens("w", 0, 10, 10).await.unwrap();
+        repository.schedule_poll("a", "w", 50).await.unwrap();
+        repository.schedule_poll("b", "w", 200).await.unwrap();
+        repository.schedule_poll("c", "w", 20).await.unwrap();
+
+        let due: Vec<String> = repository
+            .claim_due_tokens("w", 100, 220, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        assert_eq!(due, ["c", "a"]);
+        assert!(repository
+            .claim_due_tokens("w", 100, 220, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_workers_claim_each_user_once() {
+        let repository = Arc::new(InMemoryRepository::new());
+        for id in 0..50 {
+            repository
+                .save_tokens(&Token::new(format!("token-{}", id), None))
+                .await
+                .unwrap();
+        }
+
+        let workers = (0..4).map(|worker| {
+            let repository = Arc::clone(&repository);
+            tokio::spawn(async move {
+                let worker = format!("worker-{}", worker);
+                let mut claimed = Vec::new();
+                loop {
+                    let batch = repository
+                        .claim_due_tokens(&worker, 100, 220, 3)
+                        .await
+                        .unwrap();
+                    if batch.is_empty() {
+                        return claimed;
+                    }
+                    claimed.extend(batch.into_iter().map(|token| token.token));
+                    tokio::task::yield_now().await;
+                }
+            })
+        });
+
+        let mut claimed = Vec::new();
+        for worker in workers.collect::<Vec<_>>() {
+            claimed.extend(worker.await.unwrap());
+        }
+        claimed.sort();
+        let total = claimed.len();
+        claimed.dedup();
+        assert_eq!(total, 50);
+        assert_eq!(claimed.len(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_lease_ownership() {
+        let repository = InMemoryRepository::new();
+        repository
+            .save_tokens(&Token::new("a".to_string(), None))
+            .await
+            .unwrap();
+        assert_eq!(
+            repository
+                .claim_due_tokens("w1", 100, 220, 1)
+                .await
+                .unwrap()[0]
+                .token,
+            "a"
+        );
+
+        assert!(!repository.renew_lease("a", "w2", 340).await.unwrap());
+        assert!(repository.renew_lease("a", "w1", 340).await.unwrap());
+        assert!(repository
+            .claim_due_tokens("w2", 300, 420, 1)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // An expired lease is up for grabs, the old owner can neither renew nor reschedule.
+        assert_eq!(
+            repository
+                .claim_due_tokens("w2", 400, 520, 1)
+                .await
+                .unwrap()[0]
+                .token,
+            "a"
+        );
+        assert!(!repository.renew_lease("a", "w1", 640).await.unwrap());
+        repository.schedule_poll("a", "w1", 10_000).await.unwrap();
+        assert!(repository
+            .claim_due_tokens("w1", 500, 620, 1)
+            .await
+            .unwrap()
+            .is_empty());
+        repository.schedule_poll("a", "w2", 10_000).await.unwrap();
+        assert!(repository
+            .claim_due_tokens("w1", 600, 720, 1)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            repository
+                .claim_due_tokens("w1", 10_000, 10_120, 1)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_suspend_and_relink() {
+        let repository = registered("old").await;
+        repository
+            .save_courses("old", &[course(1, "Math")])
+            .await
+            .unwrap();
+        repository.save_feed_token("old", "feed").await.unwrap();
+
+        assert!(repository.suspend("old", 100).await.unwrap());
+        assert!(!repository.suspend("old", 200).await.unwrap());
+        assert!(repository
+            .claim_due_tokens("w", 300, 420, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        repository.relink("old", "new").await.unwrap();
+        assert_eq!(
+            repository.find_courses_by_token("new").await.unwrap().len(),
+            1
+        );
+        assert_eq!(
+            repository.find_token_by_feed_token("feed").await.unwrap(),
+            "new"
+        );
+        assert!(matches!(
+            repository.relink("old", "other").await,
+            Err(DbError::DataNotFound(_))
+        ));
+        assert_eq!(
+            repository
+                .claim_due_tokens("w", 300, 420, 10)
+                .await
+                .unwrap()[0]
+                .token,
+            "new"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_courses_page() {
+        let repository = registered("token").await;
+        let courses: Vec<Course> = (1..=5).map(|id| course(id, "Math")).collect();
+        repository.save_courses("token", &courses).await.unwrap();
+
+        let query = CourseQuery {
+            sort: CourseSort::Name,
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = repository.find_courses_page("token", &query).await.unwrap();
+        assert_eq!(first.items.iter().map(|c| c.id).collect::<Vec<_>>(), [1, 2]);
+
+        let query = CourseQuery {
+            cursor: first.next_cursor,
+            ..query
+        };
+        let second = repository.find_courses_page("token", &query).await.unwrap();
+        assert_eq!(
+            second.items.iter().map(|c| c.id).collect::<Vec<_>>(),
+            [3, 4]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_expired_deadlines() {
+        let repository = registered("token").await;
+        let deadline = |id, timeusermidnight| Deadline {
+            id,
+            name: "Essay".to_string(),
+            timeusermidnight,
+            formattedtime: String::new(),
+            coursename: None,
+            courseid: None,
+        };
+        repository
+            .save_deadlines("token", &[deadline(1, 100), deadline(2, 300)])
+            .await
+            .unwrap();
+
+        repository.delete_expired_deadlines(200).await.unwrap();
+        let deadlines = repository.find_deadlines_by_token("token").await.unwrap();
+        assert_eq!(deadlines.len(), 1);
+        assert_eq!(deadlines[0].id, 2);
+    }
+
+    #[tokio::test]
+    async fn test_save_registration_replaces_leftovers() {
+        let repository = InMemoryRepository::new();
+        // An interrupted registration used to leave the token without a user.
+        repository
+            .save_tokens(&Token::new("token".to_string(), None))
+            .await
+            .unwrap();
+
+        repository
+            .save_registration(&registration("token"))
+            .await
+            .unwrap();
+        assert_eq!(
+            repository
+                .find_courses_by_token("token")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(matches!(
+            repository.save_registration(&registration("token")).await,
+            Err(DbError::UserAlreadyExist(_))
+        ));
+    }
+}
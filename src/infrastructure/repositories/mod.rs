@@ -1,2 +1,8 @@
+pub mod cached_repository;
 pub mod data_repository;
 pub mod errors;
+pub mod in_memory_repository;
+pub mod postgres_repository;
+mod sql;
+pub mod sqlite_repository;
+pub mod storage;
@@ -0,0 +1,1282 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::{PgConnection, QueryBuilder};
+
+use crate::domain::entities::course::Course;
+use crate::domain::entities::deadline::Deadline;
+use crate::domain::entities::grade::{Grade, GradeItems, GradeOverview, GradesOverview};
+use crate::domain::entities::query::{
+    CourseQuery, CourseSort, Cursor, CursorValue, DeadlineQuery, DeadlineSort, GradeQuery,
+    GradeSort, GradeStatus, Page, PageQuery,
+};
+use crate::domain::entities::registration::Registration;
+use crate::domain::entities::sync_cursor::SyncCursor;
+use crate::domain::entities::token::Token;
+use crate::domain::entities::user::User;
+use crate::domain::repositories::data_repository_abstract::{
+    CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
+    TokenRepositoryAbstract, UserRepositoryAbstract,
+};
+
+use super::errors::DbError;
+use super::sql::{assemble_grades, push_page, push_range, GradeItemRow};
+
+/// Relational layout from `migrations/postgres`: one row per token, user and entity,
+/// multi-table saves run in a transaction.
+#[derive(Debug)]
+pub struct PostgresRepository {
+    pool: PgPool,
+}
+
+impl PostgresRepository {
+    pub async fn connect(database_url: &str) -> Result<Self, DbError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(10)
+            .connect(database_url)
+            .await?;
+        Ok(Self { pool })
+    }
+
+    pub async fn run_migrations(&self) -> Result<(), DbError> {
+        sqlx::migrate!("./migrations/postgres")
+            .run(&self.pool)
+            .await
+            .map_err(|e| DbError::Storage(e.to_string()))
+    }
+
+    /// No entries is only an error when the token itself is unknown.
+    async fn ensure_token(&self, token: &str, empty: bool) -> Result<(), DbError> {
+        if empty && !token_exists(&mut *self.pool.acquire().await?, token).await? {
+            return Err(DbError::DataNotFound(token.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn load_grades(
+        &self,
+        token: &str,
+        rows: Vec<(i64, Option<String>)>,
+        status: Option<GradeStatus>,
+    ) -> Result<Vec<Grade>, DbError> {
+        let course_ids: Vec<i64> = rows.iter().map(|(courseid, _)| *courseid).collect();
+        let items: Vec<GradeItemRow> = sqlx::query_as(
+            "SELECT courseid, id, itemname, percentageformatted FROM grade_items
+             WHERE token = $1 AND courseid = ANY($2) ORDER BY courseid, position",
+        )
+        .bind(token)
+        .bind(&course_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(assemble_grades(rows, items, status))
+    }
+}
+
+async fn token_exists(conn: &mut PgConnection, token: &str) -> Result<bool, DbError> {
+    let row: Option<(i32,)> = sqlx::query_as("SELECT 1 FROM tokens WHERE token = $1")
+        .bind(token)
+        .fetch_optional(conn)
+        .await?;
+    Ok(row.is_some())
+}
+
+/// Does nothing for unknown tokens, like the other per-entity saves.
+async fn upsert_user(conn: &mut PgConnection, user: &User, token: &str) -> Result<(), DbError> {
+    sqlx::query(
+        "INSERT INTO users (token, userid, username, fullname)
+         SELECT token, $2, $3, $4 FROM tokens WHERE token = $1
+         ON CONFLICT (token) DO UPDATE
+         SET userid = EXCLUDED.userid, username = EXCLUDED.username, fullname = EXCLUDED.fullname",
+    )
+    .bind(token)
+    .bind(user.userid)
+    .bind(&user.username)
+    .bind(&user.fullname)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn replace_courses(
+    conn: &mut PgConnection,
+    token: &str,
+    courses: &[Course],
+) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM courses WHERE token = $1")
+        .bind(token)
+        .execute(&mut *conn)
+        .await?;
+
+    if !courses.is_empty() {
+        let mut builder =
+            QueryBuilder::new("INSERT INTO courses (token, id, position, fullname, enddate) ");
+        builder.push_values(courses.iter().enumerate(), |mut row, (position, course)| {
+            row.push_bind(token)
+                .push_bind(course.id)
+                .push_bind(position as i32)
+                .push_bind(&course.fullname)
+                .push_bind(course.enddate);
+        });
+        builder.push(" ON CONFLICT DO NOTHING");
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+async fn replace_grades(
+    conn: &mut PgConnection,
+    token: &str,
+    grades: &[Grade],
+) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM grades WHERE token = $1")
+        .bind(token)
+        .execute(&mut *conn)
+        .await?;
+
+    if !grades.is_empty() {
+        let mut builder =
+            QueryBuilder::new("INSERT INTO grades (token, courseid, position, coursename) ");
+        builder.push_values(grades.iter().enumerate(), |mut row, (position, grade)| {
+            row.push_bind(token)
+                .push_bind(grade.courseid)
+                .push_bind(position as i32)
+                .push_bind(&grade.coursename);
+        });
+        builder.push(" ON CONFLICT DO NOTHING");
+        builder.build().execute(&mut *conn).await?;
+    }
+
+    let items: Vec<(i64, usize, &GradeItems)> = grades
+        .iter()
+        .flat_map(|grade| {
+            grade
+                .gradeitems
+                .iter()
+                .enumerate()
+                .map(move |(position, item)| (grade.courseid, position, item))
+        })
+        .collect();
+    if !items.is_empty() {
+        let mut builder = QueryBuilder::new(
+            "INSERT INTO grade_items (token, courseid, position, id, itemname, percentageformatted) ",
+        );
+        builder.push_values(items, |mut row, (courseid, position, item)| {
+            row.push_bind(token)
+                .push_bind(courseid)
+                .push_bind(position as i32)
+                .push_bind(item.id)
+                .push_bind(&item.itemname)
+                .push_bind(&item.percentageformatted);
+        });
+        builder.push(" ON CONFLICT DO NOTHING");
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+async fn replace_grades_overview(
+    conn: &mut PgConnection,
+    token: &str,
+    grades_overview: &GradesOverview,
+) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM grade_overviews WHERE token = $1")
+        .bind(token)
+        .execute(&mut *conn)
+        .await?;
+
+    if !grades_overview.grades.is_empty() {
+        let mut builder = QueryBuilder::new(
+            "INSERT INTO grade_overviews (token, courseid, position, course_name, grade, rawgrade) ",
+        );
+        builder.push_values(
+            grades_overview.grades.iter().enumerate(),
+            |mut row, (position, grade)| {
+                row.push_bind(token)
+                    .push_bind(grade.courseid)
+                    .push_bind(position as i32)
+                    .push_bind(&grade.course_name)
+                    .push_bind(&grade.grade)
+                    .push_bind(&grade.rawgrade);
+            },
+        );
+        builder.push(" ON CONFLICT DO NOTHING");
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+async fn replace_deadlines(
+    conn: &mut PgConnection,
+    token: &str,
+    deadlines: &[Deadline],
+) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM deadlines WHERE token = $1")
+        .bind(token)
+        .execute(&mut *conn)
+        .await?;
+
+    if !deadlines.is_empty() {
+        let mut builder = QueryBuilder::new(
+            "INSERT INTO deadlines (token, id, position, name, timeusermidnight, formattedtime, coursename, courseid) ",
+        );
+        builder.push_values(
+            deadlines.iter().enumerate(),
+            |mut row, (position, deadline)| {
+                row.push_bind(token)
+                    .push_bind(deadline.id)
+                    .push_bind(position as i32)
+                    .push_bind(&deadline.name)
+                    .push_bind(deadline.timeusermidnight)
+                    .push_bind(&deadline.formattedtime)
+                    .push_bind(&deadline.coursename)
+                    .push_bind(deadline.courseid);
+            },
+        );
+        builder.push(" ON CONFLICT DO NOTHING");
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl TokenRepositoryAbstract for PostgresRepository {
+    async fn find_token(&self, token: &Token) -> Result<(), DbError> {
+        if token_exists(&mut *self.pool.acquire().await?, &token.token).await? {
+            return Err(DbError::UserAlreadyExist(token.token.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn save_tokens(&self, token: &Token) -> Result<(), DbError> {
+        self.find_token(token).await?;
+
+        sqlx::query("INSERT INTO tokens (token, device_token) VALUES ($1, $2)")
+            .bind(&token.token)
+            .bind(&token.device_token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn find_all_device_tokens(&self, limit: i64, skip: u64) -> Result<Vec<Token>, DbError> {
+        let rows: Vec<(String, Option<String>)> = sqlx::query_as(
+            "SELECT token, device_token FROM tokens ORDER BY created_at, token LIMIT $1 OFFSET $2",
+        )
+        .bind(limit)
+        .bind(skip as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(token, device_token)| Token::new(token, device_token))
+            .collect())
+    }
+
+    async fn delete(&self, token: &str) -> Result<(), DbError> {
+        let result = sqlx::query("DELETE FROM tokens WHERE token = $1")
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::DataNotFound(token.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn save_feed_token(&self, token: &str, feed_token: &str) -> Result<(), DbError> {
+        let result = sqlx::query("UPDATE tokens SET feed_token = $2 WHERE token = $1")
+            .bind(token)
+            .bind(feed_token)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::DataNotFound(token.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn find_token_by_feed_token(&self, feed_token: &str) -> Result<String, DbError> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT token FROM tokens WHERE feed_token = $1")
+                .bind(feed_token)
+                .fetch_optional(&self.pool)
+                .await?;
+        row.map(|(token,)| token)
+            .ok_or(DbError::DataNotFound(feed_token.to_owned()))
+    }
+
+    async fn delete_feed_token(&self, token: &str) -> Result<(), DbError> {
+        sqlx::query("UPDATE tokens SET feed_token = NULL WHERE token = $1")
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_registration(&self, registration: &Registration) -> Result<(), DbError> {
+        let token = &registration.token.token;
+        let mut tx = self.pool.begin().await?;
+        let registered: Option<(i32,)> = sqlx::query_as("SELECT 1 FROM users WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if registered.is_some() {
+            return Err(DbError::UserAlreadyExist(token.to_owned()));
+        }
+
+        sqlx::query("DELETE FROM tokens WHERE token = $1")
+            .bind(token)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO tokens (token, device_token, tenant) VALUES ($1, $2, $3)")
+            .bind(token)
+            .bind(&registration.token.device_token)
+            .bind(&registration.token.tenant)
+            .execute(&mut *tx)
+            .await?;
+        upsert_user(&mut tx, &registration.user, token).await?;
+        replace_courses(&mut tx, token, &registration.courses).await?;
+        replace_grades(&mut tx, token, &registration.grades).await?;
+        replace_grades_overview(&mut tx, token, &registration.grades_overview).await?;
+        replace_deadlines(&mut tx, token, &registration.deadlines).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn claim_due_tokens(
+        &self,
+        worker: &str,
+        now: i64,
+        lease_until: i64,
+        limit: i64,
+    ) -> Result<Vec<Token>, DbError> {
+        let rows: Vec<(String, Option<String>)> = sqlx::query_as(
+            "UPDATE tokens SET lease_owner = $1, lease_until = $2
+             WHERE token IN (
+                 SELECT token FROM tokens
+                 WHERE (next_poll_at IS NULL OR next_poll_at <= $3)
+                   AND (lease_until IS NULL OR lease_until <= $3)
+                   AND suspended_at IS NULL
+                 ORDER BY next_poll_at NULLS FIRST, token LIMIT $4
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING token, device_token",
+        )
+        .bind(worker)
+        .bind(lease_until)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(token, device_token)| Token::new(token, device_token))
+            .collect())
+    }
+
+    async fn renew_lease(
+        &self,
+        token: &str,
+        worker: &str,
+        lease_until: i64,
+    ) -> Result<bool, DbError> {
+        let result =
+            sqlx::query("UPDATE tokens SET lease_until = $3 WHERE token = $1 AND lease_owner = $2")
+                .bind(token)
+                .bind(worker)
+                .bind(lease_until)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn schedule_poll(
+        &self,
+        token: &str,
+        worker: &str,
+        next_poll_at: i64,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            "UPDATE tokens SET next_poll_at = $3, lease_owner = NULL, lease_until = NULL
+             WHERE token = $1 AND lease_owner = $2",
+        )
+        .bind(token)
+        .bind(worker)
+        .bind(next_poll_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn suspend(&self, token: &str, suspended_at: i64) -> Result<bool, DbError> {
+        let result = sqlx::query(
+            "UPDATE tokens SET suspended_at = $2 WHERE token = $1 AND suspended_at IS NULL",
+        )
+        .bind(token)
+        .bind(suspended_at)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() > 0 {
+            return Ok(true);
+        }
+        let exists: Option<(i32,)> = sqlx::query_as("SELECT 1 FROM tokens WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await?;
+        match exists {
+            Some(_) => Ok(false),
+            None => Err(DbError::DataNotFound(token.to_owned())),
+        }
+    }
+
+    async fn find_tenant(&self, token: &str) -> Result<Option<String>, DbError> {
+        let row: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT tenant FROM tokens WHERE token = $1")
+                .bind(token)
+                .fetch_optional(&self.pool)
+                .await?;
+        row.map(|(tenant,)| tenant)
+            .ok_or(DbError::DataNotFound(token.to_owned()))
+    }
+
+    async fn relink(&self, old: &str, new: &str) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        let registered: Option<(i32,)> = sqlx::query_as("SELECT 1 FROM users WHERE token = $1")
+            .bind(new)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if registered.is_some() {
+            return Err(DbError::UserAlreadyExist(new.to_owned()));
+        }
+        let feed_token: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT feed_token FROM tokens WHERE token = $1")
+                .bind(old)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let Some((feed_token,)) = feed_token else {
+            return Err(DbError::DataNotFound(old.to_owned()));
+        };
+
+        // Every table references the token, so the new token row is inserted, the data moved
+        // over and the old row deleted. Grade items reference grades, those are copied first.
+        sqlx::query("DELETE FROM tokens WHERE token = $1")
+            .bind(new)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE tokens SET feed_token = NULL WHERE token = $1")
+            .bind(old)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO tokens (token, device_token, feed_token, created_at, tenant)
+             SELECT $2::text, device_token, $3::text, created_at, tenant FROM tokens WHERE token = $1",
+        )
+        .bind(old)
+        .bind(new)
+        .bind(feed_token)
+        .execute(&mut *tx)
+        .await?;
+        for statement in [
+            "UPDATE users SET token = $2 WHERE token = $1",
+            "UPDATE courses SET token = $2 WHERE token = $1",
+            "INSERT INTO grades (token, courseid, position, coursename)
+             SELECT $2::text, courseid, position, coursename FROM grades WHERE token = $1",
+            "UPDATE grade_items SET token = $2 WHERE token = $1",
+            "UPDATE grade_overviews SET token = $2 WHERE token = $1",
+            "UPDATE deadlines SET token = $2 WHERE token = $1",
+            "UPDATE sync_cursors SET token = $2 WHERE token = $1",
+        ] {
+            sqlx::query(statement)
+                .bind(old)
+                .bind(new)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM grades WHERE token = $1")
+            .bind(old)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM tokens WHERE token = $1")
+            .bind(old)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepositoryAbstract for PostgresRepository {
+    async fn find_user_by_token(&self, token: &str) -> Result<User, DbError> {
+        let row: Option<(String, String, i64)> =
+            sqlx::query_as("SELECT username, fullname, userid FROM users WHERE token = $1")
+                .bind(token)
+                .fetch_optional(&self.pool)
+                .await?;
+        row.map(|(username, fullname, userid)| User {
+            username,
+            fullname,
+            userid,
+        })
+        .ok_or(DbError::DataNotFound(token.to_owned()))
+    }
+
+    async fn save_user(&self, user: &User, token: &str) -> Result<(), DbError> {
+        upsert_user(&mut *self.pool.acquire().await?, user, token).await
+    }
+}
+
+#[async_trait]
+impl CourseRepositoryAbstract for PostgresRepository {
+    async fn save_courses(&self, token: &str, courses: &[Course]) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        if !token_exists(&mut tx, token).await? {
+            return Ok(());
+        }
+        replace_courses(&mut tx, token, courses).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn find_courses_by_token(&self, token: &str) -> Result<Vec<Course>, DbError> {
+        let rows: Vec<(i64, String, i64)> = sqlx::query_as(
+            "SELECT id, fullname, enddate FROM courses WHERE token = $1 ORDER BY position",
+        )
+        .bind(token)
+        .fetch_all(&self.pool)
+        .await?;
+        self.ensure_token(token, rows.is_empty()).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, fullname, enddate)| Course {
+                id,
+                fullname,
+                enddate,
+            })
+            .collect())
+    }
+
+    async fn find_courses_page(
+        &self,
+        token: &str,
+        query: &CourseQuery,
+    ) -> Result<Page<Course>, DbError> {
+        let mut builder =
+            QueryBuilder::new("SELECT id, fullname, enddate FROM courses WHERE token = ");
+        builder.push_bind(token);
+        if let Some(course_id) = query.course_id {
+            builder.push(" AND id = ").push_bind(course_id);
+        }
+        let (from, to) = query.enddate_range(Utc::now().timestamp());
+        push_range(&mut builder, "enddate", from, to);
+
+        let field = match query.sort {
+            CourseSort::Id => "id",
+            CourseSort::Name => "fullname COLLATE \"C\"",
+            CourseSort::Enddate => "enddate",
+        };
+        push_page(
+            &mut builder,
+            field,
+            "id",
+            query.order,
+            query.cursor(),
+            query.limit(),
+        );
+
+        let rows: Vec<(i64, String, i64)> = builder.build_query_as().fetch_all(&self.pool).await?;
+        self.ensure_token(token, rows.is_empty()).await?;
+        let courses = rows
+            .into_iter()
+            .map(|(id, fullname, enddate)| Course {
+                id,
+                fullname,
+                enddate,
+            })
+            .collect();
+
+        Ok(Page::from_overfetch(
+            courses,
+            query.limit(),
+            |course: &Course| {
+                let value = match query.sort {
+                    CourseSort::Id => CursorValue::Int(course.id),
+                    CourseSort::Name => CursorValue::Str(course.fullname.clone()),
+                    CourseSort::Enddate => CursorValue::Int(course.enddate),
+                };
+                Cursor::new(value, course.id)
+            },
+        ))
+    }
+
+    async fn find_sync_cursors(&self, token: &str) -> Result<Vec<SyncCursor>, DbError> {
+        let rows: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT courseid, synced_at FROM sync_cursors WHERE token = $1 ORDER BY courseid",
+        )
+        .bind(token)
+        .fetch_all(&self.pool)
+        .await?;
+        self.ensure_token(token, rows.is_empty()).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(courseid, synced_at)| SyncCursor::new(courseid, synced_at))
+            .collect())
+    }
+
+    async fn save_sync_cursors(&self, token: &str, cursors: &[SyncCursor]) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        if cursors.is_empty() || !token_exists(&mut tx, token).await? {
+            return Ok(());
+        }
+
+        let mut builder =
+            QueryBuilder::new("INSERT INTO sync_cursors (token, courseid, synced_at) ");
+        builder.push_values(cursors, |mut row, cursor| {
+            row.push_bind(token)
+                .push_bind(cursor.courseid)
+                .push_bind(cursor.synced_at);
+        });
+        builder.push(" ON CONFLICT (token, courseid) DO UPDATE SET synced_at = EXCLUDED.synced_at");
+        builder.build().execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GradeRepositoryAbstract for PostgresRepository {
+    async fn save_grades(&self, token: &str, grades: &[Grade]) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        if !token_exists(&mut tx, token).await? {
+            return Ok(());
+        }
+        replace_grades(&mut tx, token, grades).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn find_grades_by_token(&self, token: &str) -> Result<Vec<Grade>, DbError> {
+        let rows: Vec<(i64, Option<String>)> = sqlx::query_as(
+            "SELECT courseid, coursename FROM grades WHERE token = $1 ORDER BY position",
+        )
+        .bind(token)
+        .fetch_all(&self.pool)
+        .await?;
+        self.ensure_token(token, rows.is_empty()).await?;
+        self.load_grades(token, rows, None).await
+    }
+
+    async fn find_grades_page(
+        &self,
+        token: &str,
+        query: &GradeQuery,
+    ) -> Result<Page<Grade>, DbError> {
+        let mut builder =
+            QueryBuilder::new("SELECT courseid, coursename FROM grades WHERE token = ");
+        builder.push_bind(token);
+        if let Some(course_id) = query.course_id {
+            builder.push(" AND courseid = ").push_bind(course_id);
+        }
+
+        let field = match query.sort {
+            GradeSort::Course => "COALESCE(coursename, '') COLLATE \"C\"",
+            GradeSort::CourseId => "courseid",
+        };
+        push_page(
+            &mut builder,
+            field,
+            "courseid",
+            query.order,
+            query.cursor(),
+            query.limit(),
+        );
+
+        let rows: Vec<(i64, Option<String>)> =
+            builder.build_query_as().fetch_all(&self.pool).await?;
+        self.ensure_token(token, rows.is_empty()).await?;
+        let grades = self.load_grades(token, rows, query.status).await?;
+
+        Ok(Page::from_overfetch(
+            grades,
+            query.limit(),
+            |grade: &Grade| {
+                let value = match query.sort {
+                    GradeSort::Course => {
+                        CursorValue::Str(grade.coursename.clone().unwrap_or_default())
+                    }
+                    GradeSort::CourseId => CursorValue::Int(grade.courseid),
+                };
+                Cursor::new(value, grade.courseid)
+            },
+        ))
+    }
+
+    async fn save_grades_overview(
+        &self,
+        token: &str,
+        grades_overview: &GradesOverview,
+    ) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        if !token_exists(&mut tx, token).await? {
+            return Ok(());
+        }
+        replace_grades_overview(&mut tx, token, grades_overview).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn find_grades_overview_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Vec<GradeOverview>, DbError> {
+        let rows: Vec<(Option<String>, i64, String, Option<String>)> = sqlx::query_as(
+            "SELECT course_name, courseid, grade, rawgrade FROM grade_overviews
+             WHERE token = $1 ORDER BY position",
+        )
+        .bind(token)
+        .fetch_all(&self.pool)
+        .await?;
+        self.ensure_token(token, rows.is_empty()).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(course_name, courseid, grade, rawgrade)| GradeOverview {
+                course_name,
+                courseid,
+                grade,
+                rawgrade,
+            })
+            .collect())
+    }
+}
+
+type DeadlineRow = (i32, String, i64, String, Option<String>, Option<i64>);
+
+fn deadline_from_row(row: DeadlineRow) -> Deadline {
+    let (id, name, timeusermidnight, formattedtime, coursename, courseid) = row;
+    Deadline {
+        id,
+        name,
+        timeusermidnight,
+        formattedtime,
+        coursename,
+        courseid,
+    }
+}
+
+#[async_trait]
+impl DeadlineRepositoryAbstract for PostgresRepository {
+    async fn save_deadlines(&self, token: &str, deadlines: &[Deadline]) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        if !token_exists(&mut tx, token).await? {
+            return Ok(());
+        }
+        replace_deadlines(&mut tx, token, deadlines).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn find_deadlines_by_token(&self, token: &str) -> Result<Vec<Deadline>, DbError> {
+        let rows: Vec<DeadlineRow> = sqlx::query_as(
+            "SELECT id, name, timeusermidnight, formattedtime, coursename, courseid FROM deadlines
+             WHERE token = $1 ORDER BY position",
+        )
+        .bind(token)
+        .fetch_all(&self.pool)
+        .await?;
+        self.ensure_token(token, rows.is_empty()).await?;
+
+        Ok(rows.into_iter().map(deadline_from_row).collect())
+    }
+
+    async fn find_deadlines_page(
+        &self,
+        token: &str,
+        query: &DeadlineQuery,
+    ) -> Result<Page<Deadline>, DbError> {
+        let mut builder = QueryBuilder::new(
+            "SELECT id, name, timeusermidnight, formattedtime, coursename, courseid FROM deadlines
+             WHERE token = ",
+        );
+        builder.push_bind(token);
+        if let Some(course_id) = query.course_id {
+            builder.push(" AND courseid = ").push_bind(course_id);
+        }
+        let (from, to) = query.due_range(Utc::now().timestamp());
+        push_range(&mut builder, "timeusermidnight", from, to);
+
+        let field = match query.sort {
+            DeadlineSort::Due => "timeusermidnight",
+            DeadlineSort::Name => "name COLLATE \"C\"",
+        };
+        push_page(
+            &mut builder,
+            field,
+            "id",
+            query.order,
+            query.cursor(),
+            query.limit(),
+        );
+
+        let rows: Vec<DeadlineRow> = builder.build_query_as().fetch_all(&self.pool).await?;
+        self.ensure_token(token, rows.is_empty()).await?;
+        let deadlines = rows.into_iter().map(deadline_from_row).collect();
+
+        Ok(Page::from_overfetch(
+            deadlines,
+            query.limit(),
+            |deadline: &Deadline| {
+                let value = match query.sort {
+                    DeadlineSort::Due => CursorValue::Int(deadline.timeusermidnight),
+                    DeadlineSort::Name => CursorValue::Str(deadline.name.clone()),
+                };
+                Cursor::new(value, deadline.id as i64)
+            },
+        ))
+    }
+
+    async fn delete_expired_deadlines(&self, unix_date: u64) -> Result<(), DbError> {
+        sqlx::query("DELETE FROM deadlines WHERE timeusermidnight < $1")
+            .bind(unix_date as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// These run against the Postgres server in `DATABASE_URL`, each test on a throwaway database
+/// it creates there. Locally they pass without running when it is unset, in CI they fail.
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::*;
+
+    struct TestDatabase {
+        admin: PgPool,
+        name: String,
+    }
+
+    impl TestDatabase {
+        async fn drop(self, repository: PostgresRepository) {
+            repository.pool.close().await;
+            sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", self.name))
+                .execute(&self.admin)
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Migrated repository on a fresh database, `None` without a test server.
+    async fn repository() -> Option<(PostgresRepository, TestDatabase)> {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            assert!(
+                std::env::var_os("CI").is_none(),
+                "DATABASE_URL must be set in CI"
+            );
+            return None;
+        };
+        let admin = PgPool::connect(&url).await.unwrap();
+        let name = format!("test_{:08x}", rand::random::<u32>());
+        sqlx::query(&format!("CREATE DATABASE {}", name))
+            .execute(&admin)
+            .await
+            .unwrap();
+
+        let mut url = Url::parse(&url).unwrap();
+        url.set_path(&name);
+        let repository = PostgresRepository::connect(url.as_str()).await.unwrap();
+        repository.run_migrations().await.unwrap();
+        Some((repository, TestDatabase { admin, name }))
+    }
+
+    fn registration(token: &str) -> Registration {
+        Registration {
+            token: Token::new(token.to_string(), None),
+            user: User {
+                username: "student".to_string(),
+                fullname: "Test Student".to_string(),
+                userid: 7,
+            },
+            courses: vec![Course {
+                id: 1,
+                fullname: "Math".to_string(),
+                enddate: 0,
+            }],
+            grades: Vec::new(),
+            grades_overview: GradesOverview { grades: Vec::new() },
+            deadlines: Vec::new(),
+        }
+    }
+
+    fn token(token: &str) -> Token {
+        Token::new(token.to_string(), Some(format!("device-{}", token)))
+    }
+
+    fn deadline(id: i32, timeusermidnight: i64) -> Deadline {
+        Deadline {
+            id,
+            name: format!("Task {}", id),
+            timeusermidnight,
+            formattedtime: String::new(),
+            coursename: Some("Math".to_string()),
+            courseid: Some(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_and_delete() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        repository.save_tokens(&token("a")).await.unwrap();
+        assert!(matches!(
+            repository.save_tokens(&token("a")).await,
+            Err(DbError::UserAlreadyExist(_))
+        ));
+
+        let user = User {
+            username: "student".to_string(),
+            fullname: "Test Student".to_string(),
+            userid: 7,
+        };
+        repository.save_user(&user, "a").await.unwrap();
+        repository
+            .save_deadlines("a", &[deadline(1, 100)])
+            .await
+            .unwrap();
+        assert_eq!(repository.find_user_by_token("a").await.unwrap(), user);
+
+        repository.delete("a").await.unwrap();
+        assert!(matches!(
+            repository.find_deadlines_by_token("a").await,
+            Err(DbError::DataNotFound(_))
+        ));
+        assert!(matches!(
+            repository.delete("a").await,
+            Err(DbError::DataNotFound(_))
+        ));
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_sync_cursors_upsert_and_cascade() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        repository.save_tokens(&token("a")).await.unwrap();
+        repository
+            .save_sync_cursors("a", &[SyncCursor::new(1, 100), SyncCursor::new(2, 100)])
+            .await
+            .unwrap();
+        repository
+            .save_sync_cursors("a", &[SyncCursor::new(1, 200)])
+            .await
+            .unwrap();
+        assert_eq!(
+            repository.find_sync_cursors("a").await.unwrap(),
+            vec![SyncCursor::new(1, 200), SyncCursor::new(2, 100)]
+        );
+
+        repository.delete("a").await.unwrap();
+        assert!(matches!(
+            repository.find_sync_cursors("a").await,
+            Err(DbError::DataNotFound(_))
+        ));
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_claim_due_tokens() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        for name in ["a", "b", "c"] {
+            repository.save_tokens(&token(name)).await.unwrap();
+        }
+        repository.claim_due_tokens("w1", 0, 10, 10).await.unwrap();
+        repository.schedule_poll("a", "w1", 50).await.unwrap();
+        repository.schedule_poll("b", "w1", 200).await.unwrap();
+        repository.schedule_poll("c", "w1", 20).await.unwrap();
+
+        let mut due: Vec<String> = repository
+            .claim_due_tokens("w1", 100, 220, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        due.sort();
+        assert_eq!(due, ["a", "c"]);
+        assert!(repository
+            .claim_due_tokens("w2", 100, 220, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(!repository.renew_lease("a", "w2", 340).await.unwrap());
+        assert!(repository.renew_lease("a", "w1", 340).await.unwrap());
+
+        repository.schedule_poll("c", "w2", 10_000).await.unwrap();
+        let mut reclaimed: Vec<String> = repository
+            .claim_due_tokens("w2", 300, 420, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        reclaimed.sort();
+        assert_eq!(reclaimed, ["b", "c"]);
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_relink_moves_data_and_lifts_suspension() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        let mut old = registration("old");
+        old.token.device_token = Some("device".to_string());
+        old.token.tenant = Some("enu".to_string());
+        old.grades = vec![Grade {
+            coursename: Some("Math".to_string()),
+            courseid: 1,
+            gradeitems: vec![GradeItems {
+                id: 1,
+                itemname: "Quiz".to_string(),
+                percentageformatted: "90 %".to_string(),
+            }],
+        }];
+        repository.save_registration(&old).await.unwrap();
+        repository.save_feed_token("old", "feed").await.unwrap();
+        repository
+            .save_sync_cursors("old", &[SyncCursor::new(1, 100)])
+            .await
+            .unwrap();
+
+        assert!(repository.suspend("old", 100).await.unwrap());
+        assert!(!repository.suspend("old", 200).await.unwrap());
+        assert!(repository
+            .claim_due_tokens("w", 300, 420, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        repository.relink("old", "new").await.unwrap();
+        assert_eq!(
+            repository.find_tenant("new").await.unwrap().as_deref(),
+            Some("enu")
+        );
+        assert!(matches!(
+            repository.find_user_by_token("old").await,
+            Err(DbError::DataNotFound(_))
+        ));
+        assert_eq!(
+            repository.find_user_by_token("new").await.unwrap().userid,
+            7
+        );
+        assert_eq!(
+            repository.find_courses_by_token("new").await.unwrap().len(),
+            1
+        );
+        let grades = repository.find_grades_by_token("new").await.unwrap();
+        assert_eq!(grades[0].gradeitems, old.grades[0].gradeitems);
+        assert_eq!(repository.find_sync_cursors("new").await.unwrap().len(), 1);
+        assert_eq!(
+            repository.find_token_by_feed_token("feed").await.unwrap(),
+            "new"
+        );
+
+        let claimed = repository
+            .claim_due_tokens("w", 300, 420, 10)
+            .await
+            .unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].device_token.as_deref(), Some("device"));
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_relink_to_registered_token_fails() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        repository
+            .save_registration(&registration("a"))
+            .await
+            .unwrap();
+        repository
+            .save_registration(&registration("b"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repository.relink("a", "b").await,
+            Err(DbError::UserAlreadyExist(_))
+        ));
+        assert!(matches!(
+            repository.relink("missing", "c").await,
+            Err(DbError::DataNotFound(_))
+        ));
+        assert!(repository.find_user_by_token("a").await.is_ok());
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_grades_keep_order_and_filter_items() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        repository.save_tokens(&token("a")).await.unwrap();
+        let item = |id, grade: &str| GradeItems {
+            id,
+            itemname: format!("Quiz {}", id),
+            percentageformatted: grade.to_string(),
+        };
+        let grades = vec![
+            Grade {
+                coursename: Some("Physics".to_string()),
+                courseid: 2,
+                gradeitems: vec![item(3, "90.00 %")],
+            },
+            Grade {
+                coursename: Some("Math".to_string()),
+                courseid: 1,
+                gradeitems: vec![item(1, "-"), item(2, "80.00 %")],
+            },
+        ];
+        repository.save_grades("a", &grades).await.unwrap();
+
+        let stored = repository.find_grades_by_token("a").await.unwrap();
+        assert_eq!(stored[0].courseid, 2);
+        assert_eq!(stored[1].gradeitems, grades[1].gradeitems);
+
+        let query = GradeQuery {
+            status: Some(GradeStatus::Graded),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let page = repository.find_grades_page("a", &query).await.unwrap();
+        assert_eq!(page.items[0].coursename.as_deref(), Some("Math"));
+        assert_eq!(page.items[0].gradeitems, vec![item(2, "80.00 %")]);
+
+        let query = GradeQuery {
+            cursor: page.next_cursor,
+            ..query
+        };
+        let page = repository.find_grades_page("a", &query).await.unwrap();
+        assert_eq!(page.items[0].courseid, 2);
+        assert!(page.next_cursor.is_none());
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_feed_token() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        repository.save_tokens(&token("a")).await.unwrap();
+        repository.save_feed_token("a", "feed").await.unwrap();
+        assert_eq!(
+            repository.find_token_by_feed_token("feed").await.unwrap(),
+            "a"
+        );
+
+        repository.delete_feed_token("a").await.unwrap();
+        assert!(matches!(
+            repository.find_token_by_feed_token("feed").await,
+            Err(DbError::DataNotFound(_))
+        ));
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_save_registration_replaces_leftovers() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        // An interrupted registration used to leave the token without a user.
+        repository
+            .save_tokens(&Token::new("token".to_string(), None))
+            .await
+            .unwrap();
+
+        repository
+            .save_registration(&registration("token"))
+            .await
+            .unwrap();
+        assert_eq!(
+            repository
+                .find_courses_by_token("token")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(matches!(
+            repository.save_registration(&registration("token")).await,
+            Err(DbError::UserAlreadyExist(_))
+        ));
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_claim_prefers_never_polled_users() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        for name in ["a", "b"] {
+            repository.save_tokens(&token(name)).await.unwrap();
+        }
+        repository.claim_due_tokens("w", 0, 10, 1).await.unwrap();
+        repository.schedule_poll("a", "w", 50).await.unwrap();
+        repository.save_tokens(&token("c")).await.unwrap();
+
+        // `b` and `c` were never polled, `NULLS FIRST` puts them ahead of `a`.
+        let claimed = repository.claim_due_tokens("w", 100, 220, 2).await.unwrap();
+        let mut claimed: Vec<String> = claimed.into_iter().map(|token| token.token).collect();
+        claimed.sort();
+        assert_eq!(claimed, ["b", "c"]);
+        database.drop(repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_claim_skips_locked_users() {
+        let Some((repository, database)) = repository().await else {
+            return;
+        };
+        for name in ["a", "b"] {
+            repository.save_tokens(&token(name)).await.unwrap();
+        }
+        // Another worker in the middle of claiming `a`.
+        let mut other = repository.pool.begin().await.unwrap();
+        sqlx::query("SELECT token FROM tokens WHERE token = 'a' FOR UPDATE")
+            .execute(&mut *other)
+            .await
+            .unwrap();
+
+        let claimed = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            repository.claim_due_tokens("w", 100, 220, 10),
+        )
+        .await
+        .expect("claiming waited for the locked row")
+        .unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].token, "b");
+
+        other.rollback().await.unwrap();
+        database.drop(repository).await;
+    }
+}
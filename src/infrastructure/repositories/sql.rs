@@ -0,0 +1,108 @@
+//! Query building shared by the SQL backends.
+
+use std::collections::HashMap;
+
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+use crate::domain::entities::grade::{Grade, GradeItems};
+use crate::domain::entities::query::{Cursor, CursorValue, GradeStatus, SortOrder, UNGRADED};
+
+/// `courseid, id, itemname, percentageformatted` of one `grade_items` row.
+pub(crate) type GradeItemRow = (i64, i64, String, String);
+
+pub(crate) fn push_range<'a, DB>(
+    builder: &mut QueryBuilder<'a, DB>,
+    field: &str,
+    from: Option<i64>,
+    to: Option<i64>,
+) where
+    DB: Database,
+    i64: 'a + Encode<'a, DB> + Type<DB>,
+{
+    if let Some(from) = from {
+        builder.push(format!(" AND {} >= ", field)).push_bind(from);
+    }
+    if let Some(to) = to {
+        builder.push(format!(" AND {} < ", field)).push_bind(to);
+    }
+}
+
+fn push_cursor_value<'a, DB>(builder: &mut QueryBuilder<'a, DB>, value: &CursorValue)
+where
+    DB: Database,
+    i64: 'a + Encode<'a, DB> + Type<DB>,
+    String: 'a + Encode<'a, DB> + Type<DB>,
+{
+    match value {
+        CursorValue::Int(value) => builder.push_bind(*value),
+        CursorValue::Str(value) => builder.push_bind(value.clone()),
+        // Nullable sort fields are compared through `COALESCE(field, '')`.
+        CursorValue::Null => builder.push_bind(String::new()),
+    };
+}
+
+/// Keyset condition, ordering and `limit + 1` for one page ordered by `field` then `id_field`.
+pub(crate) fn push_page<'a, DB>(
+    builder: &mut QueryBuilder<'a, DB>,
+    field: &str,
+    id_field: &str,
+    order: SortOrder,
+    cursor: Option<Cursor>,
+    limit: u32,
+) where
+    DB: Database,
+    i64: 'a + Encode<'a, DB> + Type<DB>,
+    String: 'a + Encode<'a, DB> + Type<DB>,
+{
+    let (op, direction) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+    if let Some(cursor) = cursor {
+        builder.push(format!(" AND ({} {} ", field, op));
+        push_cursor_value(builder, &cursor.value);
+        builder.push(format!(" OR ({} = ", field));
+        push_cursor_value(builder, &cursor.value);
+        builder
+            .push(format!(" AND {} {} ", id_field, op))
+            .push_bind(cursor.id)
+            .push("))");
+    }
+    builder
+        .push(format!(
+            " ORDER BY {} {}, {} {} LIMIT ",
+            field, direction, id_field, direction
+        ))
+        .push_bind(limit as i64 + 1);
+}
+
+/// Attaches grade items to their `(courseid, coursename)` rows, keeping only items matching `status`.
+pub(crate) fn assemble_grades(
+    rows: Vec<(i64, Option<String>)>,
+    items: Vec<GradeItemRow>,
+    status: Option<GradeStatus>,
+) -> Vec<Grade> {
+    let mut items_by_course: HashMap<i64, Vec<GradeItems>> = HashMap::new();
+    for (courseid, id, itemname, percentageformatted) in items {
+        let graded = percentageformatted != UNGRADED;
+        if status.is_some_and(|status| graded != (status == GradeStatus::Graded)) {
+            continue;
+        }
+        items_by_course
+            .entry(courseid)
+            .or_default()
+            .push(GradeItems {
+                id,
+                itemname,
+                percentageformatted,
+            });
+    }
+
+    rows.into_iter()
+        .map(|(courseid, coursename)| Grade {
+            coursename,
+            courseid,
+            gradeitems: items_by_course.remove(&courseid).unwrap_or_default(),
+        })
+        .collect()
+}
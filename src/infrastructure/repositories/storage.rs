@@ -0,0 +1,214 @@
+use async_trait::async_trait;
+
+use crate::domain::entities::course::Course;
+use crate::domain::entities::deadline::Deadline;
+use crate::domain::entities::grade::{Grade, GradeOverview, GradesOverview};
+use crate::domain::entities::query::{CourseQuery, DeadlineQuery, GradeQuery, Page};
+use crate::domain::entities::registration::Registration;
+use crate::domain::entities::sync_cursor::SyncCursor;
+use crate::domain::entities::token::Token;
+use crate::domain::entities::user::User;
+use crate::domain::repositories::data_repository_abstract::{
+    CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
+    TokenRepositoryAbstract, UserRepositoryAbstract,
+};
+
+use super::cached_repository::CachedRepository;
+use super::data_repository::DataRepository;
+use super::errors::DbError;
+use super::in_memory_repository::InMemoryRepository;
+use super::postgres_repository::PostgresRepository;
+use super::sqlite_repository::SqliteRepository;
+
+/// The configured backend behind the read cache, what the services run against.
+pub type CachedStorage = CachedRepository<Storage>;
+
+/// Repository backend picked at startup from the `STORAGE` setting.
+#[derive(Debug)]
+pub enum Storage {
+    Mongo(DataRepository),
+    InMemory(InMemoryRepository),
+    Postgres(PostgresRepository),
+    Sqlite(SqliteRepository),
+}
+
+macro_rules! dispatch {
+    ($self:ident, $repository:ident => $call:expr) => {
+        match $self {
+            Storage::Mongo($repository) => $call,
+            Storage::InMemory($repository) => $call,
+            Storage::Postgres($repository) => $call,
+            Storage::Sqlite($repository) => $call,
+        }
+    };
+}
+
+#[async_trait]
+impl TokenRepositoryAbstract for Storage {
+    async fn find_token(&self, token: &Token) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.find_token(token).await)
+    }
+
+    async fn save_tokens(&self, token: &Token) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_tokens(token).await)
+    }
+
+    async fn find_all_device_tokens(&self, limit: i64, skip: u64) -> Result<Vec<Token>, DbError> {
+        dispatch!(self, repository => repository.find_all_device_tokens(limit, skip).await)
+    }
+
+    async fn delete(&self, token: &str) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.delete(token).await)
+    }
+
+    async fn save_feed_token(&self, token: &str, feed_token: &str) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_feed_token(token, feed_token).await)
+    }
+
+    async fn find_token_by_feed_token(&self, feed_token: &str) -> Result<String, DbError> {
+        dispatch!(self, repository => repository.find_token_by_feed_token(feed_token).await)
+    }
+
+    async fn delete_feed_token(&self, token: &str) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.delete_feed_token(token).await)
+    }
+
+    async fn save_registration(&self, registration: &Registration) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_registration(registration).await)
+    }
+
+    async fn claim_due_tokens(
+        &self,
+        worker: &str,
+        now: i64,
+        lease_until: i64,
+        limit: i64,
+    ) -> Result<Vec<Token>, DbError> {
+        dispatch!(self, repository => repository.claim_due_tokens(worker, now, lease_until, limit).await)
+    }
+
+    async fn renew_lease(
+        &self,
+        token: &str,
+        worker: &str,
+        lease_until: i64,
+    ) -> Result<bool, DbError> {
+        dispatch!(self, repository => repository.renew_lease(token, worker, lease_until).await)
+    }
+
+    async fn schedule_poll(
+        &self,
+        token: &str,
+        worker: &str,
+        next_poll_at: i64,
+    ) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.schedule_poll(token, worker, next_poll_at).await)
+    }
+
+    async fn suspend(&self, token: &str, suspended_at: i64) -> Result<bool, DbError> {
+        dispatch!(self, repository => repository.suspend(token, suspended_at).await)
+    }
+
+    async fn find_tenant(&self, token: &str) -> Result<Option<String>, DbError> {
+        dispatch!(self, repository => repository.find_tenant(token).await)
+    }
+
+    async fn relink(&self, old: &str, new: &str) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.relink(old, new).await)
+    }
+}
+
+#[async_trait]
+impl UserRepositoryAbstract for Storage {
+    async fn find_user_by_token(&self, token: &str) -> Result<User, DbError> {
+        dispatch!(self, repository => repository.find_user_by_token(token).await)
+    }
+
+    async fn save_user(&self, user: &User, token: &str) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_user(user, token).await)
+    }
+}
+
+#[async_trait]
+impl CourseRepositoryAbstract for Storage {
+    async fn save_courses(&self, token: &str, courses: &[Course]) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_courses(token, courses).await)
+    }
+
+    async fn find_courses_by_token(&self, token: &str) -> Result<Vec<Course>, DbError> {
+        dispatch!(self, repository => repository.find_courses_by_token(token).await)
+    }
+
+    async fn find_courses_page(
+        &self,
+        token: &str,
+        query: &CourseQuery,
+    ) -> Result<Page<Course>, DbError> {
+        dispatch!(self, repository => repository.find_courses_page(token, query).await)
+    }
+
+    async fn find_sync_cursors(&self, token: &str) -> Result<Vec<SyncCursor>, DbError> {
+        dispatch!(self, repository => repository.find_sync_cursors(token).await)
+    }
+
+    async fn save_sync_cursors(&self, token: &str, cursors: &[SyncCursor]) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_sync_cursors(token, cursors).await)
+    }
+}
+
+#[async_trait]
+impl GradeRepositoryAbstract for Storage {
+    async fn save_grades(&self, token: &str, grades: &[Grade]) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_grades(token, grades).await)
+    }
+
+    async fn find_grades_by_token(&self, token: &str) -> Result<Vec<Grade>, DbError> {
+        dispatch!(self, repository => repository.find_grades_by_token(token).await)
+    }
+
+    async fn find_grades_page(
+        &self,
+        token: &str,
+        query: &GradeQuery,
+    ) -> Result<Page<Grade>, DbError> {
+        dispatch!(self, repository => repository.find_grades_page(token, query).await)
+    }
+
+    async fn save_grades_overview(
+        &self,
+        token: &str,
+        grades_overview: &GradesOverview,
+    ) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_grades_overview(token, grades_overview).await)
+    }
+
+    async fn find_grades_overview_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Vec<GradeOverview>, DbError> {
+        dispatch!(self, repository => repository.find_grades_overview_by_token(token).await)
+    }
+}
+
+#[async_trait]
+impl DeadlineRepositoryAbstract for Storage {
+    async fn save_deadlines(&self, token: &str, deadlines: &[Deadline]) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.save_deadlines(token, deadlines).await)
+    }
+
+    async fn find_deadlines_by_token(&self, token: &str) -> Result<Vec<Deadline>, DbError> {
+        dispatch!(self, repository => repository.find_deadlines_by_token(token).await)
+    }
+
+    async fn find_deadlines_page(
+        &self,
+        token: &str,
+        query: &DeadlineQuery,
+    ) -> Result<Page<Deadline>, DbError> {
+        dispatch!(self, repository => repository.find_deadlines_page(token, query).await)
+    }
+
+    async fn delete_expired_deadlines(&self, unix_date: u64) -> Result<(), DbError> {
+        dispatch!(self, repository => repository.delete_expired_deadlines(unix_date).await)
+    }
+}
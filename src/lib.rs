@@ -1,38 +1,66 @@
-use config::Config;
-use domain::services::notification_service::NotificationService;
+use actix_web::web;
+use config::{Config, NotificationBackend};
+use domain::data_providers::notification_provider_abstract::NotificationProviderAbstract;
 use infrastructure::{
     app_setup::{
-        initialize_dependencies, server, spawn_deadline_cleaner_worker, spawn_notification_worker,
+        build_dependencies, initialize_dependencies, migrate_database, server,
+        spawn_deadline_cleaner_worker, spawn_notification_worker, AppDependencies,
     },
-    data_providers::moodle_client::MoodleClient,
-    notification_provider::firebase_messages_client::FirebaseMessagesClient,
-    repositories::data_repository::DataRepository,
+    data_providers::tenant_router::TenantRouter,
+    notification_provider::recording_notification_provider::RecordingNotificationProvider,
+    repositories::storage::CachedStorage,
 };
-use std::error::Error;
-use tokio::sync::OnceCell;
+use log::warn;
+use presentation::handlers::admin_handler::NotificationInbox;
+use std::{error::Error, sync::Arc};
 
 pub mod config;
 pub mod domain;
 pub mod infrastructure;
 pub mod presentation;
-static NOTIFICATION_SERVICE: OnceCell<
-    NotificationService<
-        FirebaseMessagesClient,
-        MoodleClient,
-        DataRepository,
-        DataRepository,
-        DataRepository,
-        DataRepository,
-        DataRepository,
-    >,
-> = OnceCell::const_new();
 
 pub async fn run(config: &Config) -> Result<(), Box<dyn Error>> {
-    let deps = initialize_dependencies(config).await?;
-    NOTIFICATION_SERVICE.set(deps.notification_service).unwrap();
+    match config.notifications {
+        NotificationBackend::Firebase => {
+            let deps = initialize_dependencies(config).await?;
+            serve(config, deps, None).await
+        }
+        NotificationBackend::Recording => {
+            warn!("Recording pushes instead of sending them, see /api/admin/notifications");
+            let recorder = Arc::new(RecordingNotificationProvider::default());
+            let deps = build_dependencies(config, Arc::clone(&recorder)).await?;
+            let inbox = NotificationInbox {
+                recorder,
+                admin_token: config.admin_token.clone(),
+            };
+            serve(config, deps, Some(web::Data::new(inbox))).await
+        }
+    }
+}
 
-    spawn_notification_worker(NOTIFICATION_SERVICE.get().unwrap(), config.batch_size).await;
+async fn serve<NotificationProvider>(
+    config: &Config,
+    deps: AppDependencies<
+        NotificationProvider,
+        TenantRouter,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+    >,
+    inbox: Option<web::Data<NotificationInbox>>,
+) -> Result<(), Box<dyn Error>>
+where
+    NotificationProvider: NotificationProviderAbstract + 'static,
+{
+    spawn_notification_worker(deps.notification_service, config.batch_size).await;
     spawn_deadline_cleaner_worker(deps.deadline_service).await;
-    server(deps.app_state, &config.port).await?;
+    server(deps.app_state, inbox, &config.port).await?;
     Ok(())
 }
+
+/// One-off data migrations, run with `aitu-web-app migrate`.
+pub async fn migrate(config: &Config) -> Result<(), Box<dyn Error>> {
+    migrate_database(config).await
+}
@@ -1,6 +1,6 @@
-use aitu_web_app::{config::Config, run};
+use aitu_web_app::{config::Config, migrate, run};
 use dotenv::dotenv;
-use std::error::Error;
+use std::{env, error::Error};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -10,6 +10,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let config = Config::from_env()?;
 
-    run(&config).await?;
+    match env::args().nth(1).as_deref() {
+        Some("migrate") => migrate(&config).await?,
+        _ => run(&config).await?,
+    }
     Ok(())
 }
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use actix_web::{delete, get, web, HttpResponse, Responder};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    domain::entities::errors::ServiceError,
+    infrastructure::notification_provider::recording_notification_provider::RecordingNotificationProvider,
+    presentation::shared::auth::BearerToken,
+};
+
+/// Pushes captured with `NOTIFICATIONS=recording`, readable with the `ADMIN_TOKEN` bearer.
+pub struct NotificationInbox {
+    pub recorder: Arc<RecordingNotificationProvider>,
+    /// Required by the config in recording mode, every request is refused without it.
+    pub admin_token: Option<String>,
+}
+
+impl NotificationInbox {
+    /// Compares in constant time, so response times don't reveal how much of the token matched.
+    fn authorize(&self, token: BearerToken) -> Result<(), ServiceError> {
+        match &self.admin_token {
+            Some(admin_token)
+                if bool::from(token.into_inner().as_bytes().ct_eq(admin_token.as_bytes())) =>
+            {
+                Ok(())
+            }
+            _ => Err(ServiceError::Unauthorized),
+        }
+    }
+}
+
+/// Only mounted in recording mode, see `server`.
+pub fn admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/admin")
+            .service(get_notifications)
+            .service(clear_notifications),
+    );
+}
+
+#[derive(Deserialize)]
+struct NotificationQuery {
+    device_token: Option<String>,
+}
+
+#[get("/notifications")]
+async fn get_notifications(
+    token: BearerToken,
+    query: web::Query<NotificationQuery>,
+    inbox: web::Data<NotificationInbox>,
+) -> Result<impl Responder, ServiceError> {
+    inbox.authorize(token)?;
+    let pushes = match &query.device_token {
+        Some(device_token) => inbox.recorder.pushes_to(device_token),
+        None => inbox.recorder.pushes(),
+    };
+    Ok(HttpResponse::Ok().json(pushes))
+}
+
+#[delete("/notifications")]
+async fn clear_notifications(
+    token: BearerToken,
+    inbox: web::Data<NotificationInbox>,
+) -> Result<impl Responder, ServiceError> {
+    inbox.authorize(token)?;
+    inbox.recorder.clear();
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::Value;
+
+    use crate::domain::data_providers::notification_provider_abstract::NotificationProviderAbstract;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_notifications_need_admin_token() {
+        let recorder = Arc::new(RecordingNotificationProvider::default());
+        for device in ["a", "b"] {
+            let message = recorder.create_message(device, "New grade", "Quiz 1");
+            recorder.send_notification(message).await.unwrap();
+        }
+        let inbox = web::Data::new(NotificationInbox {
+            recorder: Arc::clone(&recorder),
+            admin_token: Some("secret".to_owned()),
+        });
+        let app = test::init_service(App::new().app_data(inbox).configure(admin_routes)).await;
+
+        for bearer in ["Bearer wrong", "Bearer secre", "Bearer secrets"] {
+            let request = test::TestRequest::get()
+                .uri("/api/admin/notifications")
+                .insert_header(("Authorization", bearer))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", bearer);
+        }
+
+        let request = test::TestRequest::get()
+            .uri("/api/admin/notifications?device_token=b")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let pushes: Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(pushes.as_array().unwrap().len(), 1);
+        assert_eq!(pushes[0]["title"], "New grade");
+
+        let request = test::TestRequest::delete()
+            .uri("/api/admin/notifications")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        recorder.assert_none_sent();
+    }
+
+    #[actix_web::test]
+    async fn test_notifications_refused_without_admin_token() {
+        let inbox = web::Data::new(NotificationInbox {
+            recorder: Arc::new(RecordingNotificationProvider::default()),
+            admin_token: None,
+        });
+        let app = test::init_service(App::new().app_data(inbox).configure(admin_routes)).await;
+
+        let request = test::TestRequest::get()
+            .uri("/api/admin/notifications")
+            .insert_header(("Authorization", "Bearer anything"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -2,15 +2,14 @@ use actix_web::{get, guard, web, HttpResponse, Responder};
 
 use crate::{
     domain::entities::errors::ServiceError,
-    infrastructure::{
-        data_providers::moodle_client::MoodleClient, repositories::data_repository::DataRepository,
-    },
-    presentation::shared::app_state::AppState,
+    presentation::shared::{app_state::SharedState, deprecation::deprecated_route},
 };
 
+/// Legacy routes, kept as deprecated aliases of the `/api/v1` surface.
 pub fn course_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/courses")
+            .wrap(deprecated_route("/api/v1/me/courses"))
             .guard(guard::Get())
             .service(get_courses),
     );
@@ -19,16 +18,7 @@ pub fn course_routes(cfg: &mut web::ServiceConfig) {
 #[get("/get_courses/{token}")]
 async fn get_courses(
     token: web::Path<String>,
-    app_state: web::Data<
-        AppState<
-            MoodleClient,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-        >,
-    >,
+    app_state: SharedState,
 ) -> Result<impl Responder, ServiceError> {
     let token = token.into_inner();
     let courses = app_state.course_service.get_courses(&token).await?;
@@ -2,15 +2,14 @@ use actix_web::{get, guard, web, HttpResponse, Responder};
 
 use crate::{
     domain::entities::errors::ServiceError,
-    infrastructure::{
-        data_providers::moodle_client::MoodleClient, repositories::data_repository::DataRepository,
-    },
-    presentation::shared::app_state::AppState,
+    presentation::shared::{app_state::SharedState, deprecation::deprecated_route},
 };
 
+/// Legacy routes, kept as deprecated aliases of the `/api/v1` surface.
 pub fn deadline_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/deadlines")
+            .wrap(deprecated_route("/api/v1/me/deadlines"))
             .guard(guard::Get())
             .service(get_deadlines),
     );
@@ -19,16 +18,7 @@ pub fn deadline_routes(cfg: &mut web::ServiceConfig) {
 #[get("/get_deadlines/{token}")]
 async fn get_deadlines(
     token: web::Path<String>,
-    app_state: web::Data<
-        AppState<
-            MoodleClient,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-        >,
-    >,
+    app_state: SharedState,
 ) -> Result<impl Responder, ServiceError> {
     let deadlines = app_state
         .deadline_service
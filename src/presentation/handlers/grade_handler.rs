@@ -2,15 +2,14 @@ use actix_web::{get, guard, web, HttpResponse, Responder};
 
 use crate::{
     domain::entities::errors::ServiceError,
-    infrastructure::{
-        data_providers::moodle_client::MoodleClient, repositories::data_repository::DataRepository,
-    },
-    presentation::shared::app_state::AppState,
+    presentation::shared::{app_state::SharedState, deprecation::deprecated_route},
 };
 
+/// Legacy routes, kept as deprecated aliases of the `/api/v1` surface.
 pub fn grade_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/grades")
+            .wrap(deprecated_route("/api/v1/me/grades"))
             .guard(guard::Get())
             .service(get_grades)
             .service(get_grades_overview),
@@ -20,16 +19,7 @@ pub fn grade_routes(cfg: &mut web::ServiceConfig) {
 #[get("/get_grades/{token}")]
 async fn get_grades(
     token: web::Path<String>,
-    app_state: web::Data<
-        AppState<
-            MoodleClient,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-        >,
-    >,
+    app_state: SharedState,
 ) -> Result<impl Responder, ServiceError> {
     let grades = app_state
         .grade_service
@@ -41,16 +31,7 @@ async fn get_grades(
 #[get("/get_grades_overview/{token}")]
 async fn get_grades_overview(
     token: web::Path<String>,
-    app_state: web::Data<
-        AppState<
-            MoodleClient,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-            DataRepository,
-        >,
-    >,
+    app_state: SharedState,
 ) -> Result<impl Responder, ServiceError> {
     let grades = app_state
         .grade_service
@@ -1,4 +1,7 @@
+pub mod admin_handler;
 pub mod course_handler;
 pub mod deadline_handler;
 pub mod grade_handler;
+pub mod stream_handler;
 pub mod user_handler;
+pub mod v1;
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use actix_web::{get, web, web::Bytes, HttpRequest, HttpResponse};
+use actix_ws::Message;
+use futures::{stream, StreamExt};
+use log::warn;
+use serde::Deserialize;
+use tokio::{
+    sync::broadcast::{error::RecvError, Receiver},
+    time::{interval, Interval},
+};
+
+use crate::{
+    domain::{
+        entities::{change_event::ChangeEvent, errors::ServiceError},
+        services::live_update_service::LiveUpdateService,
+    },
+    presentation::shared::{app_state::SharedState, auth::BearerToken},
+};
+
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+pub fn stream_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/stream")
+            .service(sse_stream)
+            .service(ws_stream),
+    );
+}
+
+/// Browsers can't set headers on `EventSource`, so they pass a ticket from
+/// `POST /api/v1/stream/ticket` instead of the token.
+#[derive(Deserialize)]
+struct StreamQuery {
+    ticket: Option<String>,
+}
+
+async fn resolve_token(
+    bearer: Option<BearerToken>,
+    query: web::Query<StreamQuery>,
+    live_updates: &LiveUpdateService,
+) -> Result<String, ServiceError> {
+    match (bearer, query.into_inner().ticket) {
+        (Some(bearer), _) => Ok(bearer.into_inner()),
+        (None, Some(ticket)) => live_updates
+            .redeem_ticket(&ticket)
+            .await
+            .ok_or(ServiceError::Unauthorized),
+        (None, None) => Err(ServiceError::Unauthorized),
+    }
+}
+
+enum Next {
+    Event(ChangeEvent),
+    KeepAlive,
+    Closed,
+}
+
+async fn next_event(receiver: &mut Receiver<ChangeEvent>, keep_alive: &mut Interval) -> Next {
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => return Next::Event(event),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Live update subscriber lagged, {} events skipped", skipped);
+                }
+                Err(RecvError::Closed) => return Next::Closed,
+            },
+            _ = keep_alive.tick() => return Next::KeepAlive,
+        }
+    }
+}
+
+#[get("")]
+async fn sse_stream(
+    bearer: Option<BearerToken>,
+    query: web::Query<StreamQuery>,
+    app_state: SharedState,
+) -> Result<HttpResponse, ServiceError> {
+    let token = resolve_token(bearer, query, &app_state.live_updates).await?;
+    app_state.user_service.get_user(&token).await?;
+
+    let receiver = app_state.live_updates.subscribe(&token).await;
+    let body = stream::unfold(
+        (receiver, interval(KEEP_ALIVE)),
+        |(mut receiver, mut keep_alive)| async move {
+            let chunk = match next_event(&mut receiver, &mut keep_alive).await {
+                Next::Event(event) => format!(
+                    "event: {}\ndata: {}\n\n",
+                    event.kind.as_str(),
+                    serde_json::to_string(&event).ok()?
+                ),
+                Next::KeepAlive => ": keep-alive\n\n".to_owned(),
+                Next::Closed => return None,
+            };
+            Some((
+                Ok::<_, actix_web::Error>(Bytes::from(chunk)),
+                (receiver, keep_alive),
+            ))
+        },
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body))
+}
+
+#[get("/ws")]
+async fn ws_stream(
+    req: HttpRequest,
+    payload: web::Payload,
+    bearer: Option<BearerToken>,
+    query: web::Query<StreamQuery>,
+    app_state: SharedState,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = resolve_token(bearer, query, &app_state.live_updates).await?;
+    app_state.user_service.get_user(&token).await?;
+
+    let (response, mut session, mut messages) = actix_ws::handle(&req, payload)?;
+    let mut receiver = app_state.live_updates.subscribe(&token).await;
+
+    actix_web::rt::spawn(async move {
+        let mut keep_alive = interval(KEEP_ALIVE);
+        loop {
+            tokio::select! {
+                next = next_event(&mut receiver, &mut keep_alive) => {
+                    let sent = match next {
+                        Next::Event(event) => match serde_json::to_string(&event) {
+                            Ok(text) => session.text(text).await,
+                            Err(_) => continue,
+                        },
+                        Next::KeepAlive => session.ping(b"").await,
+                        Next::Closed => break,
+                    };
+                    if sent.is_err() {
+                        return;
+                    }
+                }
+                message = messages.next() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
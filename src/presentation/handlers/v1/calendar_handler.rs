@@ -0,0 +1,101 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::entities::errors::ServiceError,
+    presentation::shared::{app_state::SharedState, auth::BearerToken, errors::ApiError},
+};
+
+pub fn calendar_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_calendar_feed)
+        .service(revoke_calendar_feed)
+        .service(get_calendar_feed);
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CalendarFeed {
+    feed_token: String,
+    feed_url: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/calendar",
+    tag = "calendar",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 201, description = "New feed URL, any previous one stops working", body = CalendarFeed),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[post("/me/calendar")]
+async fn create_calendar_feed(
+    req: HttpRequest,
+    token: BearerToken,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let feed_token = app_state
+        .token_service
+        .create_feed_token(&token.into_inner())
+        .await?;
+    let connection = req.connection_info();
+    let feed_url = format!(
+        "{}://{}/api/v1/calendar/{}.ics",
+        connection.scheme(),
+        connection.host(),
+        feed_token
+    );
+    Ok(HttpResponse::Created().json(CalendarFeed {
+        feed_token,
+        feed_url,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me/calendar",
+    tag = "calendar",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 204, description = "Feed URL was revoked"),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+    )
+)]
+#[delete("/me/calendar")]
+async fn revoke_calendar_feed(
+    token: BearerToken,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    app_state
+        .token_service
+        .revoke_feed_token(&token.into_inner())
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/calendar/{feed_token}.ics",
+    tag = "calendar",
+    params(("feed_token" = String, Path, description = "Secret from `POST /api/v1/me/calendar`")),
+    responses(
+        (status = 200, description = "iCalendar feed of stored deadlines", content_type = "text/calendar"),
+        (status = 404, description = "Unknown or revoked feed token", body = ApiError),
+    )
+)]
+#[get("/calendar/{feed_token}.ics")]
+async fn get_calendar_feed(
+    feed_token: web::Path<String>,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let token = app_state
+        .token_service
+        .find_token_by_feed_token(&feed_token.into_inner())
+        .await?;
+    let calendar = app_state.deadline_service.get_calendar(&token).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(calendar))
+}
@@ -0,0 +1,68 @@
+use actix_web::{get, http::header::ContentDisposition, web, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::entities::errors::ServiceError,
+    infrastructure::exporters::{
+        csv_exporter::render_csv, pdf_exporter::render_pdf, xlsx_exporter::render_xlsx,
+    },
+    presentation::shared::{app_state::SharedState, auth::BearerToken, errors::ApiError},
+};
+
+pub fn export_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(export_grades);
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+    Pdf,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/grades/export/{format}",
+    tag = "me",
+    security(("bearer_token" = [])),
+    params(("format" = ExportFormat, Path, description = "`csv`, `xlsx` or `pdf` (unofficial transcript)")),
+    responses(
+        (status = 200, description = "Stored grades and course totals as a downloadable file"),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[get("/me/grades/export/{format}")]
+async fn export_grades(
+    format: web::Path<ExportFormat>,
+    token: BearerToken,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let token = token.into_inner();
+    let transcript = app_state.grade_service.get_transcript(&token).await?;
+
+    let (body, content_type, file_name) = match format.into_inner() {
+        ExportFormat::Csv => (render_csv(&transcript)?, "text/csv", "grades.csv"),
+        ExportFormat::Xlsx => (
+            render_xlsx(&transcript)?,
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            "grades.xlsx",
+        ),
+        ExportFormat::Pdf => {
+            let user = app_state.user_service.get_user(&token).await?;
+            (
+                render_pdf(&user, &transcript, Utc::now())?,
+                "application/pdf",
+                "transcript.pdf",
+            )
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition::attachment(file_name))
+        .body(body))
+}
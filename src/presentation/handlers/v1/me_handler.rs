@@ -0,0 +1,224 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::entities::{
+        course::Course,
+        deadline::Deadline,
+        errors::ServiceError,
+        grade::{Grade, GradeOverview},
+        query::{CourseQuery, DeadlineQuery, GradeQuery, Page},
+        tenant::Tenant,
+        user::User,
+    },
+    presentation::shared::{app_state::SharedState, auth::BearerToken, errors::ApiError},
+};
+
+pub fn me_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_me)
+        .service(delete_me)
+        .service(relink_me)
+        .service(get_my_tenant)
+        .service(get_my_courses)
+        .service(get_my_grades)
+        .service(get_my_grades_overview)
+        .service(get_my_deadlines);
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me",
+    tag = "me",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "Stored Moodle profile", body = User),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[get("/me")]
+async fn get_me(
+    token: BearerToken,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let user = app_state.user_service.get_user(&token.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me",
+    tag = "me",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 204, description = "User and all stored data were deleted"),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[delete("/me")]
+async fn delete_me(
+    token: BearerToken,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let token = token.into_inner();
+    app_state.token_service.delete_one_user(&token).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// New Moodle token replacing the bearer token, e.g. after a password change.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Relink {
+    pub token: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/relink",
+    tag = "me",
+    security(("bearer_token" = [])),
+    request_body = Relink,
+    responses(
+        (status = 204, description = "Token was replaced, stored data and polling resume"),
+        (status = 400, description = "Moodle rejected the new token", body = ApiError),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+        (status = 409, description = "New token belongs to another Moodle account", body = ApiError),
+    )
+)]
+#[post("/me/relink")]
+async fn relink_me(
+    token: BearerToken,
+    relink: web::Json<Relink>,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    app_state
+        .token_service
+        .relink(&token.into_inner(), &relink.token)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/tenant",
+    tag = "me",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "School the user belongs to, with its branding", body = Tenant),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[get("/me/tenant")]
+async fn get_my_tenant(
+    token: BearerToken,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let token = token.into_inner();
+    app_state.user_service.get_user(&token).await?;
+    let tenant = app_state.data_provider.tenant_of(&token).await?;
+    Ok(HttpResponse::Ok().json(tenant))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/courses",
+    tag = "me",
+    security(("bearer_token" = [])),
+    params(CourseQuery),
+    responses(
+        (status = 200, description = "Page of stored courses", body = Page<Course>),
+        (status = 400, description = "Malformed cursor or one from another sort", body = ApiError),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[get("/me/courses")]
+async fn get_my_courses(
+    token: BearerToken,
+    query: web::Query<CourseQuery>,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let courses = app_state
+        .course_service
+        .find_courses(&token.into_inner(), &query)
+        .await?;
+    Ok(HttpResponse::Ok().json(courses))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/grades",
+    tag = "me",
+    security(("bearer_token" = [])),
+    params(GradeQuery),
+    responses(
+        (status = 200, description = "Page of stored grade items grouped by course", body = Page<Grade>),
+        (status = 400, description = "Malformed cursor or one from another sort", body = ApiError),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[get("/me/grades")]
+async fn get_my_grades(
+    token: BearerToken,
+    query: web::Query<GradeQuery>,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let grades = app_state
+        .grade_service
+        .find_grades(&token.into_inner(), &query)
+        .await?;
+    Ok(HttpResponse::Ok().json(grades))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/grades/overview",
+    tag = "me",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "Stored course total grades", body = [GradeOverview]),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[get("/me/grades/overview")]
+async fn get_my_grades_overview(
+    token: BearerToken,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let grades = app_state
+        .grade_service
+        .get_grades_overview(&token.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(grades))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/deadlines",
+    tag = "me",
+    security(("bearer_token" = [])),
+    params(DeadlineQuery),
+    responses(
+        (status = 200, description = "Page of stored deadlines", body = Page<Deadline>),
+        (status = 400, description = "Malformed cursor or one from another sort", body = ApiError),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[get("/me/deadlines")]
+async fn get_my_deadlines(
+    token: BearerToken,
+    query: web::Query<DeadlineQuery>,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let deadlines = app_state
+        .deadline_service
+        .find_deadlines(&token.into_inner(), &query)
+        .await?;
+    Ok(HttpResponse::Ok().json(deadlines))
+}
@@ -0,0 +1,34 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use utoipa::OpenApi;
+
+use crate::presentation::openapi::ApiDoc;
+
+use self::{
+    calendar_handler::calendar_routes, export_handler::export_routes, me_handler::me_routes,
+    stream_handler::stream_ticket_routes, tenant_handler::tenant_routes, user_handler::user_routes,
+};
+
+pub mod calendar_handler;
+pub mod export_handler;
+pub mod me_handler;
+pub mod stream_handler;
+pub mod tenant_handler;
+pub mod user_handler;
+
+pub fn v1_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/v1")
+            .configure(user_routes)
+            .configure(me_routes)
+            .configure(calendar_routes)
+            .configure(export_routes)
+            .configure(tenant_routes)
+            .configure(stream_ticket_routes)
+            .service(openapi_json),
+    );
+}
+
+#[get("/openapi.json")]
+async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
@@ -0,0 +1,43 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::{entities::errors::ServiceError, services::live_update_service::TICKET_TTL},
+    presentation::shared::{app_state::SharedState, auth::BearerToken, errors::ApiError},
+};
+
+pub fn stream_ticket_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_stream_ticket);
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StreamTicket {
+    ticket: String,
+    /// Seconds left to open the stream with `?ticket=`.
+    expires_in: u64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/stream/ticket",
+    tag = "stream",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 201, description = "Single-use ticket for `/api/stream?ticket=`", body = StreamTicket),
+        (status = 401, description = "Missing bearer token", body = ApiError),
+        (status = 404, description = "User is not registered", body = ApiError),
+    )
+)]
+#[post("/stream/ticket")]
+async fn create_stream_ticket(
+    token: BearerToken,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    let token = token.into_inner();
+    app_state.user_service.get_user(&token).await?;
+    Ok(HttpResponse::Created().json(StreamTicket {
+        ticket: app_state.live_updates.issue_ticket(&token).await,
+        expires_in: TICKET_TTL.as_secs(),
+    }))
+}
@@ -0,0 +1,20 @@
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::{domain::entities::tenant::Tenant, presentation::shared::app_state::SharedState};
+
+pub fn tenant_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_tenants);
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tenants",
+    tag = "tenants",
+    responses(
+        (status = 200, description = "Schools users can register with, the first is the default", body = Vec<Tenant>),
+    )
+)]
+#[get("/tenants")]
+async fn get_tenants(app_state: SharedState) -> impl Responder {
+    HttpResponse::Ok().json(app_state.data_provider.tenants())
+}
@@ -0,0 +1,33 @@
+use actix_web::{post, web, HttpResponse, Responder};
+
+use crate::{
+    domain::entities::{errors::ServiceError, token::Token},
+    presentation::shared::{app_state::SharedState, errors::ApiError},
+};
+
+pub fn user_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_user);
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/users",
+    tag = "users",
+    request_body = Token,
+    responses(
+        (status = 201, description = "User was registered and initial data was stored"),
+        (status = 202, description = "User is already registered", body = ApiError),
+        (status = 400, description = "Moodle rejected the token or the tenant is unknown", body = ApiError),
+    )
+)]
+#[post("/users")]
+async fn create_user(
+    token: web::Json<Token>,
+    app_state: SharedState,
+) -> Result<impl Responder, ServiceError> {
+    app_state
+        .token_service
+        .register_user(&token.into_inner())
+        .await?;
+    Ok(HttpResponse::Created().finish())
+}
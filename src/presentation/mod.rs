@@ -1,2 +1,3 @@
 pub mod handlers;
+pub mod openapi;
 pub mod shared;
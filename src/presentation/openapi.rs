@@ -0,0 +1,120 @@
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    domain::entities::{
+        course::Course,
+        deadline::Deadline,
+        grade::{Grade, GradeItems, GradeOverview},
+        tenant::{Branding, GradeBand, GradeScale, Tenant},
+        token::Token,
+        user::User,
+    },
+    presentation::{
+        handlers::v1::{
+            self, calendar_handler::CalendarFeed, export_handler::ExportFormat, me_handler::Relink,
+            stream_handler::StreamTicket,
+        },
+        shared::errors::ApiError,
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "aitu-keeper", version = "1"),
+    paths(
+        v1::user_handler::create_user,
+        v1::me_handler::get_me,
+        v1::me_handler::delete_me,
+        v1::me_handler::relink_me,
+        v1::me_handler::get_my_tenant,
+        v1::me_handler::get_my_courses,
+        v1::me_handler::get_my_grades,
+        v1::me_handler::get_my_grades_overview,
+        v1::me_handler::get_my_deadlines,
+        v1::calendar_handler::create_calendar_feed,
+        v1::calendar_handler::revoke_calendar_feed,
+        v1::calendar_handler::get_calendar_feed,
+        v1::export_handler::export_grades,
+        v1::tenant_handler::get_tenants,
+        v1::stream_handler::create_stream_ticket,
+    ),
+    components(schemas(
+        ApiError,
+        CalendarFeed,
+        ExportFormat,
+        Course,
+        Deadline,
+        Grade,
+        GradeItems,
+        GradeOverview,
+        Relink,
+        StreamTicket,
+        Tenant,
+        Branding,
+        GradeScale,
+        GradeBand,
+        Token,
+        User
+    )),
+    modifiers(&BearerTokenAddon),
+    tags(
+        (name = "users", description = "Registration"),
+        (name = "me", description = "Data of the user owning the bearer token"),
+        (name = "calendar", description = "Subscribable iCalendar feed of deadlines"),
+        (name = "tenants", description = "Schools served by this deployment"),
+        (name = "stream", description = "Tickets for live updates at `/api/stream`"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Moodle tokens are sent as `Authorization: Bearer <token>`.
+struct BearerTokenAddon;
+
+impl Modify for BearerTokenAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_contains_v1_paths() {
+        let openapi = ApiDoc::openapi();
+        for path in [
+            "/api/v1/users",
+            "/api/v1/me",
+            "/api/v1/me/relink",
+            "/api/v1/me/tenant",
+            "/api/v1/tenants",
+            "/api/v1/me/courses",
+            "/api/v1/me/grades",
+            "/api/v1/me/grades/overview",
+            "/api/v1/me/deadlines",
+            "/api/v1/me/calendar",
+            "/api/v1/calendar/{feed_token}.ics",
+            "/api/v1/me/grades/export/{format}",
+            "/api/v1/stream/ticket",
+        ] {
+            assert!(openapi.paths.paths.contains_key(path), "missing {}", path);
+        }
+    }
+
+    #[test]
+    fn test_openapi_declares_bearer_security() {
+        let openapi = ApiDoc::openapi();
+        let components = openapi.components.unwrap();
+        assert!(components.security_schemes.contains_key("bearer_token"));
+        assert!(components.schemas.contains_key("Deadline"));
+    }
+}
@@ -2,18 +2,36 @@ use std::sync::Arc;
 
 use actix_web::web;
 
-use crate::domain::{
-    data_providers::data_provider_abstract::DataProviderAbstract,
-    repositories::data_repository_abstract::{
-        CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
-        TokenRepositoryAbstract, UserRepositoryAbstract,
+use crate::{
+    domain::{
+        data_providers::data_provider_abstract::DataProviderAbstract,
+        repositories::data_repository_abstract::{
+            CourseRepositoryAbstract, DeadlineRepositoryAbstract, GradeRepositoryAbstract,
+            TokenRepositoryAbstract, UserRepositoryAbstract,
+        },
+        services::{
+            course_service::CourseService, deadline_service::DeadlineService,
+            grade_service::GradeService, live_update_service::LiveUpdateService,
+            token_service::TokenService, user_service::UserService,
+        },
     },
-    services::{
-        course_service::CourseService, deadline_service::DeadlineService,
-        grade_service::GradeService, token_service::TokenService, user_service::UserService,
+    infrastructure::{
+        data_providers::tenant_router::TenantRouter, repositories::storage::CachedStorage,
     },
 };
 
+/// State the handlers receive, the tenant router over the cached storage.
+pub type SharedState = web::Data<
+    AppState<
+        TenantRouter,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+        CachedStorage,
+    >,
+>;
+
 pub struct AppState<DataProvider, TokenRepo, UserRepo, CourseRepo, GradeRepo, DeadlineRepo>
 where
     DataProvider: DataProviderAbstract,
@@ -29,6 +47,9 @@ where
     pub course_service: Arc<CourseService<DataProvider, CourseRepo>>,
     pub grade_service: Arc<GradeService<DataProvider, GradeRepo>>,
     pub deadline_service: Arc<DeadlineService<DataProvider, DeadlineRepo>>,
+    pub live_updates: Arc<LiveUpdateService>,
+    /// Provider the services share, for routing details handlers need directly.
+    pub data_provider: Arc<DataProvider>,
 }
 
 impl<DataProvider, TokenRepo, UserRepo, CourseRepo, GradeRepo, DeadlineRepo>
@@ -49,6 +70,8 @@ where
         course_service: Arc<CourseService<DataProvider, CourseRepo>>,
         grade_service: Arc<GradeService<DataProvider, GradeRepo>>,
         deadline_service: Arc<DeadlineService<DataProvider, DeadlineRepo>>,
+        live_updates: Arc<LiveUpdateService>,
+        data_provider: Arc<DataProvider>,
     ) -> web::Data<Self> {
         web::Data::new(Self {
             token_service,
@@ -56,6 +79,8 @@ where
             course_service,
             grade_service,
             deadline_service,
+            live_updates,
+            data_provider,
         })
     }
 }
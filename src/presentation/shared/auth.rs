@@ -0,0 +1,56 @@
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, http::header, FromRequest, HttpRequest};
+
+use crate::domain::entities::errors::ServiceError;
+
+/// Moodle token taken from the `Authorization: Bearer <token>` header.
+pub struct BearerToken(pub String);
+
+impl BearerToken {
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl FromRequest for BearerToken {
+    type Error = ServiceError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .map(|token| BearerToken(token.to_owned()))
+            .ok_or(ServiceError::Unauthorized);
+        ready(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn test_bearer_token_extracted() {
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer abc123"))
+            .to_http_request();
+        let token = BearerToken::extract(&req).await.unwrap();
+        assert_eq!(token.into_inner(), "abc123");
+    }
+
+    #[actix_web::test]
+    async fn test_bearer_token_missing() {
+        let req = TestRequest::default().to_http_request();
+        assert!(matches!(
+            BearerToken::extract(&req).await,
+            Err(ServiceError::Unauthorized)
+        ));
+    }
+}
@@ -0,0 +1,9 @@
+use actix_web::middleware::DefaultHeaders;
+
+/// Marks every response of a legacy scope as deprecated and points clients at its successor.
+pub fn deprecated_route(successor: &str) -> DefaultHeaders {
+    DefaultHeaders::new().add(("Deprecation", "true")).add((
+        "Link",
+        format!("<{}>; rel=\"successor-version\"", successor),
+    ))
+}
@@ -1,10 +1,13 @@
 use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use serde::Serialize;
+use utoipa::ToSchema;
 
-use crate::domain::entities::errors::ServiceError;
+use crate::{
+    domain::entities::errors::ServiceError, infrastructure::data_providers::errors::MoodleError,
+};
 
-#[derive(Serialize)]
-struct ApiError {
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ApiError {
     message: String,
     status: u16,
 }
@@ -24,12 +27,27 @@ impl ResponseError for ServiceError {
     fn status_code(&self) -> StatusCode {
         match self {
             ServiceError::UserAlreadyExists(_) => StatusCode::ACCEPTED,
-            ServiceError::InvalidToken(_) => StatusCode::BAD_REQUEST,
+            ServiceError::Unauthorized => StatusCode::UNAUTHORIZED,
             ServiceError::DataNotFound(_) => StatusCode::NOT_FOUND,
             ServiceError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
             ServiceError::ReqwestError(_) => StatusCode::NOT_FOUND,
             ServiceError::DeadlineSortingError(_) => StatusCode::NOT_FOUND,
             ServiceError::SystemTime(_) => StatusCode::NOT_FOUND,
+            ServiceError::Export(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ServiceError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ServiceError::Moodle(error) => match error {
+                MoodleError::InvalidToken => StatusCode::BAD_REQUEST,
+                MoodleError::AccessDenied(_) => StatusCode::FORBIDDEN,
+                MoodleError::Maintenance => StatusCode::SERVICE_UNAVAILABLE,
+                MoodleError::NotFound(_) => StatusCode::NOT_FOUND,
+                MoodleError::ServiceNotAvailable(_)
+                | MoodleError::InvalidParameter(_)
+                | MoodleError::Other { .. } => StatusCode::BAD_GATEWAY,
+            },
+            ServiceError::UnexpectedResponse(_) => StatusCode::BAD_GATEWAY,
+            ServiceError::AccountMismatch => StatusCode::CONFLICT,
+            ServiceError::UnknownTenant(_) => StatusCode::BAD_REQUEST,
+            ServiceError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
         }
     }
 }
@@ -1,2 +1,4 @@
 pub mod app_state;
+pub mod auth;
+pub mod deprecation;
 pub mod errors;
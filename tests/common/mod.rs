@@ -0,0 +1,119 @@
+//! End-to-end harness: the server on in-memory storage, talking to [`MoodleStub`] and
+//! recording pushes with [`RecordingNotificationProvider`] instead of sending them.
+
+pub mod moodle_stub;
+
+use std::sync::Arc;
+
+use actix_web::{http::StatusCode, test, App};
+use aitu_web_app::{
+    config::{CacheBackend, Config, NotificationBackend, StorageBackend},
+    domain::entities::tenant::Tenant,
+    infrastructure::{
+        app_setup::{build_dependencies, routes, AppDependencies},
+        data_providers::{request_limiter::RequestLimits, tenant_router::TenantRouter},
+        notification_provider::recording_notification_provider::RecordingNotificationProvider,
+        repositories::storage::CachedStorage,
+    },
+    presentation::handlers::admin_handler::{admin_routes, NotificationInbox},
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use self::moodle_stub::{MoodleStub, STUB_TOKEN};
+
+type Dependencies = AppDependencies<
+    RecordingNotificationProvider,
+    TenantRouter,
+    CachedStorage,
+    CachedStorage,
+    CachedStorage,
+    CachedStorage,
+    CachedStorage,
+>;
+
+pub struct Harness {
+    pub stub: MoodleStub,
+    pub pushes: Arc<RecordingNotificationProvider>,
+    pub deps: Dependencies,
+}
+
+const ADMIN_TOKEN: &str = "admin";
+
+fn config(stub: &MoodleStub) -> Config {
+    Config {
+        port: "0".to_owned(),
+        storage: StorageBackend::Memory,
+        mongo_uri: None,
+        database_url: None,
+        cache: CacheBackend::None,
+        redis_url: None,
+        cache_ttl_secs: 60,
+        tenants: vec![Tenant::single(
+            stub.base_url.clone(),
+            "&moodlewsrestformat=json".to_owned(),
+        )],
+        batch_size: 10,
+        poll_interval_secs: 900,
+        moodle_limits: RequestLimits::default(),
+        worker_id: "e2e".to_owned(),
+        notifications: NotificationBackend::Recording,
+        admin_token: Some(ADMIN_TOKEN.to_owned()),
+    }
+}
+
+impl Harness {
+    /// Must run inside an actix system, e.g. `#[actix_web::test]`, which hosts the stub.
+    pub async fn start() -> Self {
+        let stub = MoodleStub::start();
+        let pushes = Arc::new(RecordingNotificationProvider::default());
+        let deps = build_dependencies(&config(&stub), Arc::clone(&pushes))
+            .await
+            .expect("dependencies");
+        Self { stub, pushes, deps }
+    }
+
+    /// Registers the stub's user through the API.
+    pub async fn register(&self, device_token: &str) -> StatusCode {
+        let app = test::init_service(
+            App::new()
+                .app_data(self.deps.app_state.clone())
+                .configure(routes),
+        )
+        .await;
+        let request = test::TestRequest::post()
+            .uri("/api/v1/users")
+            .set_json(json!({"token": STUB_TOKEN, "device_token": device_token}))
+            .to_request();
+        test::call_service(&app, request).await.status()
+    }
+
+    /// Polls every registered user as if their next poll were due, returns how many.
+    pub async fn poll(&self) -> usize {
+        let far_future = Utc::now().timestamp() + 24 * 60 * 60;
+        self.deps
+            .notification_service
+            .poll_due_at(far_future, 100)
+            .await
+            .expect("poll")
+    }
+
+    /// Pushes as the staging admin route lists them.
+    pub async fn admin_notifications(&self) -> Value {
+        let inbox = NotificationInbox {
+            recorder: Arc::clone(&self.pushes),
+            admin_token: Some(ADMIN_TOKEN.to_owned()),
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(actix_web::web::Data::new(inbox))
+                .configure(admin_routes),
+        )
+        .await;
+        let request = test::TestRequest::get()
+            .uri("/api/admin/notifications")
+            .insert_header(("Authorization", format!("Bearer {}", ADMIN_TOKEN)))
+            .to_request();
+        test::call_and_read_body_json(&app, request).await
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+pub const STUB_TOKEN: &str = "stub-token";
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// A change to the stub's data, applied right away or at a scripted step.
+#[derive(Debug, Clone)]
+pub enum Mutation {
+    SetGrade {
+        course_id: i64,
+        item_id: i64,
+        percentage: &'static str,
+    },
+    /// `days` from the start of today, like the fixture events.
+    AddDeadline {
+        course_id: i64,
+        id: i64,
+        name: &'static str,
+        days: i64,
+    },
+    MoveDeadline {
+        course_id: i64,
+        id: i64,
+        days: i64,
+    },
+    RemoveDeadline {
+        course_id: i64,
+        id: i64,
+    },
+    /// Drops out of the action events but still exists, like a submitted assignment.
+    SubmitDeadline {
+        course_id: i64,
+        id: i64,
+    },
+    RenameCourse {
+        course_id: i64,
+        fullname: &'static str,
+    },
+    RevokeToken,
+}
+
+struct StubState {
+    revoked: bool,
+    site_info: Value,
+    courses: Vec<Value>,
+    grade_items: HashMap<i64, Value>,
+    grades_overview: Value,
+    events: HashMap<i64, Vec<Value>>,
+    /// Events no longer listed as actions, for `core_calendar_get_calendar_event_by_id`.
+    submitted: Vec<Value>,
+    /// When each course last changed, for `core_course_get_updates_since`.
+    changed_at: HashMap<i64, i64>,
+    step: usize,
+    script: BTreeMap<usize, Vec<Mutation>>,
+}
+
+/// Moodle web service stub serving the JSON in `tests/fixtures/moodle`.
+///
+/// It answers the functions the server calls, for [`STUB_TOKEN`] only. Event times in the
+/// fixtures are seconds from the start of the current UTC day, so deadlines stay upcoming.
+#[derive(Clone)]
+pub struct MoodleStub {
+    state: Arc<Mutex<StubState>>,
+    pub base_url: String,
+}
+
+fn fixture(name: &str) -> Value {
+    let path = format!(
+        "{}/tests/fixtures/moodle/{}.json",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let content = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    serde_json::from_str(&content).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+/// Fixture object keyed by course id.
+fn by_course(value: Value) -> HashMap<i64, Value> {
+    let Value::Object(map) = value else {
+        panic!("expected an object keyed by course id");
+    };
+    map.into_iter()
+        .map(|(key, value)| (key.parse().expect("course id key"), value))
+        .collect()
+}
+
+fn today() -> i64 {
+    let now = Utc::now().timestamp();
+    now - now.rem_euclid(DAY)
+}
+
+impl MoodleStub {
+    /// Loads the fixtures and serves them on a free local port.
+    pub fn start() -> Self {
+        let events = by_course(fixture("action_events"))
+            .into_iter()
+            .map(|(course_id, events)| {
+                let mut events: Vec<Value> = serde_json::from_value(events).unwrap();
+                for event in &mut events {
+                    let offset = event["timeusermidnight"].as_i64().unwrap();
+                    event["timeusermidnight"] = json!(today() + offset);
+                }
+                (course_id, events)
+            })
+            .collect();
+        let state = Arc::new(Mutex::new(StubState {
+            revoked: false,
+            site_info: fixture("site_info"),
+            courses: serde_json::from_value(fixture("courses")).unwrap(),
+            grade_items: by_course(fixture("grade_items")),
+            grades_overview: fixture("grades_overview"),
+            events,
+            submitted: Vec::new(),
+            changed_at: HashMap::new(),
+            step: 0,
+            script: BTreeMap::new(),
+        }));
+
+        let served = Arc::clone(&state);
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::from(Arc::clone(&served)))
+                .route("/webservice/rest/server.php", web::get().to(handle))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        Self {
+            state,
+            base_url: format!("http://{}/webservice/rest/server.php?", address),
+        }
+    }
+
+    /// Schedules `mutation` for when [`Self::advance`] reaches `step`.
+    pub fn at_step(&self, step: usize, mutation: Mutation) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state.script.entry(step).or_default().push(mutation);
+        self
+    }
+
+    /// Moves to the next step and applies what was scheduled for it, returns the step.
+    pub fn advance(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.step += 1;
+        let step = state.step;
+        for mutation in state.script.remove(&step).unwrap_or_default() {
+            state.apply(mutation);
+        }
+        step
+    }
+
+    pub fn apply(&self, mutation: Mutation) {
+        self.state.lock().unwrap().apply(mutation);
+    }
+}
+
+impl StubState {
+    fn apply(&mut self, mutation: Mutation) {
+        let now = Utc::now().timestamp();
+        match mutation {
+            Mutation::SetGrade {
+                course_id,
+                item_id,
+                percentage,
+            } => {
+                let items = self.grade_items.get_mut(&course_id).expect("course grades")
+                    ["usergrades"][0]["gradeitems"]
+                    .as_array_mut()
+                    .unwrap();
+                let item = items
+                    .iter_mut()
+                    .find(|item| item["id"] == item_id)
+                    .expect("grade item");
+                item["percentageformatted"] = json!(percentage);
+                self.changed_at.insert(course_id, now);
+            }
+            Mutation::AddDeadline {
+                course_id,
+                id,
+                name,
+                days,
+            } => {
+                self.events.entry(course_id).or_default().push(json!({
+                    "id": id,
+                    "name": name,
+                    "timeusermidnight": today() + days * DAY,
+                    "formattedtime": format!(
+                        r#"<a href="https://stub.edu/calendar/view.php?view=day">In {} days</a>, 23:59"#,
+                        days
+                    ),
+                }));
+                self.changed_at.insert(course_id, now);
+            }
+            Mutation::MoveDeadline {
+                course_id,
+                id,
+                days,
+            } => {
+                let event = self
+                    .events
+                    .get_mut(&course_id)
+                    .and_then(|events| events.iter_mut().find(|event| event["id"] == id))
+                    .expect("deadline");
+                event["timeusermidnight"] = json!(today() + days * DAY);
+                self.changed_at.insert(course_id, now);
+            }
+            Mutation::RemoveDeadline { course_id, id } => {
+                if let Some(events) = self.events.get_mut(&course_id) {
+                    events.retain(|event| event["id"] != id);
+                }
+                self.changed_at.insert(course_id, now);
+            }
+            Mutation::SubmitDeadline { course_id, id } => {
+                let events = self.events.get_mut(&course_id).expect("course events");
+                let index = events
+                    .iter()
+                    .position(|event| event["id"] == id)
+                    .expect("deadline");
+                self.submitted.push(events.remove(index));
+                self.changed_at.insert(course_id, now);
+            }
+            Mutation::RenameCourse {
+                course_id,
+                fullname,
+            } => {
+                let course = self
+                    .courses
+                    .iter_mut()
+                    .find(|course| course["id"] == course_id)
+                    .expect("course");
+                course["fullname"] = json!(fullname);
+            }
+            Mutation::RevokeToken => self.revoked = true,
+        }
+    }
+
+    fn respond(&self, function: &str, query: &HashMap<String, String>) -> Value {
+        let course_id = || -> i64 { query["courseid"].parse().unwrap() };
+        match function {
+            "core_webservice_get_site_info" => self.site_info.clone(),
+            "core_enrol_get_users_courses" => json!(self.courses),
+            "gradereport_user_get_grade_items" => self
+                .grade_items
+                .get(&course_id())
+                .cloned()
+                .unwrap_or_else(|| json!({"usergrades": []})),
+            "gradereport_overview_get_course_grades" => self.grades_overview.clone(),
+            "core_calendar_get_action_events_by_course" => {
+                json!({"events": self.events.get(&course_id()).cloned().unwrap_or_default()})
+            }
+            "core_calendar_get_action_events_by_courses" => {
+                let grouped: Vec<Value> = query
+                    .iter()
+                    .filter(|(key, _)| key.starts_with("courseids["))
+                    .map(|(_, value)| value.parse::<i64>().unwrap())
+                    .map(|course_id| {
+                        json!({
+                            "courseid": course_id,
+                            "events": self.events.get(&course_id).cloned().unwrap_or_default(),
+                        })
+                    })
+                    .collect();
+                json!({"groupedbycourse": grouped})
+            }
+            "core_course_get_updates_since" => {
+                let since: i64 = query["since"].parse().unwrap();
+                let instances = match self.changed_at.get(&course_id()) {
+                    Some(&changed_at) if changed_at >= since => json!([{
+                        "contextlevel": "module",
+                        "id": course_id() * 100,
+                        "updates": [{"name": "configuration", "timeupdated": changed_at, "itemids": []}],
+                    }]),
+                    _ => json!([]),
+                };
+                json!({"instances": instances, "warnings": []})
+            }
+            "core_calendar_get_calendar_event_by_id" => {
+                let id: i64 = query["eventid"].parse().unwrap();
+                let event = self
+                    .events
+                    .values()
+                    .flatten()
+                    .chain(&self.submitted)
+                    .find(|event| event["id"] == id);
+                match event {
+                    Some(event) => json!({"event": event}),
+                    None => json!({
+                        "exception": "dml_missing_record_exception",
+                        "errorcode": "invalidrecord",
+                        "message": "Can't find data record in database table event.",
+                    }),
+                }
+            }
+            other => json!({
+                "exception": "webservice_access_exception",
+                "errorcode": "accessexception",
+                "message": format!("Stub does not serve {}", other),
+            }),
+        }
+    }
+}
+
+async fn handle(
+    state: web::Data<Mutex<StubState>>,
+    query: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let state = state.lock().unwrap();
+    if state.revoked || query.get("wstoken").map(String::as_str) != Some(STUB_TOKEN) {
+        return HttpResponse::Ok().json(json!({
+            "exception": "moodle_exception",
+            "errorcode": "invalidtoken",
+            "message": "Invalid token - token not found",
+        }));
+    }
+    let function = query.get("wsfunction").cloned().unwrap_or_default();
+    HttpResponse::Ok().json(state.respond(&function, &query))
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, App};
+use aitu_web_app::infrastructure::app_setup::routes;
+use serde_json::Value;
+
+use common::{
+    moodle_stub::{Mutation, STUB_TOKEN},
+    Harness,
+};
+
+#[actix_web::test]
+async fn test_registration_and_unchanged_poll_are_silent() {
+    let harness = Harness::start().await;
+    assert_eq!(harness.register("device-1").await, StatusCode::CREATED);
+    assert_eq!(harness.register("device-1").await, StatusCode::ACCEPTED);
+
+    assert_eq!(harness.poll().await, 1);
+    harness.pushes.assert_none_sent();
+}
+
+#[actix_web::test]
+async fn test_scripted_changes_are_pushed() {
+    let harness = Harness::start().await;
+    harness.register("device-1").await;
+    harness.poll().await;
+
+    harness
+        .stub
+        .at_step(
+            1,
+            Mutation::SetGrade {
+                course_id: 1,
+                item_id: 10,
+                percentage: "95,00 %",
+            },
+        )
+        .at_step(
+            2,
+            Mutation::AddDeadline {
+                course_id: 2,
+                id: 200,
+                name: "Project report is due",
+                days: 5,
+            },
+        )
+        .at_step(
+            2,
+            Mutation::RenameCourse {
+                course_id: 2,
+                fullname: "Database Systems",
+            },
+        )
+        .at_step(
+            3,
+            Mutation::MoveDeadline {
+                course_id: 1,
+                id: 100,
+                days: 6,
+            },
+        )
+        .at_step(
+            4,
+            Mutation::RemoveDeadline {
+                course_id: 2,
+                id: 200,
+            },
+        )
+        .at_step(
+            4,
+            Mutation::SubmitDeadline {
+                course_id: 1,
+                id: 100,
+            },
+        );
+
+    harness.stub.advance();
+    harness.poll().await;
+    let pushes = harness.pushes.take();
+    assert_eq!(pushes.len(), 1);
+    assert_eq!(pushes[0].device_token.as_deref(), Some("device-1"));
+    assert_eq!(pushes[0].title, "Algorithms");
+    assert_eq!(pushes[0].body, "New grade | Quiz 1\n80,00 % -> 95,00 %");
+
+    harness.stub.advance();
+    harness.poll().await;
+    harness
+        .pushes
+        .assert_titles(&["Course renamed", "New deadline"]);
+    harness.pushes.clear();
+
+    harness.stub.advance();
+    harness.poll().await;
+    harness.pushes.assert_count(1);
+    let moved = harness.pushes.assert_sent("Deadline extended");
+    assert!(moved.data.unwrap()["new_time"].is_string());
+    harness.pushes.clear();
+
+    harness.stub.advance();
+    harness.poll().await;
+    harness.pushes.assert_count(1);
+    let cancelled = harness.pushes.assert_sent("Deadline cancelled");
+    assert!(cancelled.body.contains("Project report is due"));
+}
+
+#[actix_web::test]
+async fn test_revoked_token_suspends_user() {
+    let harness = Harness::start().await;
+    harness.register("device-1").await;
+    harness.poll().await;
+
+    harness.stub.apply(Mutation::RevokeToken);
+    harness.poll().await;
+    harness.pushes.assert_titles(&["Please log in again"]);
+
+    assert_eq!(harness.poll().await, 0);
+}
+
+#[actix_web::test]
+async fn test_admin_route_lists_recorded_pushes() {
+    let harness = Harness::start().await;
+    harness.register("device-1").await;
+    harness.poll().await;
+
+    harness.stub.apply(Mutation::SetGrade {
+        course_id: 2,
+        item_id: 20,
+        percentage: "90,00 %",
+    });
+    harness.poll().await;
+
+    let pushes = harness.admin_notifications().await;
+    assert_eq!(pushes[0]["device_token"], "device-1");
+    assert_eq!(pushes[0]["title"], "Databases");
+    assert_eq!(pushes[0]["body"], "New grade | Lab 1\n100,00 % -> 90,00 %");
+}
+
+#[actix_web::test]
+async fn test_stream_needs_bearer_or_single_use_ticket() {
+    let harness = Harness::start().await;
+    harness.register("device-1").await;
+    let app = test::init_service(
+        App::new()
+            .app_data(harness.deps.app_state.clone())
+            .configure(routes),
+    )
+    .await;
+
+    let request = test::TestRequest::get()
+        .uri(&format!("/api/stream?token={}", STUB_TOKEN))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::post()
+        .uri("/api/v1/stream/ticket")
+        .insert_header(("Authorization", format!("Bearer {}", STUB_TOKEN)))
+        .to_request();
+    let ticket: Value = test::call_and_read_body_json(&app, request).await;
+    let uri = format!("/api/stream?ticket={}", ticket["ticket"].as_str().unwrap());
+
+    let response = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
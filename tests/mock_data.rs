@@ -1,5 +0,0 @@
-use aitu_web_app::domain::entities::{
-    course::Course,
-    grade::{Grade, GradeItems, UserGrades},
-    user::User,
-};
@@ -1,2 +0,0 @@
-pub mod data_service_tests;
-pub mod mock_data;